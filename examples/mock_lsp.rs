@@ -0,0 +1,195 @@
+//! A tiny language server for trying out the editor's language server support without
+//! installing a real one. Build it with `cargo build --example mock_lsp` and point a
+//! language at `target/debug/examples/mock_lsp` in `cryptum-text-lsp.json`. The client's
+//! tests talk to it too.
+//!
+//! It reports a warning for every line containing `TODO`, completes a few fixed words,
+//! shows the hovered word on hover, treats the first occurrence of a word as its
+//! definition and every occurrence as a reference, and renames words within the file.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{Value, json};
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    Ok(serde_json::from_slice(&body).ok())
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// The word touching a (line, character) position. Columns are counted in characters.
+fn word_at(text: &str, position: &Value) -> Option<String> {
+    let line: Vec<char> = text
+        .lines()
+        .nth(position["line"].as_u64()? as usize)?
+        .chars()
+        .collect();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let column = (position["character"].as_u64()? as usize).min(line.len());
+    let mut start = column;
+    while start > 0 && is_word(line[start - 1]) {
+        start -= 1;
+    }
+    let mut end = column;
+    while end < line.len() && is_word(line[end]) {
+        end += 1;
+    }
+    (start < end).then(|| line[start..end].iter().collect())
+}
+
+/// Every whole word occurrence of `word` as a protocol range.
+fn occurrences(text: &str, word: &str) -> Vec<Value> {
+    let mut ranges = Vec::new();
+    for (line, content) in text.lines().enumerate() {
+        let chars: Vec<char> = content.chars().collect();
+        let word: Vec<char> = word.chars().collect();
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        for start in 0..chars.len() {
+            let end = start + word.len();
+            if end <= chars.len()
+                && chars[start..end] == word[..]
+                && (start == 0 || !is_word(&chars[start - 1]))
+                && chars.get(end).is_none_or(|c| !is_word(c))
+            {
+                ranges.push(json!({
+                    "start": { "line": line, "character": start },
+                    "end": { "line": line, "character": end },
+                }));
+            }
+        }
+    }
+    ranges
+}
+
+fn diagnostics(uri: &str, text: &str) -> Value {
+    let diagnostics: Vec<Value> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(line, content)| {
+            let start = content.find("TODO")?;
+            let start = content[..start].chars().count();
+            Some(json!({
+                "range": {
+                    "start": { "line": line, "character": start },
+                    "end": { "line": line, "character": start + 4 },
+                },
+                "severity": 2,
+                "message": "Unfinished TODO",
+            }))
+        })
+        .collect();
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn main() -> io::Result<()> {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut documents: HashMap<String, String> = HashMap::new();
+
+    while let Some(message) = read_message(&mut input)? {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let text = documents.get(&uri).cloned().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                },
+                "serverInfo": { "name": "mock_lsp" },
+            }),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = params["textDocument"]["text"]
+                    .as_str()
+                    .or_else(|| params["contentChanges"][0]["text"].as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                write_message(&mut output, &diagnostics(&uri, &text))?;
+                documents.insert(uri, text);
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                continue;
+            }
+            "textDocument/completion" => json!(
+                ["mock_alpha", "mock_beta", "mock_gamma"]
+                    .map(|label| json!({ "label": label, "detail": "mock completion" }))
+            ),
+            "textDocument/hover" => match word_at(&text, &params["position"]) {
+                Some(word) => json!({ "contents": format!("Mock hover for `{word}`") }),
+                None => Value::Null,
+            },
+            "textDocument/definition" => match word_at(&text, &params["position"]) {
+                Some(word) => occurrences(&text, &word)
+                    .first()
+                    .map(|range| json!({ "uri": uri, "range": range }))
+                    .unwrap_or(Value::Null),
+                None => Value::Null,
+            },
+            "textDocument/references" => match word_at(&text, &params["position"]) {
+                Some(word) => json!(
+                    occurrences(&text, &word)
+                        .into_iter()
+                        .map(|range| json!({ "uri": uri, "range": range }))
+                        .collect::<Vec<_>>()
+                ),
+                None => json!([]),
+            },
+            "textDocument/rename" => match word_at(&text, &params["position"]) {
+                Some(word) => {
+                    let edits: Vec<Value> = occurrences(&text, &word)
+                        .into_iter()
+                        .map(|range| json!({ "range": range, "newText": params["newName"] }))
+                        .collect();
+                    json!({ "changes": { uri: edits } })
+                }
+                None => Value::Null,
+            },
+            "exit" => break,
+            _ => Value::Null,
+        };
+        // Only requests get a response
+        if let Some(id) = message.get("id") {
+            write_message(
+                &mut output,
+                &json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            )?;
+        }
+    }
+    Ok(())
+}
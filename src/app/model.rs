@@ -1,36 +1,110 @@
-use std::path::PathBuf;
+use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc};
 
-use gtk4::gio::FileInfo;
-use libadwaita::{OverlaySplitView, ToastOverlay, WindowTitle};
+use gtk4::gio::{FileInfo, SimpleActionGroup};
+use libadwaita::{OverlaySplitView, ToastOverlay, ViewStack, WindowTitle};
 use relm4::{Controller, prelude::*};
 use relm4_components::{open_dialog::OpenDialog, save_dialog::SaveDialog};
 use serde::{Deserialize, Serialize};
 use sourceview5::LanguageManager;
 
+use crate::{
+    fs::{editorconfig::EditorConfig, search::FileMatches, session::Session},
+    lsp::{
+        client::{CompletionEntry, ServerEvent},
+        manager::LspManager,
+    },
+    util::{
+        brackets::BracketsConfig,
+        completion::EditorCompletion,
+        folding::Folding,
+        format::{FormatResult, FormattersConfig},
+        goto::GoToLine,
+        indentation::{Indent, Indentation},
+        keybindings::Keybindings,
+        keymap::{Keymap, KeymapState},
+        lines::SortOptions,
+        markdown::MarkdownPreview,
+        multi_cursor::MultiCursor,
+        outline::Outline,
+        picker::Picker,
+        problems::Problems,
+        project_search::ProjectSearch,
+        search::FindBar,
+        snippets::SnippetLibrary,
+        split::SplitViews,
+        tasks::TaskRunner,
+        terminal::TerminalPanel,
+        transform::Transform,
+        whitespace::{
+            LanguageWhitespace, WhitespaceOption, WhitespaceOptions, default_language_whitespace,
+        },
+    },
+};
+
 // Structs
 #[derive(Debug)]
 pub struct State {
     // Containers
     pub root: libadwaita::ApplicationWindow,
     pub nav_view: OverlaySplitView,
+    pub sidebar_stack: ViewStack,
     // Widgets
     pub file_view: gtk::ListView,
     pub editor: sourceview5::View,
+    pub completion: EditorCompletion,
+    pub multi_cursor: MultiCursor,
+    pub folding: Rc<Folding>,
+    pub keymap: KeymapState,
+    pub keybindings: Keybindings,
+    pub snippets: Rc<RefCell<SnippetLibrary>>,
     pub buffer: sourceview5::Buffer,
     pub language_manager: LanguageManager,
     pub open_dialog: Controller<OpenDialog>,
     pub folder_dialog: Controller<OpenDialog>,
+    pub snippet_import_dialog: Controller<OpenDialog>,
     pub save_as_dialog: Controller<SaveDialog>,
     pub title: WindowTitle,
     pub file_type_label: gtk::Label,
     pub cursor_position_label: gtk::Label,
+    pub editorconfig_label: gtk::Label,
+    pub indentation: Indentation,
     pub mini_map: sourceview5::Map,
+    pub splits: SplitViews,
+    pub markdown_preview: MarkdownPreview,
     pub toast_overlay: ToastOverlay,
+    pub find_bar: FindBar,
+    pub project_search: ProjectSearch,
+    pub picker: Picker,
+    pub go_to_line: GoToLine,
+    pub outline: Outline,
+    pub lsp: LspManager,
+    pub problems: Problems,
+    pub task_runner: TaskRunner,
+    pub terminal: TerminalPanel,
+    pub action_groups: Vec<(&'static str, SimpleActionGroup)>,
     // Misc
     pub current_file_path: PathBuf,
     pub current_folder_path: PathBuf,
+    pub file_index: Vec<PathBuf>,
+    pub recent_files: Vec<PathBuf>,
     pub buffer_style: Option<sourceview5::StyleScheme>,
     pub view_hidden: bool,
+    pub formatters: FormattersConfig,
+    pub brackets: Rc<BracketsConfig>,
+    pub format_on_save: bool,
+    pub session: Session,
+    /// Indentation from the settings, which a file's own conventions can override
+    pub indent_settings: IndentSettings,
+    pub editorconfig: EditorConfig,
+    pub whitespace: WhitespaceOptions,
+    /// Whitespace options changed per sourceview language id
+    pub whitespace_languages: HashMap<String, LanguageWhitespace>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IndentSettings {
+    pub use_spaces: bool,
+    pub tab_width: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,6 +113,16 @@ pub struct Settings {
     pub editor_monospace: bool,
     pub editor_use_spaces_for_tabs: bool,
     pub editor_tab_width: u32,
+    #[serde(default)]
+    pub editor_completion_delay: u32,
+    #[serde(default)]
+    pub editor_format_on_save: bool,
+    #[serde(default)]
+    pub editor_keymap: Keymap,
+    #[serde(default)]
+    pub editor_whitespace: WhitespaceOptions,
+    #[serde(default = "default_language_whitespace")]
+    pub editor_whitespace_languages: HashMap<String, LanguageWhitespace>,
     pub view_sidebar: bool,
     pub view_mini_map: bool,
     pub view_hidden_files: bool,
@@ -51,6 +135,11 @@ impl Default for Settings {
             editor_monospace: true,
             editor_use_spaces_for_tabs: true,
             editor_tab_width: 4,
+            editor_completion_delay: 0,
+            editor_format_on_save: false,
+            editor_keymap: Keymap::Default,
+            editor_whitespace: WhitespaceOptions::default(),
+            editor_whitespace_languages: default_language_whitespace(),
             view_sidebar: true,
             view_mini_map: true,
             view_hidden_files: false,
@@ -72,27 +161,137 @@ pub enum Msg {
     SaveAsRequest,
     SaveAsResponse(PathBuf),
     SaveFile,
+    /// Saves the file, then quits once it's written
+    SaveAndQuit,
+    Quit,
+    QuickOpen,
+    FolderIndexed(PathBuf, Vec<PathBuf>),
+    ImportSnippetsRequest,
+    ImportSnippetsResponse(PathBuf),
+    ShowTaskPicker,
+    RerunTask,
+    CancelTask,
+    /// Exports the Markdown document to HTML, replacing an existing export when true
+    ExportHtml(bool),
     // Edit
     ClearEditor,
+    ShowFind,
+    ShowReplace,
+    FindNext,
+    FindPrevious,
+    ReplaceMatch,
+    ReplaceAll,
+    ShowProjectSearch,
+    ShowGoToLine,
+    GoToLine,
+    ShowSymbolPicker,
+    GoToDefinition,
+    FindReferences,
+    ShowRename,
+    RenameSymbol(String),
+    NextProblem,
+    PreviousProblem,
+    /// Expands the snippet at this index in the snippet library, replacing the text between
+    /// the two offsets
+    InsertSnippet(usize, i32, i32),
+    FormatDocument,
+    DocumentFormatted(FormatResult),
+    JumpToBracket,
+    SelectToBracket,
+    AddNextOccurrence,
+    MoveLines(bool),
+    DuplicateLines,
+    DeleteLines,
+    JoinLines,
+    SortLines(SortOptions),
+    ReverseLines,
+    ToggleLineComment,
+    ToggleBlockComment,
+    Transform(Transform),
     // View
     ToggleFileTree,
     ToggleHiddenFiles,
     ToggleMiniMap,
     ToggleBufferStyleScheme,
     ToggleFullscreen,
+    ShowCommandPalette,
+    ToggleProblems,
+    ToggleOutput,
+    ToggleTerminal,
+    NewTerminal,
+    TogglePreview,
+    SplitEditor(gtk::Orientation),
+    CloseSplit,
+    /// Closes the split showing the editor without asking about unsaved changes
+    ClosePane(sourceview5::View),
+    FocusNextSplit,
+    /// A split's editor gained focus, making its file the current one
+    ActivatePane(sourceview5::View),
+    ToggleFold(i32),
+    Fold,
+    Unfold,
+    FoldAll,
+    UnfoldAll,
     // About
     ShowKeyboardShortcuts,
     ShowPreferences,
     ShowAbout,
     // File tree
     LoadFileFromTree(FileInfo),
+    // Outline
+    RefreshOutline,
+    OutlineActivate(i32),
+    // Language servers
+    LspEvent(String, ServerEvent),
+    LspSync,
+    /// Completion request for a zero based line and column, answered through the sender
+    LspCompletion(i32, i32, relm4::Sender<Vec<CompletionEntry>>),
+    LspHover(i32, i32, relm4::Sender<Option<String>>),
+    // Problems
+    ProblemActivate(i32),
+    // Markdown preview
+    RefreshPreview,
+    SyncPreviewScroll,
+    /// A click in the preview, by character offset
+    PreviewClicked(i32),
+    // Tasks
+    TaskOutput(u32, String),
+    TaskFinished(u32),
+    /// A click in the output panel, by line and character offset
+    TaskOutputClicked(i32, i32),
+    // Picker
+    PickerFilter,
+    PickerActivate(i32),
+    // Project search
+    ProjectSearchStart,
+    ProjectSearchResult(u32, FileMatches),
+    ProjectSearchFinished(u32),
+    ProjectSearchPreview,
+    ProjectSearchActivate(i32),
+    ProjectReplaceSelected,
+    ProjectReplaceUndo,
     // Other
     LoadSettings,
     UpdateMonospace(bool),
     UpdateTabType(bool),
     UpdateTabWidth(u32),
+    UpdateCompletionDelay(u32),
+    UpdateFormatOnSave(bool),
+    UpdateKeymap(Keymap),
+    UpdateWhitespace(WhitespaceOption, bool),
+    /// Indentation for the current file only, `None` going back to the automatic one
+    SetIndentation(Option<Indent>),
+    /// A new shortcut for an action, `None` turning its shortcut off
+    SetKeybinding(String, Option<String>),
+    /// Moves a shortcut to an action after the user confirms taking it from another
+    ReassignKeybinding(String, String),
+    ResetKeybinding(String),
+    /// Sets every action's shortcuts from the keybindings, after recording a shortcut
+    ReapplyKeybindings,
     UpdateVisibility(ItemVis, bool),
     CursorPositionChanged,
+    SearchChanged,
+    SearchCountChanged,
     Ignore,
 }
 
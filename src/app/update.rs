@@ -6,16 +6,67 @@ use gtk4::{
 };
 use relm4::ComponentController;
 use relm4_components::{open_dialog::OpenDialogMsg, save_dialog::SaveDialogMsg};
-use sourceview5::prelude::ViewExt;
 
 use crate::{
     app::model::{Msg, State},
     fs::{
-        file::{load_file, save_file},
-        folder::load_folder,
+        editorconfig::apply_editorconfig,
+        file::{open_file, save_file, save_file_as},
+        folder::{index_folder, load_folder},
         settings::{load_settings, save_settings},
     },
-    util::widget::{toggle_buffer_style, update_vis},
+    lsp::{
+        handlers::{
+            find_references, go_to_definition, handle_server_event, rename_symbol,
+            request_completion, request_hover, show_rename_dialog,
+        },
+        manager::{close_document, flush_changes},
+    },
+    util::{
+        brackets::{jump_to_bracket, select_to_bracket},
+        completion::set_popup_delay,
+        folding::{
+            fold_all, fold_at_cursor, remember_folds, toggle_fold, unfold_all, unfold_at_cursor,
+        },
+        format::{AfterFormat, apply_formatting, format_document},
+        goto::{go_to_line, show_go_to_line},
+        indentation::{detect_document_indentation, set_indentation},
+        keybindings::{reapply_keybindings, reassign_keybinding, reset_keybinding, set_keybinding},
+        keymap::set_keymap,
+        lines::{
+            delete_lines, duplicate_lines, join_lines, move_lines, reverse_lines, sort_lines,
+            toggle_block_comment, toggle_line_comment,
+        },
+        markdown::{
+            export_html, open_preview_link, refresh_preview, sync_preview_scroll, toggle_preview,
+        },
+        multi_cursor::add_next_occurrence,
+        outline::{refresh_outline, symbol_picker_items},
+        palette::command_palette_items,
+        picker::{activate_picker, filter_picker, quick_open_items, show_picker},
+        problems::{go_to_problem, open_problem},
+        project_search::{
+            add_project_search_result, finish_project_search, open_project_search_result,
+            replace_in_project, start_project_search, undo_project_replace,
+            update_project_search_preview,
+        },
+        search::{
+            find_match, replace_all, replace_match, show_find_bar, update_match_count,
+            update_search,
+        },
+        snippets::{import_snippets, insert_snippet},
+        split::{
+            activate_pane, close_pane, close_split, focus_next_split, separate_document,
+            split_editor,
+        },
+        tasks::{
+            add_task_output, cancel_task, finish_task, open_task_link, rerun_task, show_task_picker,
+        },
+        terminal::{new_terminal, terminals_follow_folder, toggle_terminal},
+        transform::transform_selection,
+        whitespace::apply_whitespace_drawing,
+        widget::{place_cursor, toggle_buffer_style, update_vis},
+    },
 };
 
 pub(crate) fn handle_messages(
@@ -26,38 +77,92 @@ pub(crate) fn handle_messages(
     match message {
         // File
         Msg::NewFile => {
+            remember_folds(state);
+            close_document(state);
+            separate_document(state);
+            state.multi_cursor.clear();
             state.buffer.set_text("");
+            state.buffer.set_modified(false);
             state.current_file_path = PathBuf::new();
+            detect_document_indentation(state);
+            apply_editorconfig(state);
+            apply_whitespace_drawing(state);
         }
         Msg::FolderRequest => state.folder_dialog.emit(OpenDialogMsg::Open),
         Msg::FolderResponse(path) => {
             state.current_folder_path = path;
-            load_folder(state, sender);
+            terminals_follow_folder(state);
+            state.file_index.clear();
+            load_folder(state, sender.clone());
+            index_folder(state, sender);
         }
         Msg::OpenRequest => state.open_dialog.emit(OpenDialogMsg::Open),
-        Msg::OpenResponse(path) => {
-            state.current_file_path = path;
-            load_file(state);
-        }
+        Msg::OpenResponse(path) => open_file(state, path),
         Msg::SaveAsRequest => state
             .save_as_dialog
             .emit(SaveDialogMsg::SaveAs("".to_string())),
-        Msg::SaveAsResponse(path) => {
-            _ = std::fs::write(
-                &path,
-                state
-                    .buffer
-                    .text(&state.buffer.start_iter(), &state.buffer.end_iter(), false),
-            )
+        Msg::SaveAsResponse(path) => save_file_as(state, &path),
+        Msg::SaveFile => save_file(state, AfterFormat::Save, sender),
+        Msg::SaveAndQuit => save_file(state, AfterFormat::SaveAndQuit, sender),
+        Msg::Quit => state.root.close(),
+        Msg::QuickOpen => {
+            let items = quick_open_items(state);
+            show_picker(state, "Open File", "Search files by name", items);
         }
-        Msg::SaveFile => {
-            save_file(state, sender);
+        Msg::FolderIndexed(folder, files) => {
+            if folder == state.current_folder_path {
+                state.file_index = files;
+            }
         }
+        Msg::ImportSnippetsRequest => state.snippet_import_dialog.emit(OpenDialogMsg::Open),
+        Msg::ImportSnippetsResponse(path) => import_snippets(state, path),
+        Msg::ShowTaskPicker => show_task_picker(state),
+        Msg::RerunTask => rerun_task(state),
+        Msg::CancelTask => cancel_task(state),
+        Msg::ExportHtml(replace) => export_html(state, replace, sender),
         // Edit
         Msg::ClearEditor => {
             state.buffer.set_text("");
             state.buffer.undo();
         }
+        Msg::ShowFind => show_find_bar(state, false),
+        Msg::ShowReplace => show_find_bar(state, true),
+        Msg::FindNext => find_match(state, false),
+        Msg::FindPrevious => find_match(state, true),
+        Msg::ReplaceMatch => replace_match(state),
+        Msg::ReplaceAll => replace_all(state),
+        Msg::ShowProjectSearch => {
+            state.nav_view.set_show_sidebar(true);
+            state.sidebar_stack.set_visible_child_name("search");
+            state.project_search.search_entry.grab_focus();
+        }
+        Msg::ShowGoToLine => show_go_to_line(state),
+        Msg::GoToLine => go_to_line(state),
+        Msg::ShowSymbolPicker => {
+            let items = symbol_picker_items(state);
+            show_picker(state, "Go to Symbol", "Search symbols in file", items);
+        }
+        Msg::GoToDefinition => go_to_definition(state),
+        Msg::FindReferences => find_references(state),
+        Msg::ShowRename => show_rename_dialog(state, sender),
+        Msg::RenameSymbol(new_name) => rename_symbol(state, new_name),
+        Msg::NextProblem => go_to_problem(state, false),
+        Msg::PreviousProblem => go_to_problem(state, true),
+        Msg::InsertSnippet(index, start, end) => insert_snippet(state, index, start, end),
+        Msg::FormatDocument => format_document(state, AfterFormat::Nothing, sender),
+        Msg::DocumentFormatted(result) => apply_formatting(state, result, sender),
+        Msg::JumpToBracket => jump_to_bracket(state),
+        Msg::SelectToBracket => select_to_bracket(state),
+        Msg::AddNextOccurrence => add_next_occurrence(state),
+        Msg::MoveLines(up) => move_lines(state, up),
+        Msg::DuplicateLines => duplicate_lines(state),
+        Msg::DeleteLines => delete_lines(state),
+        Msg::JoinLines => join_lines(state),
+        Msg::SortLines(options) => sort_lines(state, options),
+        Msg::ReverseLines => reverse_lines(state),
+        Msg::ToggleLineComment => toggle_line_comment(state),
+        Msg::ToggleBlockComment => toggle_block_comment(state),
+        Msg::Transform(transform) => transform_selection(state, transform),
         // View
         Msg::ToggleFileTree => {
             state.nav_view.set_show_sidebar(!state.nav_view.shows_sidebar());
@@ -75,9 +180,34 @@ pub(crate) fn handle_messages(
             toggle_buffer_style(state);
         }
         Msg::ToggleFullscreen => state.root.set_fullscreened(!state.root.is_fullscreen()),
+        Msg::ShowCommandPalette => {
+            let items = command_palette_items(state);
+            show_picker(state, "Command Palette", "Search commands", items);
+        }
+        Msg::ToggleProblems => state
+            .problems
+            .revealer
+            .set_reveal_child(!state.problems.revealer.reveals_child()),
+        Msg::ToggleOutput => state
+            .task_runner
+            .revealer
+            .set_reveal_child(!state.task_runner.revealer.reveals_child()),
+        Msg::ToggleTerminal => toggle_terminal(state),
+        Msg::NewTerminal => new_terminal(state),
+        Msg::TogglePreview => toggle_preview(state),
+        Msg::SplitEditor(orientation) => split_editor(state, orientation, &sender),
+        Msg::CloseSplit => close_split(state, sender),
+        Msg::ClosePane(view) => close_pane(state, &view),
+        Msg::FocusNextSplit => focus_next_split(state),
+        Msg::ActivatePane(view) => activate_pane(state, &view),
+        Msg::ToggleFold(line) => toggle_fold(state, line),
+        Msg::Fold => fold_at_cursor(state),
+        Msg::Unfold => unfold_at_cursor(state),
+        Msg::FoldAll => fold_all(state),
+        Msg::UnfoldAll => unfold_all(state),
         // About
         Msg::ShowKeyboardShortcuts => {
-            crate::util::dialogs::create_keyboard_shortcut_dialog();
+            crate::util::dialogs::create_keyboard_shortcut_dialog(state);
         }
         Msg::ShowPreferences => {
             crate::util::dialogs::create_preferences_dialog(state, sender);
@@ -93,31 +223,93 @@ pub(crate) fn handle_messages(
                     .and_downcast_ref::<File>()
                 && let Some(path) = file.path()
             {
-                state.current_file_path = path;
-                load_file(state);
+                open_file(state, path);
+            }
+        }
+        // Outline
+        Msg::RefreshOutline => refresh_outline(state),
+        Msg::OutlineActivate(index) => {
+            if let Some(symbol) = state.outline.symbols.get(index as usize) {
+                place_cursor(state, symbol.line, 0);
             }
         }
+        // Language servers
+        Msg::LspEvent(server, event) => handle_server_event(state, server, event),
+        Msg::LspSync => flush_changes(state),
+        Msg::LspCompletion(line, column, reply) => request_completion(state, line, column, reply),
+        Msg::LspHover(line, column, reply) => request_hover(state, line, column, reply),
+        // Problems
+        Msg::ProblemActivate(index) => open_problem(state, index),
+        // Markdown preview
+        Msg::RefreshPreview => refresh_preview(state),
+        Msg::SyncPreviewScroll => sync_preview_scroll(state),
+        Msg::PreviewClicked(offset) => open_preview_link(state, offset),
+        // Tasks
+        Msg::TaskOutput(run_id, text) => add_task_output(state, run_id, &text),
+        Msg::TaskFinished(run_id) => finish_task(state, run_id),
+        Msg::TaskOutputClicked(line, offset) => open_task_link(state, line, offset),
+        // Picker
+        Msg::PickerFilter => filter_picker(state),
+        Msg::PickerActivate(row) => activate_picker(state, row),
+        // Project search
+        Msg::ProjectSearchStart => start_project_search(state, sender),
+        Msg::ProjectSearchResult(search_id, file_matches) => {
+            add_project_search_result(state, search_id, file_matches)
+        }
+        Msg::ProjectSearchFinished(search_id) => finish_project_search(state, search_id),
+        Msg::ProjectSearchPreview => update_project_search_preview(state),
+        Msg::ProjectSearchActivate(index) => open_project_search_result(state, index),
+        Msg::ProjectReplaceSelected => replace_in_project(state, sender),
+        Msg::ProjectReplaceUndo => undo_project_replace(state, sender),
         // Other
         Msg::LoadSettings => {
             println!("Loading Settings...");
             load_settings(state);
+            let keymap = state.keymap.keymap;
+            set_keymap(state, keymap, &sender);
         }
         Msg::UpdateMonospace(value) => {
             state.editor.set_monospace(value);
             save_settings(state);
         }
         Msg::UpdateTabType(use_spaces) => {
-            state.editor.set_insert_spaces_instead_of_tabs(use_spaces);
+            state.indent_settings.use_spaces = use_spaces;
+            apply_editorconfig(state);
             save_settings(state);
         }
         Msg::UpdateTabWidth(tab_width) => {
-            state.editor.set_tab_width(tab_width);
+            state.indent_settings.tab_width = tab_width;
+            apply_editorconfig(state);
+            save_settings(state);
+        }
+        Msg::UpdateCompletionDelay(delay) => {
+            set_popup_delay(state, delay);
+            save_settings(state);
+        }
+        Msg::UpdateFormatOnSave(value) => {
+            state.format_on_save = value;
+            save_settings(state);
+        }
+        Msg::UpdateWhitespace(option, value) => {
+            state.whitespace.set(option, value);
+            apply_whitespace_drawing(state);
+            save_settings(state);
+        }
+        Msg::UpdateKeymap(keymap) => {
+            set_keymap(state, keymap, &sender);
             save_settings(state);
         }
+        Msg::SetKeybinding(action, accel) => set_keybinding(state, action, accel, &sender),
+        Msg::ReassignKeybinding(action, accel) => reassign_keybinding(state, action, accel),
+        Msg::ResetKeybinding(action) => reset_keybinding(state, action),
+        Msg::ReapplyKeybindings => reapply_keybindings(state),
+        Msg::SetIndentation(indent) => set_indentation(state, indent),
         Msg::UpdateVisibility(item, vis) => {
             update_vis(item, vis, state);
         }
         Msg::CursorPositionChanged => {}
+        Msg::SearchChanged => update_search(state),
+        Msg::SearchCountChanged => update_match_count(state),
         Msg::Ignore => {}
     }
 }
@@ -2,7 +2,7 @@ use gtk4::prelude::TextBufferExt;
 
 use crate::{
     app::model::{State, WidgetStruct},
-    util::widget::update_file_type,
+    util::{search::match_count_text, widget::update_file_type},
 };
 
 pub(crate) fn handle_view(
@@ -24,7 +24,16 @@ pub(crate) fn handle_view(
         }
     }
     let cursor_iter = &state.buffer.iter_at_offset(state.buffer.cursor_position());
+    state.cursor_position_label.set_label(
+        format!(
+            "{}:{}   ",
+            cursor_iter.line() + 1,
+            cursor_iter.line_offset() + 1
+        )
+        .as_str(),
+    );
     state
-        .cursor_position_label
-        .set_label(format!("{}:{}   ", cursor_iter.line(), cursor_iter.line_offset()).as_str());
+        .find_bar
+        .match_label
+        .set_label(&match_count_text(state));
 }
@@ -1,3 +1,6 @@
+pub mod editorconfig;
 pub mod file;
 pub mod folder;
+pub mod search;
+pub mod session;
 pub mod settings;
@@ -0,0 +1,567 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use globset::GlobBuilder;
+use gtk4::prelude::*;
+use regex::Regex;
+use sourceview5::prelude::*;
+
+use crate::{
+    app::model::State,
+    util::{indentation::apply_indentation, whitespace::clean_up_whitespace},
+};
+
+static NUMBER_RANGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(-?\d+)\.\.(-?\d+)\}").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentSize {
+    /// Indent by the tab width
+    Tab,
+    Columns(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Utf8,
+    Utf8Bom,
+    Latin1,
+    Utf16Be,
+    Utf16Le,
+}
+
+/// The EditorConfig properties that apply to a file, `None` where no file sets one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditorConfig {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<IndentSize>,
+    pub tab_width: Option<u32>,
+    pub end_of_line: Option<LineEnding>,
+    pub charset: Option<Charset>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+    pub max_line_length: Option<u32>,
+    /// The `.editorconfig` files with a section matching the file, closest last
+    pub sources: Vec<PathBuf>,
+}
+
+impl EditorConfig {
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Sets a property from a `key = value` line. `unset` clears one set by a file
+    /// further up.
+    fn set(&mut self, key: &str, value: &str) {
+        let value = value.to_lowercase();
+        let unset = value == "unset";
+        let number = value.parse::<u32>().ok().filter(|number| *number > 0);
+        let flag = match value.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+        match key {
+            "indent_style" => {
+                self.indent_style = match value.as_str() {
+                    "tab" => Some(IndentStyle::Tab),
+                    "space" => Some(IndentStyle::Space),
+                    _ if unset => None,
+                    _ => self.indent_style,
+                }
+            }
+            "indent_size" => {
+                self.indent_size = match value.as_str() {
+                    "tab" => Some(IndentSize::Tab),
+                    _ if unset => None,
+                    _ => number.map(IndentSize::Columns).or(self.indent_size),
+                }
+            }
+            "tab_width" => {
+                self.tab_width = if unset {
+                    None
+                } else {
+                    number.or(self.tab_width)
+                }
+            }
+            "end_of_line" => {
+                self.end_of_line = match value.as_str() {
+                    "lf" => Some(LineEnding::Lf),
+                    "crlf" => Some(LineEnding::CrLf),
+                    "cr" => Some(LineEnding::Cr),
+                    _ if unset => None,
+                    _ => self.end_of_line,
+                }
+            }
+            "charset" => {
+                self.charset = match value.as_str() {
+                    "utf-8" => Some(Charset::Utf8),
+                    "utf-8-bom" => Some(Charset::Utf8Bom),
+                    "latin1" => Some(Charset::Latin1),
+                    "utf-16be" => Some(Charset::Utf16Be),
+                    "utf-16le" => Some(Charset::Utf16Le),
+                    _ if unset => None,
+                    _ => self.charset,
+                }
+            }
+            "trim_trailing_whitespace" => {
+                self.trim_trailing_whitespace = if unset {
+                    None
+                } else {
+                    flag.or(self.trim_trailing_whitespace)
+                }
+            }
+            "insert_final_newline" => {
+                self.insert_final_newline = if unset {
+                    None
+                } else {
+                    flag.or(self.insert_final_newline)
+                }
+            }
+            "max_line_length" => {
+                self.max_line_length = match value.as_str() {
+                    "off" => None,
+                    _ if unset => None,
+                    _ => number.or(self.max_line_length),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The tab width, which defaults to the indent size when only that is set.
+    pub fn tab_width(&self) -> Option<u32> {
+        self.tab_width.or(match self.indent_size {
+            Some(IndentSize::Columns(columns)) => Some(columns),
+            _ => None,
+        })
+    }
+
+    /// A line for each property in effect, for the status bar tooltip.
+    fn describe(&self) -> String {
+        let mut lines: Vec<String> = self
+            .sources
+            .iter()
+            .map(|source| source.display().to_string())
+            .collect();
+        if let Some(style) = self.indent_style {
+            lines.push(format!("indent_style = {style:?}").to_lowercase());
+        }
+        match self.indent_size {
+            Some(IndentSize::Tab) => lines.push("indent_size = tab".to_string()),
+            Some(IndentSize::Columns(columns)) => lines.push(format!("indent_size = {columns}")),
+            None => {}
+        }
+        if let Some(tab_width) = self.tab_width {
+            lines.push(format!("tab_width = {tab_width}"));
+        }
+        if let Some(end_of_line) = self.end_of_line {
+            lines.push(format!("end_of_line = {end_of_line:?}").to_lowercase());
+        }
+        if let Some(charset) = self.charset {
+            let charset = match charset {
+                Charset::Utf8 => "utf-8",
+                Charset::Utf8Bom => "utf-8-bom",
+                Charset::Latin1 => "latin1",
+                Charset::Utf16Be => "utf-16be",
+                Charset::Utf16Le => "utf-16le",
+            };
+            lines.push(format!("charset = {charset}"));
+        }
+        if let Some(trim) = self.trim_trailing_whitespace {
+            lines.push(format!("trim_trailing_whitespace = {trim}"));
+        }
+        if let Some(newline) = self.insert_final_newline {
+            lines.push(format!("insert_final_newline = {newline}"));
+        }
+        if let Some(length) = self.max_line_length {
+            lines.push(format!("max_line_length = {length}"));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Turns `{1..3}` into `{1,2,3}`, which the glob matcher understands.
+fn expand_number_ranges(glob: &str) -> String {
+    NUMBER_RANGE
+        .replace_all(glob, |captures: &regex::Captures| {
+            let start: i64 = captures[1].parse().unwrap_or_default();
+            let end: i64 = captures[2].parse().unwrap_or_default();
+            let numbers: Vec<String> = (start.min(end)..=start.max(end))
+                .take(10_000)
+                .map(|number| number.to_string())
+                .collect();
+            format!("{{{}}}", numbers.join(","))
+        })
+        .to_string()
+}
+
+/// Rewrites a `**` that's part of a path component, as in `src/**.rs`, so it matches across
+/// folders as EditorConfig's does, rather than like `*`.
+fn expand_double_stars(glob: &str) -> String {
+    let mut result = String::with_capacity(glob.len());
+    let mut rest = glob;
+    while let Some(index) = rest.find("**") {
+        let previous = rest[..index].chars().last().or(result.chars().last());
+        let next = rest[index + 2..].chars().next();
+        let component = previous.is_none_or(|c| c == '/') && next.is_none_or(|c| c == '/');
+        result.push_str(&rest[..index]);
+        result.push_str(if component { "**" } else { "{*,**/*}" });
+        rest = &rest[index + 2..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Whether a section's glob matches `path`, relative to the `.editorconfig` file's folder.
+fn section_matches(section: &str, relative: &Path) -> bool {
+    // A glob without a slash matches the file name in any folder
+    let glob = match section.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if section.contains('/') => section.to_string(),
+        None => format!("**/{section}"),
+    };
+    GlobBuilder::new(&expand_double_stars(&expand_number_ranges(&glob)))
+        .literal_separator(true)
+        .backslash_escape(true)
+        .build()
+        .is_ok_and(|glob| glob.compile_matcher().is_match(relative))
+}
+
+/// Applies the sections of one `.editorconfig` file matching `path`, returning whether
+/// it's marked as the root.
+fn apply_file(config: &mut EditorConfig, config_path: &Path, path: &Path) -> bool {
+    let Ok(contents) = read_to_string(config_path) else {
+        return false;
+    };
+    let folder = config_path.parent().unwrap_or(Path::new(""));
+    let Ok(relative) = path.strip_prefix(folder) else {
+        return false;
+    };
+    let mut root = false;
+    // Properties before the first section only set `root`
+    let mut section: Option<bool> = None;
+    let mut matched = false;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            let matches = section_matches(glob, relative);
+            matched |= matches;
+            section = Some(matches);
+            continue;
+        }
+        let Some((key, value)) = line.split_once(['=', ':']) else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_lowercase(), value.trim());
+        match section {
+            None if key == "root" => root = value.eq_ignore_ascii_case("true"),
+            Some(true) => config.set(&key, value),
+            _ => {}
+        }
+    }
+    if matched {
+        config.sources.push(config_path.to_path_buf());
+    }
+    root
+}
+
+/// Works out the EditorConfig properties for `path` from the `.editorconfig` files in its
+/// folder and those above it, up to one marked `root = true`.
+pub fn editorconfig_for(path: &Path) -> EditorConfig {
+    let mut config_paths: Vec<PathBuf> = Vec::new();
+    for folder in path.ancestors().skip(1) {
+        let config_path = folder.join(".editorconfig");
+        if !config_path.is_file() {
+            continue;
+        }
+        let is_root = read_to_string(&config_path).is_ok_and(|contents| {
+            contents
+                .lines()
+                .map(str::trim)
+                .take_while(|line| !line.starts_with('['))
+                .filter_map(|line| line.split_once('='))
+                .any(|(key, value)| {
+                    key.trim().eq_ignore_ascii_case("root")
+                        && value.trim().eq_ignore_ascii_case("true")
+                })
+        });
+        config_paths.push(config_path);
+        if is_root {
+            break;
+        }
+    }
+    // Closer files take precedence, so they're applied last
+    let mut config = EditorConfig::default();
+    for config_path in config_paths.iter().rev() {
+        apply_file(&mut config, config_path, path);
+    }
+    config
+}
+
+/// Reads a file's text in the charset EditorConfig asks for, or as UTF-8.
+pub fn decode(bytes: &[u8], charset: Option<Charset>) -> Option<String> {
+    let utf16 = |big_endian: bool| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| {
+                if big_endian {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                }
+            })
+            .collect();
+        String::from_utf16(&units)
+            .ok()
+            .map(|text| text.trim_start_matches('\u{feff}').to_string())
+    };
+    match charset {
+        Some(Charset::Latin1) => Some(bytes.iter().map(|byte| *byte as char).collect()),
+        Some(Charset::Utf16Be) => utf16(true),
+        Some(Charset::Utf16Le) => utf16(false),
+        _ => String::from_utf8(bytes.to_vec())
+            .ok()
+            .map(|text| text.trim_start_matches('\u{feff}').to_string()),
+    }
+}
+
+/// Encodes text for saving, failing for characters latin1 can't hold.
+pub fn encode(text: &str, charset: Option<Charset>) -> Result<Vec<u8>, String> {
+    Ok(match charset {
+        None | Some(Charset::Utf8) => text.as_bytes().to_vec(),
+        Some(Charset::Utf8Bom) => ["\u{feff}", text].concat().into_bytes(),
+        Some(Charset::Latin1) => text
+            .chars()
+            .map(|c| u8::try_from(c).map_err(|_| format!("“{c}” can't be saved as latin1")))
+            .collect::<Result<Vec<u8>, String>>()?,
+        Some(Charset::Utf16Be) => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        Some(Charset::Utf16Le) => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+    })
+}
+
+/// The charset a file is in: the one EditorConfig asks for, or UTF-8 with a byte order
+/// mark when it names none and the file starts with one.
+fn file_charset(bytes: &[u8], charset: Option<Charset>) -> Option<Charset> {
+    match charset {
+        None if bytes.starts_with("\u{feff}".as_bytes()) => Some(Charset::Utf8Bom),
+        _ => charset,
+    }
+}
+
+/// Reads a file that isn't open, like the project search does, returning its text and
+/// the charset to write it back in.
+pub fn read_text(path: &Path) -> Option<(String, Option<Charset>)> {
+    let bytes = std::fs::read(path).ok()?;
+    let charset = file_charset(&bytes, editorconfig_for(path).charset);
+    decode(&bytes, charset).map(|text| (text, charset))
+}
+
+/// Writes text read with `read_text` back in the charset it was read in.
+pub fn write_text(path: &Path, text: &str, charset: Option<Charset>) -> Result<(), String> {
+    std::fs::write(path, encode(text, charset)?).map_err(|error| error.to_string())
+}
+
+/// Uses `line_ending` for every line break in `text`.
+pub fn convert_line_endings(text: &str, line_ending: LineEnding) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\n', line_ending.as_str())
+}
+
+/// Sets up the editor for the current file's EditorConfig properties.
+pub(crate) fn apply_editorconfig(state: &mut State) {
+    let config = if state.current_file_path.as_os_str().is_empty() {
+        EditorConfig::default()
+    } else {
+        editorconfig_for(&state.current_file_path)
+    };
+    let editor = &state.editor;
+    editor.set_show_right_margin(config.max_line_length.is_some());
+    if let Some(length) = config.max_line_length {
+        editor.set_right_margin_position(length);
+    }
+    state.editorconfig_label.set_visible(!config.is_empty());
+    state
+        .editorconfig_label
+        .set_tooltip_text(Some(&config.describe()));
+    state.editorconfig = config;
+    apply_indentation(state);
+}
+
+/// Cleans up the buffer's whitespace, and returns the bytes to write with the
+/// EditorConfig line endings and charset.
+pub(crate) fn prepare_save(state: &mut State) -> Result<Vec<u8>, String> {
+    clean_up_whitespace(state);
+    file_contents(&state.buffer, &state.editorconfig)
+}
+
+/// The buffer's text, folded regions included, encoded as the config says.
+fn file_contents(buffer: &sourceview5::Buffer, config: &EditorConfig) -> Result<Vec<u8>, String> {
+    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), true);
+    let text = match config.end_of_line {
+        Some(line_ending) => convert_line_endings(&text, line_ending),
+        None => text.to_string(),
+    };
+    encode(&text, config.charset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_brace_and_range_globs() {
+        assert!(section_matches("*.{js,py}", Path::new("app.js")));
+        assert!(section_matches("*.{js,py}", Path::new("src/tool.py")));
+        assert!(!section_matches("*.{js,py}", Path::new("main.rs")));
+        assert!(section_matches("file{1..3}.txt", Path::new("file2.txt")));
+        assert!(section_matches(
+            "file{1..3}.txt",
+            Path::new("docs/file3.txt")
+        ));
+        assert!(!section_matches("file{1..3}.txt", Path::new("file4.txt")));
+        assert_eq!(expand_number_ranges("{3..1}"), "{1,2,3}");
+    }
+
+    #[test]
+    fn anchors_globs_with_a_slash() {
+        assert!(section_matches("/Makefile", Path::new("Makefile")));
+        assert!(!section_matches("/Makefile", Path::new("sub/Makefile")));
+        assert!(section_matches("Makefile", Path::new("sub/Makefile")));
+        assert!(section_matches("src/*.rs", Path::new("src/main.rs")));
+        assert!(!section_matches("src/*.rs", Path::new("src/util/mod.rs")));
+        assert!(section_matches("src/**.rs", Path::new("src/util/mod.rs")));
+        assert!(section_matches("src/**.rs", Path::new("src/main.rs")));
+        assert!(!section_matches("src/**.rs", Path::new("lib/main.rs")));
+        assert!(section_matches("docs/**", Path::new("docs/guide/intro.md")));
+    }
+
+    #[test]
+    fn stops_at_the_root_and_unsets_properties() {
+        let folder =
+            std::env::temp_dir().join(format!("cryptum-editorconfig-{}", std::process::id()));
+        let project = folder.join("project");
+        std::fs::create_dir_all(project.join("vendor")).unwrap();
+        // Ignored, as the project's file is marked as the root
+        std::fs::write(folder.join(".editorconfig"), "[*]\ncharset = latin1\n").unwrap();
+        std::fs::write(
+            project.join(".editorconfig"),
+            "root = true\n\n[*]\nindent_style = space\nindent_size = 4\n\n[*.{js,py}]\nindent_size = 2\nend_of_line = crlf\n",
+        )
+        .unwrap();
+        std::fs::write(
+            project.join("vendor/.editorconfig"),
+            "[*]\nindent_style = unset\nindent_size = unset\n",
+        )
+        .unwrap();
+
+        let config = editorconfig_for(&project.join("main.rs"));
+        assert_eq!(config.indent_style, Some(IndentStyle::Space));
+        assert_eq!(config.indent_size, Some(IndentSize::Columns(4)));
+        assert_eq!(config.charset, None);
+        assert_eq!(config.sources, vec![project.join(".editorconfig")]);
+
+        let config = editorconfig_for(&project.join("app.py"));
+        assert_eq!(config.indent_size, Some(IndentSize::Columns(2)));
+        assert_eq!(config.end_of_line, Some(LineEnding::CrLf));
+
+        let config = editorconfig_for(&project.join("vendor/lib.js"));
+        assert_eq!(config.indent_style, None);
+        assert_eq!(config.indent_size, None);
+        assert_eq!(config.end_of_line, Some(LineEnding::CrLf));
+
+        _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn writes_text_back_in_its_charset() {
+        let folder = std::env::temp_dir().join(format!("cryptum-charset-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(
+            folder.join(".editorconfig"),
+            "root = true\n\n[*.txt]\ncharset = latin1\n",
+        )
+        .unwrap();
+
+        let latin1 = folder.join("notes.txt");
+        std::fs::write(&latin1, b"caf\xe9\n").unwrap();
+        let (text, charset) = read_text(&latin1).unwrap();
+        assert_eq!((text.as_str(), charset), ("café\n", Some(Charset::Latin1)));
+        write_text(&latin1, "cafés\n", charset).unwrap();
+        assert_eq!(std::fs::read(&latin1).unwrap(), b"caf\xe9s\n");
+
+        let bom = folder.join("main.rs");
+        std::fs::write(&bom, "\u{feff}fn main() {}\n").unwrap();
+        let (text, charset) = read_text(&bom).unwrap();
+        assert_eq!(
+            (text.as_str(), charset),
+            ("fn main() {}\n", Some(Charset::Utf8Bom))
+        );
+        write_text(&bom, "fn run() {}\n", charset).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&bom).unwrap(),
+            "\u{feff}fn run() {}\n"
+        );
+
+        _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[gtk4::test]
+    fn saves_folded_regions() {
+        let text = "fn main() {\n    let x = 1;\n    println!(\"{x}\");\n}\n";
+        let buffer = sourceview5::Buffer::new(None);
+        buffer.set_text(text);
+        let tag = buffer
+            .create_tag(Some("fold"), &[("invisible", &true)])
+            .unwrap();
+        let (start, end) = (
+            buffer.iter_at_line(1).unwrap(),
+            buffer.iter_at_line(3).unwrap(),
+        );
+        buffer.apply_tag(&tag, &start, &end);
+
+        let config = EditorConfig::default();
+        assert_eq!(file_contents(&buffer, &config).unwrap(), text.as_bytes());
+        let config = EditorConfig {
+            end_of_line: Some(LineEnding::CrLf),
+            ..Default::default()
+        };
+        assert_eq!(
+            file_contents(&buffer, &config).unwrap(),
+            text.replace('\n', "\r\n").as_bytes()
+        );
+    }
+}
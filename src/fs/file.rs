@@ -1,6 +1,7 @@
 use std::{
     fs::{File, exists},
     io::Write,
+    path::{Path, PathBuf},
 };
 
 use gtk4::prelude::TextBufferExt;
@@ -9,13 +10,46 @@ use sourceview5::prelude::BufferExt;
 
 use crate::{
     app::model::{Msg, State},
-    util::widget::update_syntax,
+    fs::editorconfig::{apply_editorconfig, decode, editorconfig_for, prepare_save},
+    lsp::manager::{did_save, open_document},
+    util::{
+        folding::{remember_folds, restore_folds},
+        format::{AfterFormat, format_document},
+        indentation::detect_document_indentation,
+        split::switch_pane_document,
+        whitespace::apply_whitespace_drawing,
+        widget::update_syntax,
+    },
 };
 
+const MAX_RECENT_FILES: usize = 50;
+
+/// Loads `path` into the editor and moves it to the front of the recently used files.
+pub fn open_file(state: &mut State, path: PathBuf) {
+    remember_folds(state);
+    state.recent_files.retain(|recent| recent != &path);
+    state.recent_files.insert(0, path.clone());
+    state.recent_files.truncate(MAX_RECENT_FILES);
+    // Another split may have it open already
+    if switch_pane_document(state, &path) {
+        return;
+    }
+    state.current_file_path = path;
+    load_file(state);
+}
+
 pub fn load_file(state: &mut State) {
-    match std::fs::read_to_string(&state.current_file_path) {
-        Ok(f) => {
+    apply_editorconfig(state);
+    let charset = state.editorconfig.charset;
+    match std::fs::read(&state.current_file_path)
+        .ok()
+        .and_then(|bytes| decode(&bytes, charset))
+    {
+        Some(f) => {
+            state.multi_cursor.clear();
             state.buffer.set_text(&f);
+            state.buffer.set_modified(false);
+            detect_document_indentation(state);
             match update_syntax(
                 &state.language_manager,
                 &state.current_file_path.display().to_string(),
@@ -26,10 +60,14 @@ pub fn load_file(state: &mut State) {
                 }
                 None => {
                     state.buffer.set_highlight_syntax(false);
+                    state.buffer.set_language(None);
                 }
             }
+            apply_whitespace_drawing(state);
+            open_document(state);
+            restore_folds(state);
         }
-        Err(_) => {
+        None => {
             state
                 .toast_overlay
                 .add_toast(Toast::new("Error when loading file!"));
@@ -38,25 +76,59 @@ pub fn load_file(state: &mut State) {
 }
 
 /// The program will attempt to save file, falling back to "Save As"
-/// if it can't create the file from the current file path.
-pub fn save_file(state: &mut State, sender: relm4::ComponentSender<State>) {
+/// if it can't create the file from the current file path. `save` is
+/// `AfterFormat::Save`, or `AfterFormat::SaveAndQuit` to quit once it's written.
+pub fn save_file(state: &mut State, save: AfterFormat, sender: relm4::ComponentSender<State>) {
     if exists(&state.current_file_path).is_ok() {
-        if let Ok(mut file) = File::create(&state.current_file_path) {
-            if file
-                .write_all(
-                    state
-                        .buffer
-                        .text(&state.buffer.start_iter(), &state.buffer.end_iter(), false)
-                        .as_bytes(),
-                )
-                .is_err()
-            {
-                state
-                    .toast_overlay
-                    .add_toast(Toast::new("Error when saving file!"));
-            }
+        if state.format_on_save {
+            // Written once the formatter is done
+            format_document(state, save, sender);
+        } else {
+            write_file(state, save, sender);
+        }
+    }
+}
+
+/// Writes the buffer to the current file, cleaned up and encoded for its EditorConfig
+/// properties, quitting after for `AfterFormat::SaveAndQuit`.
+pub fn write_file(state: &mut State, save: AfterFormat, sender: relm4::ComponentSender<State>) {
+    let contents = match prepare_save(state) {
+        Ok(contents) => contents,
+        Err(error) => {
+            state.toast_overlay.add_toast(Toast::new(&error));
+            return;
+        }
+    };
+    if let Ok(mut file) = File::create(&state.current_file_path) {
+        if file.write_all(&contents).is_err() {
+            state
+                .toast_overlay
+                .add_toast(Toast::new("Error when saving file!"));
         } else {
-            sender.input(Msg::SaveAsRequest);
+            state.buffer.set_modified(false);
+            did_save(state);
+            remember_folds(state);
+            if save == AfterFormat::SaveAndQuit {
+                sender.input(Msg::Quit);
+            }
         }
+    } else {
+        sender.input(Msg::SaveAsRequest);
     }
 }
+
+/// Writes the buffer to `path` from "Save As", cleaned up and encoded for the
+/// EditorConfig properties of `path`.
+pub fn save_file_as(state: &mut State, path: &Path) {
+    let current_config = std::mem::replace(&mut state.editorconfig, editorconfig_for(path));
+    let contents = prepare_save(state);
+    state.editorconfig = current_config;
+    let message = match contents {
+        Ok(contents) => match std::fs::write(path, contents) {
+            Ok(_) => return,
+            Err(_) => "Error when saving file!".to_string(),
+        },
+        Err(error) => error,
+    };
+    state.toast_overlay.add_toast(Toast::new(&message));
+}
@@ -5,6 +5,7 @@ use gtk4::{
     glib::clone,
     prelude::ListItemExt,
 };
+use ignore::WalkBuilder;
 use sourceview5::prelude::*;
 
 use crate::app::model::{Msg, State};
@@ -62,3 +63,26 @@ pub fn load_folder(state: &mut State, sender: relm4::ComponentSender<State>) {
     state.file_view.set_model(Some(&selection));
     state.file_view.set_factory(Some(&factory));
 }
+
+/// Lists every file under the current folder (honouring .gitignore) on a background thread
+/// for the quick open picker.
+pub fn index_folder(state: &State, sender: relm4::ComponentSender<State>) {
+    let folder = state.current_folder_path.clone();
+    let include_hidden = state.view_hidden;
+    relm4::spawn_local(async move {
+        let walk_folder = folder.clone();
+        if let Ok(files) = relm4::spawn_blocking(move || {
+            WalkBuilder::new(&walk_folder)
+                .hidden(!include_hidden)
+                .build()
+                .flatten()
+                .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+                .map(|entry| entry.into_path())
+                .collect::<Vec<_>>()
+        })
+        .await
+        {
+            sender.input(Msg::FolderIndexed(folder, files));
+        }
+    });
+}
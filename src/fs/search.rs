@@ -0,0 +1,145 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+
+use crate::fs::editorconfig::read_text;
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub pattern: String,
+    pub case_sensitive: bool,
+    pub regex: bool,
+    pub include_hidden: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineMatch {
+    /// Zero based line number
+    pub line: u32,
+    /// Zero based character offset into the line
+    pub column: u32,
+    /// Which match this is within its line, used to replace matches selectively
+    pub index: usize,
+    pub preview: String,
+    pub matched: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub matches: Vec<LineMatch>,
+}
+
+impl SearchQuery {
+    pub fn to_regex(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.regex {
+            self.pattern.clone()
+        } else {
+            regex::escape(&self.pattern)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+    }
+
+    /// Literal searches shouldn't treat `$` in the replacement as a capture group.
+    pub fn replacement(&self, replace: &str) -> String {
+        if self.regex {
+            replace.to_string()
+        } else {
+            replace.replace('$', "$$")
+        }
+    }
+}
+
+/// Walks the folder (honouring .gitignore and friends) and hands each file with matches
+/// to `on_file`, stopping early if `cancelled` gets set.
+pub fn search_folder(
+    folder: &Path,
+    query: &SearchQuery,
+    cancelled: &Arc<AtomicBool>,
+    mut on_file: impl FnMut(FileMatches),
+) {
+    let Ok(regex) = query.to_regex() else {
+        return;
+    };
+    for entry in WalkBuilder::new(folder)
+        .hidden(!query.include_hidden)
+        .build()
+        .flatten()
+    {
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        // Skips binary files and ones not in their EditorConfig charset
+        let Some((content, _)) = read_text(entry.path()) else {
+            continue;
+        };
+        let matches = search_text(&content, &regex);
+        if !matches.is_empty() {
+            on_file(FileMatches {
+                path: entry.into_path(),
+                matches,
+            });
+        }
+    }
+}
+
+pub fn search_text(content: &str, regex: &Regex) -> Vec<LineMatch> {
+    let mut matches = Vec::new();
+    for (line, text) in content.lines().enumerate() {
+        let found_iter = regex.find_iter(text).filter(|found| !found.is_empty());
+        for (index, found) in found_iter.enumerate() {
+            matches.push(LineMatch {
+                line: line as u32,
+                column: text[..found.start()].chars().count() as u32,
+                index,
+                preview: text.trim().chars().take(200).collect(),
+                matched: found.as_str().to_string(),
+            });
+        }
+    }
+    matches
+}
+
+/// Replaces only the selected matches (by line and index within the line) in `content`.
+pub fn replace_selected(
+    content: &str,
+    regex: &Regex,
+    replacement: &str,
+    selected: &[(u32, usize)],
+) -> String {
+    let mut output = String::with_capacity(content.len());
+    for (line, text) in content.split_inclusive('\n').enumerate() {
+        // Matches are found without the line ending, the same as `search_text`
+        let body = text
+            .strip_suffix('\n')
+            .map_or(text, |t| t.strip_suffix('\r').unwrap_or(t));
+        let mut index = 0;
+        let mut last = 0;
+        for caps in regex.captures_iter(body) {
+            let found = caps.get(0).unwrap();
+            if found.is_empty() {
+                continue;
+            }
+            if selected.contains(&(line as u32, index)) {
+                output.push_str(&text[last..found.start()]);
+                caps.expand(replacement, &mut output);
+                last = found.end();
+            }
+            index += 1;
+        }
+        output.push_str(&text[last..]);
+    }
+    output
+}
@@ -0,0 +1,31 @@
+use std::{collections::HashMap, fs::read_to_string, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Per file editor state that is kept between runs, unlike `Settings` which applies to
+/// every file.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Session {
+    /// The first line of each folded region, by file
+    #[serde(default)]
+    pub folds: HashMap<PathBuf, Vec<i32>>,
+}
+
+fn session_path() -> PathBuf {
+    let mut session_path = dirs::config_dir().unwrap();
+    session_path.push("cryptum-text-session.json");
+    session_path
+}
+
+pub fn load_session() -> Session {
+    read_to_string(session_path())
+        .ok()
+        .and_then(|session| serde_json::from_str(&session).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_session(session: &Session) {
+    if let Ok(session) = serde_json::to_string_pretty(session) {
+        _ = std::fs::write(session_path(), session);
+    }
+}
@@ -1,9 +1,45 @@
-use std::{fs::read_to_string, path::Path};
+use std::{fs::read_to_string, io::ErrorKind, path::Path};
 
 use gtk4::prelude::*;
+use libadwaita::{Toast, ToastOverlay};
+use serde::{Serialize, de::DeserializeOwned};
 use sourceview5::prelude::*;
 
-use crate::app::model::{Settings, State};
+use crate::{
+    app::model::{IndentSettings, Settings, State},
+    fs::editorconfig::apply_editorconfig,
+    util::{completion::set_popup_delay, whitespace::apply_whitespace_drawing},
+};
+
+/// Loads a JSON config file from the config directory, writing out `default` if it doesn't
+/// exist yet. A file that can't be read or parsed is left for the user to fix, and the
+/// defaults are used with a toast saying why.
+pub fn load_json_config<T: Serialize + DeserializeOwned>(
+    file_name: &str,
+    default: T,
+    toast_overlay: &ToastOverlay,
+) -> T {
+    let Some(config_path) = dirs::config_dir().map(|config_dir| config_dir.join(file_name)) else {
+        return default;
+    };
+    let error = match read_to_string(&config_path) {
+        Ok(config_file) => match serde_json::from_str(&config_file) {
+            Ok(config) => return config,
+            Err(error) => error.to_string(),
+        },
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            if let Ok(config_file) = serde_json::to_string_pretty(&default) {
+                _ = std::fs::write(&config_path, config_file);
+            }
+            return default;
+        }
+        Err(error) => error.to_string(),
+    };
+    toast_overlay.add_toast(Toast::new(&format!(
+        "Couldn't load {file_name}, using the defaults: {error}"
+    )));
+    default
+}
 
 pub fn save_settings(state: &mut State) {
     let mut config_path = dirs::config_dir().unwrap();
@@ -14,8 +50,13 @@ pub fn save_settings(state: &mut State) {
         serde_json::to_string_pretty(&Settings {
             editor_monospace: state.editor.is_monospace(),
             editor_theme: state.buffer_style.as_ref().unwrap().to_string(),
-            editor_use_spaces_for_tabs: state.editor.is_insert_spaces_instead_of_tabs(),
-            editor_tab_width: state.editor.tab_width(),
+            editor_use_spaces_for_tabs: state.indent_settings.use_spaces,
+            editor_tab_width: state.indent_settings.tab_width,
+            editor_completion_delay: state.completion.popup_delay.get(),
+            editor_format_on_save: state.format_on_save,
+            editor_keymap: state.keymap.keymap,
+            editor_whitespace: state.whitespace,
+            editor_whitespace_languages: state.whitespace_languages.clone(),
             view_sidebar: state.nav_view.shows_sidebar(),
             view_mini_map: state.mini_map.is_visible(),
             view_hidden_files: state.view_hidden,
@@ -53,8 +94,16 @@ pub fn load_settings(state: &mut State) {
     state.nav_view.set_show_sidebar(settings.view_sidebar);
     state.mini_map.set_visible(settings.view_mini_map);
     state.view_hidden = settings.view_hidden_files;
-    state
-        .editor
-        .set_insert_spaces_instead_of_tabs(settings.editor_use_spaces_for_tabs);
-    state.editor.set_tab_width(settings.editor_tab_width);
+    state.indent_settings = IndentSettings {
+        use_spaces: settings.editor_use_spaces_for_tabs,
+        tab_width: settings.editor_tab_width,
+    };
+    apply_editorconfig(state);
+    state.whitespace = settings.editor_whitespace;
+    state.whitespace_languages = settings.editor_whitespace_languages;
+    apply_whitespace_drawing(state);
+    set_popup_delay(state, settings.editor_completion_delay);
+    state.format_on_save = settings.editor_format_on_save;
+    // Applied by the caller, which can attach the keymap's handlers
+    state.keymap.keymap = settings.editor_keymap;
 }
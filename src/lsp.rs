@@ -0,0 +1,7 @@
+pub mod client;
+pub mod config;
+pub mod diagnostics;
+pub mod handlers;
+pub mod manager;
+pub mod providers;
+pub mod transport;
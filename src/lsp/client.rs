@@ -0,0 +1,574 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufReader},
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicI64, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+use gtk4::{
+    gio::{self, prelude::FileExt},
+    glib::clone,
+};
+use serde_json::{Value, json};
+
+use crate::lsp::{
+    config::LspServerConfig,
+    transport::{read_message, write_message},
+};
+
+/// How long a server gets to answer `shutdown`, and then to exit, before it's killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A completion result, trimmed down to what the completion popup shows.
+#[derive(Debug, Clone)]
+pub struct CompletionEntry {
+    pub label: String,
+    pub detail: String,
+    pub documentation: String,
+    pub insert_text: String,
+    /// The range `insert_text` replaces when the server sent a `textEdit`, as zero based
+    /// lines and UTF-16 columns
+    pub range: Option<((u32, u32), (u32, u32))>,
+}
+
+/// What to do with the response to a request sent to the server.
+#[derive(Debug)]
+pub enum PendingRequest {
+    Initialize,
+    Completion(relm4::Sender<Vec<CompletionEntry>>),
+    /// Diagnostics at the hovered position are shown above the server's hover text
+    Hover(relm4::Sender<Option<String>>, Option<String>),
+    Definition,
+    References,
+    Rename,
+}
+
+impl PendingRequest {
+    /// Passes a response on to the completion or hover provider waiting for it. Any other
+    /// request is given back, to be handled with the editor's state.
+    pub fn reply(self, message: &Value) -> Option<Self> {
+        let result = &message["result"];
+        let failed = message.get("error").is_some();
+        match self {
+            PendingRequest::Completion(reply) => {
+                reply.emit(if failed {
+                    Vec::new()
+                } else {
+                    parse_completion(result)
+                });
+                None
+            }
+            PendingRequest::Hover(reply, diagnostics) => {
+                let hover = if failed {
+                    String::new()
+                } else {
+                    markup_content_text(&result["contents"])
+                };
+                let text = [diagnostics.unwrap_or_default(), hover]
+                    .into_iter()
+                    .filter(|text| !text.trim().is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                reply.emit((!text.is_empty()).then_some(text));
+                None
+            }
+            pending => Some(pending),
+        }
+    }
+}
+
+/// Messages from the server's reader thread, forwarded to the main thread.
+#[derive(Debug)]
+pub enum ServerEvent {
+    Message(Value),
+    Exited,
+}
+
+/// A running language server, talking JSON-RPC over its stdin and stdout.
+#[derive(Debug)]
+pub struct LspClient {
+    child: Child,
+    stdin: ChildStdin,
+    next_id: i64,
+    pub pending: HashMap<i64, PendingRequest>,
+    pub initialized: bool,
+    /// Notifications sent before the server finished initializing
+    queued: Vec<Value>,
+    pub capabilities: Value,
+    /// Id of the `shutdown` request, whose response the reader thread passes to
+    /// `shutdown_reply` instead of `on_event`
+    shutdown_id: Arc<AtomicI64>,
+    shutdown_reply: mpsc::Receiver<()>,
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    gio::File::for_path(path).uri().to_string()
+}
+
+pub fn uri_to_path(uri: &str) -> Option<std::path::PathBuf> {
+    gio::File::for_uri(uri).path()
+}
+
+impl LspClient {
+    /// Starts the server in `root` and sends the `initialize` request. Everything the
+    /// server sends is passed to `on_event` from a background thread.
+    pub fn spawn(
+        config: &LspServerConfig,
+        root: &Path,
+        on_event: impl Fn(ServerEvent) + Send + 'static,
+    ) -> io::Result<LspClient> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let shutdown_id = Arc::new(AtomicI64::new(0));
+        let (shutdown_sender, shutdown_reply) = mpsc::channel();
+        std::thread::spawn(clone!(
+            #[strong]
+            shutdown_id,
+            move || {
+                while let Ok(Some(message)) = read_message(&mut stdout) {
+                    if message.get("method").is_none()
+                        && message["id"].as_i64() == Some(shutdown_id.load(Ordering::Relaxed))
+                    {
+                        _ = shutdown_sender.send(());
+                        continue;
+                    }
+                    on_event(ServerEvent::Message(message));
+                }
+                on_event(ServerEvent::Exited);
+            }
+        ));
+
+        let mut client = LspClient {
+            child,
+            stdin,
+            next_id: 0,
+            pending: HashMap::new(),
+            initialized: false,
+            queued: Vec::new(),
+            capabilities: Value::Null,
+            shutdown_id,
+            shutdown_reply,
+        };
+        let root_uri = path_to_uri(root);
+        client.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{
+                    "uri": root_uri,
+                    "name": root.file_name().map(|name| name.to_string_lossy()).unwrap_or_default(),
+                }],
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "completion": { "completionItem": { "snippetSupport": false } },
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                        "definition": {},
+                        "references": {},
+                        "rename": { "prepareSupport": false },
+                        "publishDiagnostics": {},
+                    },
+                    "workspace": { "workspaceFolders": true },
+                },
+            }),
+            PendingRequest::Initialize,
+        )?;
+        Ok(client)
+    }
+
+    pub fn request(
+        &mut self,
+        method: &str,
+        params: Value,
+        pending: PendingRequest,
+    ) -> io::Result<()> {
+        self.next_id += 1;
+        self.pending.insert(self.next_id, pending);
+        write_message(
+            &mut self.stdin,
+            &json!({ "jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params }),
+        )
+    }
+
+    /// Sends a notification, holding it back until the server has been initialized.
+    pub fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        if self.initialized {
+            write_message(&mut self.stdin, &message)
+        } else {
+            self.queued.push(message);
+            Ok(())
+        }
+    }
+
+    /// Replies to a request sent by the server.
+    pub fn reply(&mut self, id: &Value, result: Value) -> io::Result<()> {
+        write_message(
+            &mut self.stdin,
+            &json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        )
+    }
+
+    /// Finishes the handshake once the `initialize` response arrives.
+    pub fn on_initialized(&mut self, result: &Value) -> io::Result<()> {
+        self.capabilities = result["capabilities"].clone();
+        self.initialized = true;
+        self.notify("initialized", json!({}))?;
+        for message in std::mem::take(&mut self.queued) {
+            write_message(&mut self.stdin, &message)?;
+        }
+        Ok(())
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        !matches!(
+            self.capabilities.get(capability),
+            None | Some(Value::Null) | Some(Value::Bool(false))
+        )
+    }
+}
+
+impl Drop for LspClient {
+    /// Asks the server to shut down and exit, killing it if it doesn't in time.
+    fn drop(&mut self) {
+        self.next_id += 1;
+        self.shutdown_id.store(self.next_id, Ordering::Relaxed);
+        if write_message(
+            &mut self.stdin,
+            &json!({ "jsonrpc": "2.0", "id": self.next_id, "method": "shutdown" }),
+        )
+        .is_ok()
+        {
+            // Fails straight away if the server is already gone
+            _ = self.shutdown_reply.recv_timeout(SHUTDOWN_TIMEOUT);
+        }
+        _ = write_message(
+            &mut self.stdin,
+            &json!({ "jsonrpc": "2.0", "method": "exit" }),
+        );
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while matches!(self.child.try_wait(), Ok(None)) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+/// Parses a completion response, which is either a list of items or a `CompletionList`.
+pub fn parse_completion(result: &Value) -> Vec<CompletionEntry> {
+    let items = match result {
+        Value::Array(items) => items,
+        Value::Object(list) => match list.get("items") {
+            Some(Value::Array(items)) => items,
+            _ => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let label = item["label"].as_str()?.to_string();
+            let insert_text = item["textEdit"]["newText"]
+                .as_str()
+                .or_else(|| item["insertText"].as_str())
+                .unwrap_or(&label)
+                .to_string();
+            let range = &item["textEdit"]["range"];
+            Some(CompletionEntry {
+                range: lsp_position(&range["start"]).zip(lsp_position(&range["end"])),
+                detail: item["detail"].as_str().unwrap_or_default().to_string(),
+                documentation: markup_content_text(&item["documentation"]),
+                label,
+                insert_text,
+            })
+        })
+        .collect()
+}
+
+/// Parses an LSP `Position` into its line and UTF-16 column.
+fn lsp_position(position: &Value) -> Option<(u32, u32)> {
+    Some((
+        position["line"].as_u64()? as u32,
+        position["character"].as_u64()? as u32,
+    ))
+}
+
+/// Flattens `MarkupContent`, `MarkedString` or a list of them into plain text.
+pub fn markup_content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(markup_content_text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Value::Object(object) => object
+            .get("value")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Parses a `Location`, `Location[]` or `LocationLink[]` response into (uri, line, character).
+pub fn parse_locations(result: &Value) -> Vec<(String, u32, u32)> {
+    let locations = match result {
+        Value::Array(locations) => locations.clone(),
+        Value::Object(_) => vec![result.clone()],
+        _ => Vec::new(),
+    };
+    locations
+        .iter()
+        .filter_map(|location| {
+            let uri = location["uri"]
+                .as_str()
+                .or_else(|| location["targetUri"].as_str())?;
+            let range = if location["range"].is_object() {
+                &location["range"]
+            } else {
+                &location["targetSelectionRange"]
+            };
+            Some((
+                uri.to_string(),
+                range["start"]["line"].as_u64()? as u32,
+                range["start"]["character"].as_u64()? as u32,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use serde_json::{Value, json};
+
+    use super::{
+        LspClient, PendingRequest, ServerEvent, markup_content_text, parse_completion,
+        parse_locations,
+    };
+    use crate::lsp::{config::LspServerConfig, diagnostics::parse_diagnostics};
+
+    #[test]
+    fn parses_completion_lists() {
+        let items = json!([
+            { "label": "push", "detail": "fn push(&mut self)", "insertText": "push()" },
+            {
+                "label": "pop",
+                "textEdit": {
+                    "newText": "pop()",
+                    "range": {
+                        "start": { "line": 3, "character": 4 },
+                        "end": { "line": 3, "character": 6 },
+                    },
+                },
+                "insertText": "ignored",
+            },
+            { "label": "len", "documentation": { "kind": "markdown", "value": "The length" } },
+            { "detail": "no label" },
+        ]);
+        let entries = parse_completion(&items);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].insert_text, "push()");
+        assert_eq!(entries[0].detail, "fn push(&mut self)");
+        assert_eq!(entries[1].insert_text, "pop()");
+        assert_eq!(entries[1].range, Some(((3, 4), (3, 6))));
+        assert_eq!(entries[0].range, None);
+        assert_eq!(entries[2].insert_text, "len");
+        assert_eq!(entries[2].documentation, "The length");
+
+        let list = json!({ "isIncomplete": false, "items": items });
+        assert_eq!(parse_completion(&list).len(), 3);
+        assert!(parse_completion(&Value::Null).is_empty());
+    }
+
+    #[test]
+    fn flattens_markup_content() {
+        assert_eq!(markup_content_text(&json!("plain")), "plain");
+        assert_eq!(
+            markup_content_text(&json!({ "kind": "markdown", "value": "**bold**" })),
+            "**bold**"
+        );
+        assert_eq!(
+            markup_content_text(&json!([
+                { "language": "rust", "value": "fn main()" },
+                "",
+                "Runs first",
+            ])),
+            "fn main()\n\nRuns first"
+        );
+        assert_eq!(markup_content_text(&Value::Null), "");
+    }
+
+    #[test]
+    fn parses_locations_and_links() {
+        let range = json!({
+            "start": { "line": 4, "character": 2 },
+            "end": { "line": 4, "character": 6 },
+        });
+        let location = json!({ "uri": "file:///a.rs", "range": range });
+        assert_eq!(
+            parse_locations(&location),
+            vec![("file:///a.rs".to_string(), 4, 2)]
+        );
+        let link = json!({
+            "targetUri": "file:///b.rs",
+            "targetRange": {
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 9, "character": 1 },
+            },
+            "targetSelectionRange": range,
+        });
+        assert_eq!(
+            parse_locations(&json!([location, link])),
+            vec![
+                ("file:///a.rs".to_string(), 4, 2),
+                ("file:///b.rs".to_string(), 4, 2)
+            ]
+        );
+        assert!(parse_locations(&Value::Null).is_empty());
+    }
+
+    /// The example server, built next to the test binary by `cargo test`.
+    fn mock_server() -> LspServerConfig {
+        // Test binaries are in target/<profile>/deps, examples in target/<profile>/examples
+        let mut path = std::env::current_exe().unwrap();
+        path.pop();
+        path.pop();
+        path.push("examples");
+        path.push(format!("mock_lsp{}", std::env::consts::EXE_SUFFIX));
+        LspServerConfig {
+            command: path.display().to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    fn next_event(events: &mpsc::Receiver<ServerEvent>) -> ServerEvent {
+        events
+            .recv_timeout(Duration::from_secs(5))
+            .expect("The server didn't answer in time")
+    }
+
+    fn next_message(events: &mpsc::Receiver<ServerEvent>) -> Value {
+        match next_event(events) {
+            ServerEvent::Message(message) => message,
+            ServerEvent::Exited => panic!("The server exited"),
+        }
+    }
+
+    #[test]
+    fn talks_to_the_mock_server() {
+        let (event_sender, events) = mpsc::channel();
+        let mut client = LspClient::spawn(&mock_server(), &std::env::temp_dir(), move |event| {
+            _ = event_sender.send(event)
+        })
+        .unwrap();
+
+        let response = next_message(&events);
+        let id = response["id"].as_i64().unwrap();
+        assert!(matches!(
+            client.pending.remove(&id),
+            Some(PendingRequest::Initialize)
+        ));
+        client.on_initialized(&response["result"]).unwrap();
+        assert!(client.supports("hoverProvider"));
+        assert!(!client.supports("documentFormattingProvider"));
+
+        let uri = "file:///mock/main.rs";
+        let (reply_sender, reply_receiver) = relm4::channel();
+        client
+            .notify(
+                "textDocument/didOpen",
+                json!({
+                    "textDocument": {
+                        "uri": uri,
+                        "languageId": "rust",
+                        "version": 1,
+                        "text": "let a = 1; // TODO\nlet b = a;\n",
+                    },
+                }),
+            )
+            .unwrap();
+        let notification = next_message(&events);
+        assert_eq!(
+            notification["method"].as_str(),
+            Some("textDocument/publishDiagnostics")
+        );
+        let diagnostics = parse_diagnostics(&notification["params"]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, 2);
+        assert_eq!(
+            (diagnostics[0].start, diagnostics[0].end),
+            ((0, 14), (0, 18))
+        );
+
+        client
+            .request(
+                "textDocument/completion",
+                json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": 1, "character": 0 },
+                }),
+                PendingRequest::Completion(reply_sender),
+            )
+            .unwrap();
+        let response = next_message(&events);
+        let pending = client
+            .pending
+            .remove(&response["id"].as_i64().unwrap())
+            .unwrap();
+        assert!(pending.reply(&response).is_none());
+        let labels: Vec<String> = reply_receiver
+            .recv_sync()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.label)
+            .collect();
+        assert_eq!(labels, ["mock_alpha", "mock_beta", "mock_gamma"]);
+
+        client
+            .request(
+                "textDocument/definition",
+                json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": 1, "character": 8 },
+                }),
+                PendingRequest::Definition,
+            )
+            .unwrap();
+        let response = next_message(&events);
+        let pending = client
+            .pending
+            .remove(&response["id"].as_i64().unwrap())
+            .unwrap();
+        assert!(matches!(
+            pending.reply(&response),
+            Some(PendingRequest::Definition)
+        ));
+        assert_eq!(
+            parse_locations(&response["result"]),
+            vec![(uri.to_string(), 0, 4)]
+        );
+
+        // The response to `shutdown` isn't passed on, the server exiting is
+        drop(client);
+        assert!(matches!(next_event(&events), ServerEvent::Exited));
+    }
+}
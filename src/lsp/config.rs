@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use libadwaita::ToastOverlay;
+use serde::{Deserialize, Serialize};
+
+use crate::fs::settings::load_json_config;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LspServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Language servers keyed by sourceview language id.
+pub type LspConfig = HashMap<String, LspServerConfig>;
+
+fn server(command: &str, args: &[&str]) -> LspServerConfig {
+    LspServerConfig {
+        command: command.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    }
+}
+
+pub fn default_lsp_config() -> LspConfig {
+    let typescript = server("typescript-language-server", &["--stdio"]);
+    HashMap::from([
+        ("rust".to_string(), server("rust-analyzer", &[])),
+        ("python".to_string(), server("pylsp", &[])),
+        ("python3".to_string(), server("pylsp", &[])),
+        ("c".to_string(), server("clangd", &[])),
+        ("cpp".to_string(), server("clangd", &[])),
+        ("js".to_string(), typescript.clone()),
+        ("typescript".to_string(), typescript),
+    ])
+}
+
+/// Loads the language server config, writing out the defaults if the file doesn't exist yet.
+pub fn load_lsp_config(toast_overlay: &ToastOverlay) -> LspConfig {
+    load_json_config("cryptum-text-lsp.json", default_lsp_config(), toast_overlay)
+}
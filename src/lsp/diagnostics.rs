@@ -0,0 +1,133 @@
+use gtk4::{pango::Underline, prelude::*};
+use serde_json::Value;
+use sourceview5::{MarkAttributes, prelude::*};
+
+use crate::{
+    app::model::State,
+    lsp::{client::path_to_uri, manager::buffer_iter},
+};
+
+const SEVERITY_CATEGORIES: [(&str, &str); 3] = [
+    ("lsp-error", "dialog-error-symbolic"),
+    ("lsp-warning", "dialog-warning-symbolic"),
+    ("lsp-info", "dialog-information-symbolic"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// 1 is an error, 2 a warning, 3 and 4 are information and hints
+    pub severity: u64,
+    pub message: String,
+    /// What produced the diagnostic, such as `rustc` or `clippy`
+    pub source: String,
+    /// Zero based lines and UTF-16 columns, as sent by the server
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+}
+
+impl Diagnostic {
+    fn category(&self) -> &'static str {
+        match self.severity {
+            1 => SEVERITY_CATEGORIES[0].0,
+            2 => SEVERITY_CATEGORIES[1].0,
+            _ => SEVERITY_CATEGORIES[2].0,
+        }
+    }
+}
+
+/// Sets up the gutter icons used to show diagnostics in an editor.
+pub fn setup_diagnostic_marks(editor: &sourceview5::View) {
+    editor.set_show_line_marks(true);
+    for (priority, (category, icon)) in SEVERITY_CATEGORIES.iter().rev().enumerate() {
+        let attributes = MarkAttributes::new();
+        attributes.set_icon_name(icon);
+        editor.set_mark_attributes(category, &attributes, priority as i32);
+    }
+}
+
+/// Sets up the underlines used to show diagnostics in a buffer.
+pub fn setup_diagnostic_tags(buffer: &sourceview5::Buffer) {
+    buffer.create_tag(Some("lsp-error"), &[("underline", &Underline::Error)]);
+    buffer.create_tag(Some("lsp-warning"), &[("underline", &Underline::Single)]);
+    buffer.create_tag(Some("lsp-info"), &[]);
+}
+
+/// Parses the diagnostics of a `textDocument/publishDiagnostics` notification.
+pub fn parse_diagnostics(params: &Value) -> Vec<Diagnostic> {
+    let Some(diagnostics) = params["diagnostics"].as_array() else {
+        return Vec::new();
+    };
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let range = &diagnostic["range"];
+            Some(Diagnostic {
+                severity: diagnostic["severity"].as_u64().unwrap_or(1),
+                message: diagnostic["message"].as_str()?.to_string(),
+                source: diagnostic["source"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                start: (
+                    range["start"]["line"].as_u64()? as u32,
+                    range["start"]["character"].as_u64()? as u32,
+                ),
+                end: (
+                    range["end"]["line"].as_u64()? as u32,
+                    range["end"]["character"].as_u64()? as u32,
+                ),
+            })
+        })
+        .collect()
+}
+
+/// The current file's diagnostics from every source, its language server and the last
+/// task's output.
+fn current_diagnostics(state: &State) -> Vec<&Diagnostic> {
+    if state.current_file_path.as_os_str().is_empty() {
+        return Vec::new();
+    }
+    let uri = path_to_uri(&state.current_file_path);
+    [&state.lsp.diagnostics, &state.problems.task_diagnostics]
+        .into_iter()
+        .filter_map(|diagnostics| diagnostics.get(&uri))
+        .flatten()
+        .collect()
+}
+
+/// Replaces the marks and underlines in the buffer with the current file's diagnostics.
+pub(crate) fn show_diagnostics(state: &State) {
+    let buffer = &state.buffer;
+    let (start, end) = buffer.bounds();
+    for (category, _) in SEVERITY_CATEGORIES {
+        buffer.remove_source_marks(&start, &end, Some(category));
+        buffer.remove_tag_by_name(category, &start, &end);
+    }
+    for diagnostic in current_diagnostics(state) {
+        let (Some(start), Some(end)) = (
+            buffer_iter(buffer, diagnostic.start),
+            buffer_iter(buffer, diagnostic.end),
+        ) else {
+            continue;
+        };
+        buffer.create_source_mark(None, diagnostic.category(), &start);
+        buffer.apply_tag_by_name(diagnostic.category(), &start, &end);
+    }
+}
+
+/// The messages of the diagnostics covering a zero based line, for the hover popover.
+pub(crate) fn diagnostics_at_line(state: &State, line: i32) -> Option<String> {
+    let messages: Vec<String> = current_diagnostics(state)
+        .into_iter()
+        .filter(|diagnostic| (diagnostic.start.0..=diagnostic.end.0).contains(&(line as u32)))
+        .map(|diagnostic| {
+            let prefix = match diagnostic.severity {
+                1 => "Error",
+                2 => "Warning",
+                _ => "Info",
+            };
+            format!("{prefix}: {}", diagnostic.message)
+        })
+        .collect();
+    (!messages.is_empty()).then(|| messages.join("\n"))
+}
@@ -0,0 +1,447 @@
+use std::path::Path;
+
+use gtk4::{
+    glib::{self, clone},
+    prelude::*,
+};
+use libadwaita::{AlertDialog, ResponseAppearance, Toast, prelude::*};
+use serde_json::{Value, json};
+
+use crate::{
+    app::model::{Msg, State},
+    fs::file::open_file,
+    lsp::{
+        client::{
+            CompletionEntry, PendingRequest, ServerEvent, parse_locations, path_to_uri, uri_to_path,
+        },
+        diagnostics::{diagnostics_at_line, parse_diagnostics, show_diagnostics},
+        manager::{
+            buffer_iter, char_column, document_client, flush_changes, line_text, protocol_position,
+        },
+    },
+    util::{
+        picker::{PickerAction, PickerItem, show_picker},
+        problems::refresh_problems,
+        widget::place_cursor,
+    },
+};
+
+/// Handles a message or the exit of the server keyed by `server`.
+pub(crate) fn handle_server_event(state: &mut State, server: String, event: ServerEvent) {
+    let message = match event {
+        ServerEvent::Message(message) => message,
+        ServerEvent::Exited => {
+            // Servers that were stopped on purpose are already gone
+            if state.lsp.clients.remove(&server).is_none() {
+                return;
+            }
+            // Starting it again for the next document would most likely crash it again
+            state.lsp.failed.insert(server.clone());
+            if state
+                .lsp
+                .document
+                .as_ref()
+                .is_some_and(|document| document.server == server)
+            {
+                state.lsp.document = None;
+                show_diagnostics(state);
+            }
+            state
+                .toast_overlay
+                .add_toast(Toast::new(&format!("Language server \"{server}\" exited")));
+            return;
+        }
+    };
+    let Some(client) = state.lsp.clients.get_mut(&server) else {
+        return;
+    };
+
+    match (message.get("id"), message["method"].as_str()) {
+        // Requests from the server
+        (Some(id), Some(method)) => {
+            let result = match method {
+                // One (empty) settings object per requested section
+                "workspace/configuration" => Value::Array(
+                    message["params"]["items"]
+                        .as_array()
+                        .map(|items| items.iter().map(|_| Value::Null).collect())
+                        .unwrap_or_default(),
+                ),
+                _ => Value::Null,
+            };
+            _ = client.reply(id, result);
+        }
+        // Responses to our requests
+        (Some(id), None) => {
+            let Some(pending) = id.as_i64().and_then(|id| client.pending.remove(&id)) else {
+                return;
+            };
+            handle_response(state, &server, pending, &message);
+        }
+        // Notifications
+        (None, Some("textDocument/publishDiagnostics")) => {
+            let params = &message["params"];
+            let Some(uri) = params["uri"].as_str() else {
+                return;
+            };
+            state
+                .lsp
+                .diagnostics
+                .insert(uri.to_string(), parse_diagnostics(params));
+            refresh_problems(state);
+            if path_to_uri(&state.current_file_path) == uri {
+                show_diagnostics(state);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn handle_response(state: &mut State, server: &str, pending: PendingRequest, message: &Value) {
+    let Some(pending) = pending.reply(message) else {
+        return;
+    };
+    let result = &message["result"];
+    if let Some(error) = message.get("error") {
+        state.toast_overlay.add_toast(Toast::new(&format!(
+            "Language server error: {}",
+            error["message"].as_str().unwrap_or("unknown error")
+        )));
+        return;
+    }
+
+    match pending {
+        PendingRequest::Initialize => {
+            if let Some(client) = state.lsp.clients.get_mut(server) {
+                _ = client.on_initialized(result);
+            }
+        }
+        // Answered by `PendingRequest::reply`
+        PendingRequest::Completion(_) | PendingRequest::Hover(..) => {}
+        PendingRequest::Definition => match parse_locations(result).first() {
+            Some((uri, line, character)) => open_location(state, uri, *line, *character),
+            None => state
+                .toast_overlay
+                .add_toast(Toast::new("No definition found")),
+        },
+        PendingRequest::References => show_references(state, parse_locations(result)),
+        PendingRequest::Rename => {
+            let files = apply_workspace_edit(state, result);
+            state
+                .toast_overlay
+                .add_toast(Toast::new(&format!("Renamed in {files} file(s)")));
+        }
+    }
+}
+
+/// Sends `method` for the cursor position, if the open document's server supports `capability`.
+fn request_at_cursor(
+    state: &mut State,
+    capability: &str,
+    method: &str,
+    extra: Value,
+    pending: PendingRequest,
+) {
+    flush_changes(state);
+    let cursor = state.buffer.iter_at_mark(&state.buffer.get_insert());
+    let position = protocol_position(&state.buffer, cursor.line(), cursor.line_offset());
+    let Some((document, client)) = document_client(state, capability) else {
+        state.toast_overlay.add_toast(Toast::new(
+            "No language server supporting this for the current file",
+        ));
+        return;
+    };
+    let mut params = json!({
+        "textDocument": { "uri": document.uri },
+        "position": position,
+    });
+    if let (Value::Object(params), Value::Object(extra)) = (&mut params, extra) {
+        params.extend(extra);
+    }
+    _ = client.request(method, params, pending);
+}
+
+pub(crate) fn request_completion(
+    state: &mut State,
+    line: i32,
+    column: i32,
+    reply: relm4::Sender<Vec<CompletionEntry>>,
+) {
+    flush_changes(state);
+    let position = protocol_position(&state.buffer, line, column);
+    match document_client(state, "completionProvider") {
+        Some((document, client)) => {
+            let params = json!({ "textDocument": { "uri": document.uri }, "position": position });
+            if client
+                .request(
+                    "textDocument/completion",
+                    params,
+                    PendingRequest::Completion(reply.clone()),
+                )
+                .is_err()
+            {
+                reply.emit(Vec::new());
+            }
+        }
+        None => reply.emit(Vec::new()),
+    }
+}
+
+pub(crate) fn request_hover(
+    state: &mut State,
+    line: i32,
+    column: i32,
+    reply: relm4::Sender<Option<String>>,
+) {
+    flush_changes(state);
+    let diagnostics = diagnostics_at_line(state, line);
+    let position = protocol_position(&state.buffer, line, column);
+    match document_client(state, "hoverProvider") {
+        Some((document, client)) => {
+            let params = json!({ "textDocument": { "uri": document.uri }, "position": position });
+            if client
+                .request(
+                    "textDocument/hover",
+                    params,
+                    PendingRequest::Hover(reply.clone(), diagnostics.clone()),
+                )
+                .is_err()
+            {
+                reply.emit(diagnostics);
+            }
+        }
+        None => reply.emit(diagnostics),
+    }
+}
+
+pub(crate) fn go_to_definition(state: &mut State) {
+    request_at_cursor(
+        state,
+        "definitionProvider",
+        "textDocument/definition",
+        Value::Null,
+        PendingRequest::Definition,
+    );
+}
+
+pub(crate) fn find_references(state: &mut State) {
+    request_at_cursor(
+        state,
+        "referencesProvider",
+        "textDocument/references",
+        json!({ "context": { "includeDeclaration": true } }),
+        PendingRequest::References,
+    );
+}
+
+pub(crate) fn rename_symbol(state: &mut State, new_name: String) {
+    request_at_cursor(
+        state,
+        "renameProvider",
+        "textDocument/rename",
+        json!({ "newName": new_name }),
+        PendingRequest::Rename,
+    );
+}
+
+/// Asks for the new name of the symbol under the cursor.
+pub(crate) fn show_rename_dialog(state: &State, sender: relm4::ComponentSender<State>) {
+    let cursor = state.buffer.iter_at_mark(&state.buffer.get_insert());
+    let line = line_text(&state.buffer, cursor.line());
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut start = (cursor.line_offset() as usize).min(chars.len());
+    let mut end = start;
+    while start > 0 && is_word(chars[start - 1]) {
+        start -= 1;
+    }
+    while end < chars.len() && is_word(chars[end]) {
+        end += 1;
+    }
+
+    let entry = gtk4::Entry::builder()
+        .text(chars[start..end].iter().collect::<String>())
+        .activates_default(true)
+        .build();
+    let dialog = AlertDialog::builder()
+        .heading("Rename Symbol")
+        .extra_child(&entry)
+        .default_response("rename")
+        .close_response("cancel")
+        .build();
+    dialog.add_responses(&[("cancel", "Cancel"), ("rename", "Rename")]);
+    dialog.set_response_appearance("rename", ResponseAppearance::Suggested);
+    dialog.connect_response(
+        None,
+        clone!(
+            #[weak]
+            entry,
+            move |_, response| {
+                if response == "rename" && !entry.text().is_empty() {
+                    sender.input(Msg::RenameSymbol(entry.text().to_string()));
+                }
+            }
+        ),
+    );
+    dialog.present(Some(&state.root));
+    entry.grab_focus();
+}
+
+/// Opens the file at `uri` if it isn't the current one, and moves the cursor to the
+/// protocol position.
+pub(crate) fn open_location(state: &mut State, uri: &str, line: u32, character: u32) {
+    let Some(path) = uri_to_path(uri) else {
+        return;
+    };
+    if path != state.current_file_path {
+        open_file(state, path);
+    }
+    let column = char_column(&line_text(&state.buffer, line as i32), character);
+    place_cursor(state, line as i32, column);
+}
+
+fn show_references(state: &mut State, locations: Vec<(String, u32, u32)>) {
+    if locations.is_empty() {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("No references found"));
+        return;
+    }
+    let mut items = Vec::new();
+    for (uri, line, character) in locations {
+        let Some(path) = uri_to_path(&uri) else {
+            continue;
+        };
+        let text = if path == state.current_file_path {
+            line_text(&state.buffer, line as i32)
+        } else {
+            std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|text| text.lines().nth(line as usize).map(str::to_string))
+                .unwrap_or_default()
+        };
+        let display_path = path
+            .strip_prefix(&state.current_folder_path)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        items.push(PickerItem {
+            title: text.trim().to_string(),
+            subtitle: format!("{display_path}:{}", line + 1),
+            action: PickerAction::OpenLocation(
+                path.clone(),
+                line as i32,
+                char_column(&text, character),
+            ),
+            recent: path == state.current_file_path,
+        });
+    }
+    show_picker(state, "References", "Filter references", items);
+}
+
+/// The byte offset of a protocol position in `text`.
+fn byte_offset(text: &str, (line, character): (u32, u32)) -> usize {
+    let mut offset = 0;
+    for (index, content) in text.split_inclusive('\n').enumerate() {
+        if index == line as usize {
+            let column = char_column(content, character) as usize;
+            return offset
+                + content
+                    .char_indices()
+                    .nth(column)
+                    .map(|(byte, _)| byte)
+                    .unwrap_or(content.trim_end_matches(['\r', '\n']).len());
+        }
+        offset += content.len();
+    }
+    text.len()
+}
+
+/// A protocol `TextEdit`, with (line, UTF-16 column) positions.
+struct TextEdit {
+    start: (u32, u32),
+    end: (u32, u32),
+    new_text: String,
+}
+
+/// Parses text edits, sorted so they can be applied from the end backwards.
+fn text_edits(edits: &Value) -> Vec<TextEdit> {
+    let position = |position: &Value| -> Option<(u32, u32)> {
+        Some((
+            position["line"].as_u64()? as u32,
+            position["character"].as_u64()? as u32,
+        ))
+    };
+    let mut edits: Vec<_> = edits
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|edit| {
+            Some(TextEdit {
+                start: position(&edit["range"]["start"])?,
+                end: position(&edit["range"]["end"])?,
+                new_text: edit["newText"].as_str()?.to_string(),
+            })
+        })
+        .collect();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.start));
+    edits
+}
+
+/// Applies a `WorkspaceEdit`, editing the buffer for the current file and writing
+/// other files directly. Returns the number of files changed.
+fn apply_workspace_edit(state: &mut State, edit: &Value) -> usize {
+    let mut changes: Vec<(String, &Value)> = Vec::new();
+    if let Some(map) = edit["changes"].as_object() {
+        changes.extend(map.iter().map(|(uri, edits)| (uri.clone(), edits)));
+    }
+    for change in edit["documentChanges"].as_array().into_iter().flatten() {
+        if let Some(uri) = change["textDocument"]["uri"].as_str() {
+            changes.push((uri.to_string(), &change["edits"]));
+        }
+    }
+
+    let mut files = 0;
+    for (uri, edits) in changes {
+        let Some(path) = uri_to_path(&uri) else {
+            continue;
+        };
+        let edits = text_edits(edits);
+        if path == state.current_file_path {
+            apply_buffer_edits(state, edits);
+            files += 1;
+        } else if apply_file_edits(&path, edits).is_ok() {
+            files += 1;
+        } else {
+            state
+                .toast_overlay
+                .add_toast(Toast::new(&format!("Couldn't edit {}", path.display())));
+        }
+    }
+    files
+}
+
+fn apply_buffer_edits(state: &State, edits: Vec<TextEdit>) {
+    let buffer = &state.buffer;
+    buffer.begin_user_action();
+    for edit in edits {
+        if let (Some(mut start), Some(mut end)) = (
+            buffer_iter(buffer, edit.start),
+            buffer_iter(buffer, edit.end),
+        ) {
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &edit.new_text);
+        }
+    }
+    buffer.end_user_action();
+}
+
+fn apply_file_edits(path: &Path, edits: Vec<TextEdit>) -> std::io::Result<()> {
+    let mut text = std::fs::read_to_string(path)?;
+    for edit in edits {
+        let start = byte_offset(&text, edit.start);
+        let end = byte_offset(&text, edit.end).max(start);
+        text.replace_range(start..end, &edit.new_text);
+    }
+    std::fs::write(path, text)
+}
@@ -0,0 +1,387 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+    time::Duration,
+};
+
+use gtk4::{
+    glib::{self, ControlFlow, SourceId, clone},
+    prelude::*,
+};
+use libadwaita::{Toast, ToastOverlay};
+use serde_json::json;
+use sourceview5::prelude::*;
+
+use crate::{
+    app::model::{Msg, State},
+    lsp::{
+        client::{LspClient, ServerEvent, path_to_uri},
+        config::{LspConfig, LspServerConfig, load_lsp_config},
+        diagnostics::{
+            Diagnostic, setup_diagnostic_marks, setup_diagnostic_tags, show_diagnostics,
+        },
+        providers::{LspCompletionProvider, LspHoverProvider},
+    },
+    util::problems::refresh_problems,
+};
+
+/// The buffer's file, as the language server knows it.
+#[derive(Debug, Clone)]
+pub struct OpenDocument {
+    /// Key of the server in `LspManager::clients`
+    pub server: String,
+    pub uri: String,
+    pub version: i32,
+}
+
+/// Keeps track of the running language servers and what they've been told about the buffer.
+#[derive(Debug)]
+pub struct LspManager {
+    pub config: LspConfig,
+    /// Running servers keyed by their command line, so languages sharing a server share the process
+    pub clients: HashMap<String, LspClient>,
+    /// Servers that couldn't be started or that exited, so they aren't started again
+    pub failed: HashSet<String>,
+    pub document: Option<OpenDocument>,
+    /// Latest diagnostics keyed by document uri
+    pub diagnostics: HashMap<String, Vec<Diagnostic>>,
+    /// Set when the buffer changed since the server was last sent its text
+    pub dirty: Rc<Cell<bool>>,
+    pub sender: relm4::Sender<Msg>,
+}
+
+pub fn setup_lsp(
+    editor: &sourceview5::View,
+    buffer: &sourceview5::Buffer,
+    sender: &relm4::ComponentSender<State>,
+    toast_overlay: &ToastOverlay,
+) -> LspManager {
+    let dirty: Rc<Cell<bool>> = Rc::default();
+    attach_lsp(editor, buffer, &dirty, sender);
+
+    LspManager {
+        config: load_lsp_config(toast_overlay),
+        clients: HashMap::new(),
+        failed: HashSet::new(),
+        document: None,
+        diagnostics: HashMap::new(),
+        dirty,
+        sender: sender.input_sender().clone(),
+    }
+}
+
+/// Adds completion, hover and diagnostics from the language servers to an editor, and
+/// marks `dirty` when its buffer changes.
+pub fn attach_lsp(
+    editor: &sourceview5::View,
+    buffer: &sourceview5::Buffer,
+    dirty: &Rc<Cell<bool>>,
+    sender: &relm4::ComponentSender<State>,
+) {
+    attach_lsp_view(editor, sender);
+    watch_lsp_buffer(buffer, dirty, sender);
+}
+
+/// Adds completion, hover and the diagnostic icons from the language servers to an editor.
+pub fn attach_lsp_view(editor: &sourceview5::View, sender: &relm4::ComponentSender<State>) {
+    editor
+        .completion()
+        .add_provider(&LspCompletionProvider::new(sender.input_sender().clone()));
+    editor
+        .hover()
+        .add_provider(&LspHoverProvider::new(sender.input_sender().clone()));
+    setup_diagnostic_marks(editor);
+}
+
+/// Sets up a buffer to show diagnostics, and marks `dirty` when it changes.
+pub fn watch_lsp_buffer(
+    buffer: &sourceview5::Buffer,
+    dirty: &Rc<Cell<bool>>,
+    sender: &relm4::ComponentSender<State>,
+) {
+    setup_diagnostic_tags(buffer);
+
+    // Send the new text to the server after a pause in typing
+    let pending: Rc<RefCell<Option<SourceId>>> = Rc::default();
+    buffer.connect_changed(clone!(
+        #[strong]
+        sender,
+        #[strong]
+        dirty,
+        move |_| {
+            dirty.set(true);
+            if let Some(source) = pending.borrow_mut().take() {
+                source.remove();
+            }
+            *pending.borrow_mut() = Some(glib::timeout_add_local(
+                Duration::from_millis(300),
+                clone!(
+                    #[strong]
+                    sender,
+                    #[strong]
+                    pending,
+                    move || {
+                        pending.borrow_mut().take();
+                        sender.input(Msg::LspSync);
+                        ControlFlow::Break
+                    }
+                ),
+            ));
+        }
+    ));
+}
+
+fn server_key(config: &LspServerConfig) -> String {
+    std::iter::once(config.command.as_str())
+        .chain(config.args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Maps sourceview language ids to the ids the protocol expects, where they differ.
+fn protocol_language_id(language_id: &str) -> &str {
+    match language_id {
+        "js" => "javascript",
+        "python3" => "python",
+        "sh" => "shellscript",
+        other => other,
+    }
+}
+
+/// The open folder if the file is inside it, otherwise the file's own folder.
+fn workspace_root(state: &State) -> PathBuf {
+    if !state.current_folder_path.as_os_str().is_empty()
+        && state
+            .current_file_path
+            .starts_with(&state.current_folder_path)
+    {
+        return state.current_folder_path.clone();
+    }
+    state
+        .current_file_path
+        .parent()
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default()
+}
+
+fn start_server(state: &mut State, key: &str, config: &LspServerConfig) -> bool {
+    if state.lsp.clients.contains_key(key) {
+        return true;
+    }
+    if state.lsp.failed.contains(key) {
+        return false;
+    }
+    // The reader thread can't hold the component's sender, so events take a detour
+    let (event_sender, event_receiver) = relm4::channel::<ServerEvent>();
+    match LspClient::spawn(config, &workspace_root(state), move |event| {
+        event_sender.emit(event)
+    }) {
+        Ok(client) => {
+            let sender = state.lsp.sender.clone();
+            let server = key.to_string();
+            relm4::spawn_local(async move {
+                while let Some(event) = event_receiver.recv().await {
+                    sender.emit(Msg::LspEvent(server.clone(), event));
+                }
+            });
+            state.lsp.clients.insert(key.to_string(), client);
+            true
+        }
+        Err(_) => {
+            state.lsp.failed.insert(key.to_string());
+            state.toast_overlay.add_toast(Toast::new(&format!(
+                "Couldn't start language server \"{}\"",
+                config.command
+            )));
+            false
+        }
+    }
+}
+
+/// Tells the language server for the buffer's language about the newly loaded file,
+/// starting the server if it isn't running yet.
+pub(crate) fn open_document(state: &mut State) {
+    close_document(state);
+    let Some(language) = state.buffer.language() else {
+        return;
+    };
+    let language_id = language.id().to_string();
+    let Some(config) = state.lsp.config.get(&language_id).cloned() else {
+        return;
+    };
+    let key = server_key(&config);
+    if !start_server(state, &key, &config) {
+        return;
+    }
+    let uri = path_to_uri(&state.current_file_path);
+    let text = buffer_text(&state.buffer);
+    if let Some(client) = state.lsp.clients.get_mut(&key) {
+        _ = client.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": protocol_language_id(&language_id),
+                    "version": 1,
+                    "text": text,
+                },
+            }),
+        );
+    }
+    state.lsp.document = Some(OpenDocument {
+        server: key,
+        uri,
+        version: 1,
+    });
+    state.lsp.dirty.set(false);
+    show_diagnostics(state);
+    refresh_problems(state);
+}
+
+pub(crate) fn close_document(state: &mut State) {
+    if let Some(document) = state.lsp.document.take()
+        && let Some(client) = state.lsp.clients.get_mut(&document.server)
+    {
+        _ = client.notify(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": document.uri } }),
+        );
+    }
+    show_diagnostics(state);
+    refresh_problems(state);
+}
+
+/// Sends the buffer's text to the server if it changed since it was last sent.
+pub(crate) fn flush_changes(state: &mut State) {
+    if !state.lsp.dirty.replace(false) {
+        return;
+    }
+    let text = buffer_text(&state.buffer);
+    if let Some(document) = &mut state.lsp.document
+        && let Some(client) = state.lsp.clients.get_mut(&document.server)
+    {
+        document.version += 1;
+        _ = client.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": document.uri, "version": document.version },
+                "contentChanges": [{ "text": text }],
+            }),
+        );
+    }
+}
+
+pub(crate) fn did_save(state: &mut State) {
+    flush_changes(state);
+    if let Some(document) = &state.lsp.document
+        && let Some(client) = state.lsp.clients.get_mut(&document.server)
+    {
+        _ = client.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": document.uri } }),
+        );
+    }
+}
+
+/// The open document's server, if it's running and supports `capability`.
+pub(crate) fn document_client<'a>(
+    state: &'a mut State,
+    capability: &str,
+) -> Option<(&'a OpenDocument, &'a mut LspClient)> {
+    let document = state.lsp.document.as_ref()?;
+    let client = state.lsp.clients.get_mut(&document.server)?;
+    client.supports(capability).then_some((document, client))
+}
+
+pub fn buffer_text(buffer: &sourceview5::Buffer) -> String {
+    buffer
+        .text(&buffer.start_iter(), &buffer.end_iter(), true)
+        .to_string()
+}
+
+/// The text of a zero based line, without the line break.
+pub fn line_text(buffer: &sourceview5::Buffer, line: i32) -> String {
+    let Some(start) = buffer.iter_at_line(line) else {
+        return String::new();
+    };
+    let mut end = start;
+    if !end.ends_line() {
+        end.forward_to_line_end();
+    }
+    buffer.text(&start, &end, true).to_string()
+}
+
+/// Protocol positions count UTF-16 code units, the buffer counts characters.
+pub fn utf16_column(line: &str, column: i32) -> u32 {
+    line.chars()
+        .take(column.max(0) as usize)
+        .map(char::len_utf16)
+        .sum::<usize>() as u32
+}
+
+pub fn char_column(line: &str, utf16_column: u32) -> i32 {
+    let mut units = 0;
+    line.chars()
+        .take_while(|c| {
+            units += c.len_utf16() as u32;
+            units <= utf16_column
+        })
+        .count() as i32
+}
+
+/// The protocol position of a zero based line and character column in the buffer.
+pub fn protocol_position(
+    buffer: &sourceview5::Buffer,
+    line: i32,
+    column: i32,
+) -> serde_json::Value {
+    json!({ "line": line, "character": utf16_column(&line_text(buffer, line), column) })
+}
+
+/// The buffer position of a protocol (line, UTF-16 column) pair.
+pub fn buffer_iter(
+    buffer: &sourceview5::Buffer,
+    (line, column): (u32, u32),
+) -> Option<gtk4::TextIter> {
+    let column = char_column(&line_text(buffer, line as i32), column);
+    buffer
+        .iter_at_line_offset(line as i32, column)
+        .or_else(|| buffer.iter_at_line(line as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{char_column, utf16_column};
+
+    #[test]
+    fn counts_utf16_columns() {
+        assert_eq!(utf16_column("let x = 1;", 4), 4);
+        // "é" is one unit, "😀" two
+        assert_eq!(utf16_column("é😀x", 1), 1);
+        assert_eq!(utf16_column("é😀x", 2), 3);
+        assert_eq!(utf16_column("é😀x", 3), 4);
+        assert_eq!(utf16_column("ab", 10), 2);
+        assert_eq!(utf16_column("ab", -1), 0);
+    }
+
+    #[test]
+    fn counts_char_columns() {
+        assert_eq!(char_column("let x = 1;", 4), 4);
+        assert_eq!(char_column("é😀x", 1), 1);
+        assert_eq!(char_column("é😀x", 3), 2);
+        // A column inside a surrogate pair stays before the character
+        assert_eq!(char_column("é😀x", 2), 1);
+        assert_eq!(char_column("ab", 10), 2);
+    }
+
+    #[test]
+    fn converts_columns_both_ways() {
+        let line = "fn 😀(ü: &str) -> 𝔘 {";
+        for column in 0..line.chars().count() as i32 {
+            assert_eq!(char_column(line, utf16_column(line, column)), column);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::{cell::OnceCell, future::Future, pin::Pin};
+
+use gtk4::{
+    CustomFilter, FilterChange, FilterListModel, gio,
+    glib::{self, clone},
+    prelude::*,
+    subclass::prelude::*,
+};
+use relm4::gtk;
+use sourceview5::{
+    CompletionCell, CompletionColumn, CompletionContext, CompletionProposal, HoverContext,
+    HoverDisplay, subclass::prelude::*,
+};
+
+use crate::{
+    app::model::Msg,
+    lsp::{client::CompletionEntry, manager::buffer_iter},
+};
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct LspProposal {
+        pub entry: OnceCell<CompletionEntry>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LspProposal {
+        const NAME: &'static str = "CryptumLspProposal";
+        type Type = super::LspProposal;
+        type Interfaces = (CompletionProposal,);
+    }
+
+    impl ObjectImpl for LspProposal {}
+    impl CompletionProposalImpl for LspProposal {}
+
+    #[derive(Default)]
+    pub struct LspCompletionProvider {
+        pub sender: OnceCell<relm4::Sender<Msg>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LspCompletionProvider {
+        const NAME: &'static str = "CryptumLspCompletionProvider";
+        type Type = super::LspCompletionProvider;
+        type Interfaces = (sourceview5::CompletionProvider,);
+    }
+
+    impl ObjectImpl for LspCompletionProvider {}
+
+    impl CompletionProviderImpl for LspCompletionProvider {
+        fn title(&self) -> Option<glib::GString> {
+            Some("Language Server".into())
+        }
+
+        fn priority(&self, _context: &CompletionContext) -> i32 {
+            // Ahead of word and snippet completion
+            100
+        }
+
+        fn is_trigger(&self, _iter: &gtk::TextIter, c: char) -> bool {
+            matches!(c, '.' | ':' | '>')
+        }
+
+        fn populate_future(
+            &self,
+            context: &CompletionContext,
+        ) -> Pin<Box<dyn Future<Output = Result<gio::ListModel, glib::Error>>>> {
+            let sender = self.sender.get().cloned();
+            let context = context.clone();
+            Box::pin(async move {
+                let (Some(sender), Some((_, end))) = (sender, context.bounds()) else {
+                    return Err(glib::Error::new(
+                        gio::IOErrorEnum::NotSupported,
+                        "No language server",
+                    ));
+                };
+                let (reply_sender, reply_receiver) = relm4::channel();
+                sender.emit(Msg::LspCompletion(
+                    end.line(),
+                    end.line_offset(),
+                    reply_sender,
+                ));
+                let store = gio::ListStore::new::<super::LspProposal>();
+                for entry in reply_receiver.recv().await.unwrap_or_default() {
+                    store.append(&super::LspProposal::new(entry));
+                }
+                // Narrow the results down as the word under the cursor grows
+                let filter = CustomFilter::new(clone!(
+                    #[weak]
+                    context,
+                    #[upgrade_or]
+                    true,
+                    move |item| {
+                        let word = context.word().to_lowercase();
+                        item.downcast_ref::<super::LspProposal>()
+                            .is_some_and(|proposal| {
+                                proposal.entry().label.to_lowercase().contains(&word)
+                            })
+                    }
+                ));
+                Ok(FilterListModel::new(Some(store), Some(filter)).upcast())
+            })
+        }
+
+        fn refilter(&self, _context: &CompletionContext, model: &gio::ListModel) {
+            if let Some(filter) = model
+                .downcast_ref::<FilterListModel>()
+                .and_then(|model| model.filter())
+            {
+                filter.changed(FilterChange::Different);
+            }
+        }
+
+        fn display(
+            &self,
+            _context: &CompletionContext,
+            proposal: &CompletionProposal,
+            cell: &CompletionCell,
+        ) {
+            let Some(proposal) = proposal.downcast_ref::<super::LspProposal>() else {
+                return;
+            };
+            let entry = proposal.entry();
+            match cell.column() {
+                CompletionColumn::TypedText => cell.set_text(Some(&entry.label)),
+                CompletionColumn::After => cell.set_text(Some(&entry.detail)),
+                CompletionColumn::Details => cell.set_text(Some(&entry.documentation)),
+                _ => cell.set_text(None),
+            }
+        }
+
+        fn activate(&self, context: &CompletionContext, proposal: &CompletionProposal) {
+            if let Some(proposal) = proposal.downcast_ref::<super::LspProposal>()
+                && let Some(buffer) = context.buffer()
+                && let Some((mut start, mut end)) = context.bounds()
+            {
+                let entry = proposal.entry();
+                // The server's range can start before the typed word, e.g. to replace a `.`
+                if let Some((edit_start, edit_end)) = entry.range
+                    && let (Some(edit_start), Some(edit_end)) = (
+                        buffer_iter(&buffer, edit_start),
+                        buffer_iter(&buffer, edit_end),
+                    )
+                {
+                    start = edit_start;
+                    // Anything typed since the request is replaced too
+                    if edit_end.offset() > end.offset() {
+                        end = edit_end;
+                    }
+                }
+                buffer.begin_user_action();
+                buffer.delete(&mut start, &mut end);
+                buffer.insert(&mut start, &entry.insert_text);
+                buffer.end_user_action();
+            }
+        }
+    }
+
+    #[derive(Default)]
+    pub struct LspHoverProvider {
+        pub sender: OnceCell<relm4::Sender<Msg>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LspHoverProvider {
+        const NAME: &'static str = "CryptumLspHoverProvider";
+        type Type = super::LspHoverProvider;
+        type Interfaces = (sourceview5::HoverProvider,);
+    }
+
+    impl ObjectImpl for LspHoverProvider {}
+
+    impl HoverProviderImpl for LspHoverProvider {
+        fn populate_future(
+            &self,
+            context: &HoverContext,
+            display: &HoverDisplay,
+        ) -> Pin<Box<dyn Future<Output = Result<(), glib::Error>> + 'static>> {
+            let sender = self.sender.get().cloned();
+            let iter = context.iter();
+            let display = display.clone();
+            Box::pin(async move {
+                let nothing = || glib::Error::new(gio::IOErrorEnum::NotFound, "Nothing to show");
+                let (Some(sender), Some(iter)) = (sender, iter) else {
+                    return Err(nothing());
+                };
+                let (reply_sender, reply_receiver) = relm4::channel();
+                sender.emit(Msg::LspHover(iter.line(), iter.line_offset(), reply_sender));
+                let Some(Some(text)) = reply_receiver.recv().await else {
+                    return Err(nothing());
+                };
+                display.append(
+                    &gtk::Label::builder()
+                        .label(text)
+                        .wrap(true)
+                        .max_width_chars(80)
+                        .selectable(true)
+                        .xalign(0.0)
+                        .build(),
+                );
+                Ok(())
+            })
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct LspProposal(ObjectSubclass<imp::LspProposal>)
+        @implements CompletionProposal;
+}
+
+impl LspProposal {
+    pub fn new(entry: CompletionEntry) -> Self {
+        let proposal: Self = glib::Object::new();
+        _ = proposal.imp().entry.set(entry);
+        proposal
+    }
+
+    pub fn entry(&self) -> &CompletionEntry {
+        self.imp().entry.get().unwrap()
+    }
+}
+
+glib::wrapper! {
+    /// Completion proposals from the language server for the current buffer.
+    pub struct LspCompletionProvider(ObjectSubclass<imp::LspCompletionProvider>)
+        @implements sourceview5::CompletionProvider;
+}
+
+impl LspCompletionProvider {
+    pub fn new(sender: relm4::Sender<Msg>) -> Self {
+        let provider: Self = glib::Object::new();
+        _ = provider.imp().sender.set(sender);
+        provider
+    }
+}
+
+glib::wrapper! {
+    /// Hover popovers with the language server's hover text and any diagnostics.
+    pub struct LspHoverProvider(ObjectSubclass<imp::LspHoverProvider>)
+        @implements sourceview5::HoverProvider;
+}
+
+impl LspHoverProvider {
+    pub fn new(sender: relm4::Sender<Msg>) -> Self {
+        let provider: Self = glib::Object::new();
+        _ = provider.imp().sender.set(sender);
+        provider
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Writes a JSON-RPC message with the `Content-Length` header LSP expects.
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Reads the next JSON-RPC message, returning `None` once the stream is closed.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(content_length) = content_length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Message is missing a Content-Length header",
+        ));
+    };
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::json;
+
+    use super::{read_message, write_message};
+
+    #[test]
+    fn round_trips_messages() {
+        let first = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" });
+        let second = json!({ "jsonrpc": "2.0", "method": "note", "params": ["ü→😀"] });
+        let mut stream = Vec::new();
+        write_message(&mut stream, &first).unwrap();
+        write_message(&mut stream, &second).unwrap();
+
+        let mut reader = Cursor::new(stream);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(first));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(second));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn counts_the_length_in_bytes() {
+        let mut stream = Vec::new();
+        write_message(&mut stream, &json!("😀")).unwrap();
+        assert!(stream.starts_with(b"Content-Length: 6\r\n\r\n"));
+    }
+
+    #[test]
+    fn reads_other_headers() {
+        let body = r#"{"id":3}"#;
+        let stream = format!(
+            "content-length: {}\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{body}",
+            body.len()
+        );
+        assert_eq!(
+            read_message(&mut Cursor::new(stream)).unwrap(),
+            Some(json!({ "id": 3 }))
+        );
+    }
+
+    #[test]
+    fn fails_without_a_length() {
+        let stream = "Content-Type: application/json\r\n\r\n{}";
+        assert!(read_message(&mut Cursor::new(stream)).is_err());
+    }
+
+    #[test]
+    fn fails_on_a_truncated_body() {
+        let stream = "Content-Length: 20\r\n\r\n{}";
+        assert!(read_message(&mut Cursor::new(stream)).is_err());
+    }
+}
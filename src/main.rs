@@ -1,12 +1,12 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, rc::Rc};
 
 use gtk4::{MenuButton, ScrolledWindow};
 use libadwaita::{
-    prelude::*, HeaderBar, NavigationPage, OverlaySplitView, ToastOverlay,
-    WindowTitle,
+    prelude::*, HeaderBar, NavigationPage, OverlaySplitView, ToastOverlay, ViewStack,
+    ViewSwitcher, ViewSwitcherPolicy, WindowTitle,
 };
 use relm4::{
-    actions::{AccelsPlus, RelmAction, RelmActionGroup},
+    actions::{ActionGroupName, RelmAction, RelmActionGroup},
     gtk::glib::clone,
     prelude::*,
 };
@@ -18,14 +18,42 @@ use sourceview5::LanguageManager;
 
 mod app;
 use app::{
-    model::{Msg, State, WidgetStruct},
+    model::{IndentSettings, Msg, State, WidgetStruct},
     update::handle_messages,
     view::handle_view,
 };
 
 mod util;
-use util::{menu::menu_bar, widget::setup_editor};
+use util::{
+    brackets::{load_brackets_config, setup_auto_close},
+    completion::setup_completion,
+    folding::setup_folding,
+    format::load_formatters_config,
+    goto::setup_go_to_line,
+    indentation::setup_indentation,
+    keybindings::{apply_keybindings, load_keybindings},
+    keymap::setup_keymap,
+    lines::SortOptions,
+    markdown::setup_markdown_preview,
+    menu::menu_bar,
+    multi_cursor::setup_multi_cursor,
+    outline::setup_outline,
+    picker::setup_picker,
+    problems::setup_problems,
+    project_search::setup_project_search,
+    search::setup_find_bar,
+    snippets::setup_snippets,
+    split::setup_splits,
+    tasks::setup_tasks,
+    terminal::setup_terminal,
+    transform::Transform,
+    whitespace::{WhitespaceOptions, default_language_whitespace},
+    widget::setup_editor,
+};
 mod fs;
+use fs::{editorconfig::EditorConfig, session::load_session};
+mod lsp;
+use lsp::manager::setup_lsp;
 
 impl SimpleComponent for State {
     type Init = PathBuf;
@@ -69,6 +97,13 @@ impl SimpleComponent for State {
                 OpenDialogResponse::Accept(path) => Msg::OpenResponse(path),
                 OpenDialogResponse::Cancel => Msg::Ignore,
             });
+        let snippet_import_dialog = OpenDialog::builder()
+            .transient_for_native(&root)
+            .launch(OpenDialogSettings::default())
+            .forward(sender.input_sender(), |response| match response {
+                OpenDialogResponse::Accept(path) => Msg::ImportSnippetsResponse(path),
+                OpenDialogResponse::Cancel => Msg::Ignore,
+            });
         let save_as_dialog = SaveDialog::builder()
             .transient_for_native(&root)
             .launch(SaveDialogSettings::default())
@@ -87,7 +122,6 @@ impl SimpleComponent for State {
         header.pack_end(&hamburger);
         let sidebar_button = gtk::Button::builder().icon_name("sidebar-show-symbolic").build();
         header.pack_start(&sidebar_button);
-        let sidebar_header = HeaderBar::builder().build();
         let file_tree = gtk::ListView::builder()
             .css_classes(vec!["navigation-sidebar"])
             .vexpand(true)
@@ -98,6 +132,8 @@ impl SimpleComponent for State {
             .hscrollbar_policy(gtk4::PolicyType::Never)
             .child(&file_tree)
             .build();
+        let project_search = setup_project_search(&sender);
+        let picker = setup_picker(&sender);
         let language_manager = LanguageManager::builder().build();
         let buffer_style = sourceview5::StyleSchemeManager::new().scheme("Adwaita-dark");
         let buffer = sourceview5::Buffer::builder()
@@ -105,14 +141,62 @@ impl SimpleComponent for State {
             .highlight_matching_brackets(true)
             .build();
         let editor = setup_editor(&buffer);
+        let completion = setup_completion(&editor);
+        let multi_cursor = setup_multi_cursor(&editor, &buffer);
+        let toast_overlay = ToastOverlay::new();
+        let brackets = Rc::new(load_brackets_config(&toast_overlay));
+        setup_auto_close(&editor, &brackets);
+        let folding = setup_folding(&editor, &buffer, &sender);
+        let keymap = setup_keymap();
+        let snippets = setup_snippets(&editor, &sender);
         let mini_map = sourceview5::Map::builder()
             .width_request(120)
             .overflow(gtk4::Overflow::Visible)
             .view(&editor)
             .build();
+        let markdown_preview = setup_markdown_preview(&editor, &buffer, &sender);
         let file_type_label = gtk::Label::builder().halign(gtk4::Align::Start).build();
-        let cursor_position_label = gtk::Label::builder().halign(gtk4::Align::End).build();
-        let toast_overlay = ToastOverlay::new();
+        let cursor_position_label = gtk::Label::builder()
+            .halign(gtk4::Align::End)
+            .tooltip_text("Go to Line (Ctrl+L)")
+            .build();
+        let editorconfig_label = gtk::Label::builder()
+            .label("EditorConfig")
+            .visible(false)
+            .build();
+        let indentation = setup_indentation(&sender);
+        let go_to_line = setup_go_to_line(&cursor_position_label, &sender);
+        let find_bar = setup_find_bar(&buffer, &sender);
+        let outline = setup_outline(&buffer, &sender);
+        let formatters = load_formatters_config(&toast_overlay);
+        let lsp = setup_lsp(&editor, &buffer, &sender, &toast_overlay);
+        let problems = setup_problems(&sender);
+        let task_runner = setup_tasks(&sender);
+        let terminal = setup_terminal(&sender);
+        let sidebar_stack = ViewStack::new();
+        sidebar_stack.add_titled_with_icon(
+            &file_tree_scroll,
+            Some("files"),
+            "Files",
+            "folder-symbolic",
+        );
+        sidebar_stack.add_titled_with_icon(
+            &project_search.container,
+            Some("search"),
+            "Search",
+            "system-search-symbolic",
+        );
+        sidebar_stack.add_titled_with_icon(
+            &outline.container,
+            Some("outline"),
+            "Outline",
+            "view-list-symbolic",
+        );
+        let sidebar_switcher = ViewSwitcher::builder()
+            .stack(&sidebar_stack)
+            .policy(ViewSwitcherPolicy::Wide)
+            .build();
+        let sidebar_header = HeaderBar::builder().title_widget(&sidebar_switcher).build();
 
         // Define containers
         let main_box = gtk::Box::builder()
@@ -138,14 +222,23 @@ impl SimpleComponent for State {
 
         // Add widgets to containers
         editor_scroll_window.set_child(Some(&editor));
+        status_bar_box.append(&keymap.indicator);
         status_bar_box.append(&file_type_label);
+        status_bar_box.append(&editorconfig_label);
+        status_bar_box.append(&indentation.button);
+        status_bar_box.append(&problems.summary_button);
         status_bar_box.append(&cursor_position_label);
         file_tree_box.append(&sidebar_header);
-        file_tree_box.append(&file_tree_scroll);
+        file_tree_box.append(&sidebar_stack);
         toast_overlay.set_child(Some(&editor_box_vertical));
-        editor_box_horizontal.append(&editor_scroll_window);
-        editor_box_horizontal.append(&mini_map);
+        let splits = setup_splits(&editor, &editor_scroll_window, &mini_map, &sender);
+        editor_box_horizontal.append(&splits.area);
+        editor_box_horizontal.append(&markdown_preview.container);
+        editor_box_vertical.append(&find_bar.search_bar);
         editor_box_vertical.append(&editor_box_horizontal);
+        editor_box_vertical.append(&problems.revealer);
+        editor_box_vertical.append(&task_runner.revealer);
+        editor_box_vertical.append(&terminal.revealer);
         editor_box_vertical.append(&status_bar_box);
         main_box.append(&header);
         main_box.append(&toast_overlay);
@@ -187,21 +280,8 @@ impl SimpleComponent for State {
         ));
 
         // Setup actions
-        let program = relm4::main_application();
-        // File accelerators
-        program.set_accelerators_for_action::<NewFileAction>(&["<control><shift>n"]);
-        program.set_accelerators_for_action::<OpenAction>(&["<control>o"]);
-        program.set_accelerators_for_action::<OpenFolderAction>(&["<control><shift>o"]);
-        program.set_accelerators_for_action::<SaveAction>(&["<control>s"]);
-        program.set_accelerators_for_action::<SaveAsAction>(&["<control><shift>s"]);
-        // View accelerators
-        program.set_accelerators_for_action::<ToggleFileListAction>(&["<control><alt>f"]);
-        program.set_accelerators_for_action::<ToggleHiddenFilesAction>(&["<control>h"]);
-        program.set_accelerators_for_action::<ToggleMiniMapAction>(&["<control><alt>m"]);
-        program.set_accelerators_for_action::<ToggleFullscreenAction>(&["F11"]);
-        // About accelerators
-        program.set_accelerators_for_action::<ShowPreferencesAction>(&["<control>comma"]);
-        program.set_accelerators_for_action::<ShowKeyboardShortcutsAction>(&["<control>question"]);
+        let keybindings = load_keybindings(&toast_overlay);
+        apply_keybindings(&keybindings);
 
         // Create action groups and add actions to them
         let mut file_action_group = RelmActionGroup::<FileActionGroup>::new();
@@ -234,12 +314,318 @@ impl SimpleComponent for State {
             sender,
             move |_| sender.input(Msg::FolderRequest)
         )));
+        file_action_group.add_action(RelmAction::<QuickOpenAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::QuickOpen)
+        )));
+        file_action_group.add_action(RelmAction::<ImportSnippetsAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ImportSnippetsRequest)
+        )));
+        file_action_group.add_action(RelmAction::<RunTaskAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ShowTaskPicker)
+        )));
+        file_action_group.add_action(RelmAction::<RerunTaskAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::RerunTask)
+        )));
+        file_action_group.add_action(RelmAction::<CancelTaskAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::CancelTask)
+        )));
+        file_action_group.add_action(RelmAction::<ExportHtmlAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ExportHtml(false))
+        )));
         // Edit actions
         edit_action_group.add_action(RelmAction::<ClearAction>::new_stateless(clone!(
             #[strong]
             sender,
             move |_| sender.input(Msg::ClearEditor)
         )));
+        edit_action_group.add_action(RelmAction::<FindAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ShowFind)
+        )));
+        edit_action_group.add_action(RelmAction::<ReplaceAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ShowReplace)
+        )));
+        edit_action_group.add_action(RelmAction::<FindNextAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::FindNext)
+        )));
+        edit_action_group.add_action(RelmAction::<FindPreviousAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::FindPrevious)
+        )));
+        edit_action_group.add_action(RelmAction::<FindInProjectAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ShowProjectSearch)
+        )));
+        edit_action_group.add_action(RelmAction::<GoToLineAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ShowGoToLine)
+        )));
+        edit_action_group.add_action(RelmAction::<GoToSymbolAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ShowSymbolPicker)
+        )));
+        edit_action_group.add_action(RelmAction::<GoToDefinitionAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::GoToDefinition)
+        )));
+        edit_action_group.add_action(RelmAction::<FindReferencesAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::FindReferences)
+        )));
+        edit_action_group.add_action(RelmAction::<RenameSymbolAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ShowRename)
+        )));
+        edit_action_group.add_action(RelmAction::<NextProblemAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::NextProblem)
+        )));
+        edit_action_group.add_action(RelmAction::<PreviousProblemAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::PreviousProblem)
+        )));
+        edit_action_group.add_action(RelmAction::<FormatDocumentAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::FormatDocument)
+        )));
+        edit_action_group.add_action(RelmAction::<JumpToBracketAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::JumpToBracket)
+        )));
+        edit_action_group.add_action(RelmAction::<SelectToBracketAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::SelectToBracket)
+        )));
+        edit_action_group.add_action(RelmAction::<AddNextOccurrenceAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::AddNextOccurrence)
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<MoveLinesUpAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::MoveLines(true))
+        )));
+        edit_action_group.add_action(RelmAction::<MoveLinesDownAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::MoveLines(false))
+        )));
+        edit_action_group.add_action(RelmAction::<DuplicateLinesAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::DuplicateLines)
+        )));
+        edit_action_group.add_action(RelmAction::<DeleteLinesAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::DeleteLines)
+        )));
+        edit_action_group.add_action(RelmAction::<JoinLinesAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::JoinLines)
+        )));
+        edit_action_group.add_action(RelmAction::<SortLinesAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::SortLines(SortOptions::default()))
+        )));
+        edit_action_group.add_action(RelmAction::<SortLinesIgnoreCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::SortLines(SortOptions {
+                    ignore_case: true,
+                    ..Default::default()
+                }))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<SortLinesNumericAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::SortLines(SortOptions {
+                numeric: true,
+                ..Default::default()
+            }))
+        )));
+        edit_action_group.add_action(RelmAction::<SortLinesUniqueAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::SortLines(SortOptions {
+                unique: true,
+                ..Default::default()
+            }))
+        )));
+        edit_action_group.add_action(RelmAction::<ReverseLinesAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ReverseLines)
+        )));
+        edit_action_group.add_action(RelmAction::<ToggleLineCommentAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::ToggleLineComment)
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<ToggleBlockCommentAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::ToggleBlockComment)
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformUpperCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::UpperCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformLowerCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::LowerCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformTitleCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::TitleCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformInvertCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::InvertCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformSnakeCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::SnakeCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformCamelCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::CamelCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformKebabCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::KebabCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformPascalCaseAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::PascalCase))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformTrimWhitespaceAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::TrimWhitespace))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformTabsToSpacesAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::TabsToSpaces))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformSpacesToTabsAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::SpacesToTabs))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformUrlEncodeAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::UrlEncode))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformUrlDecodeAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::UrlDecode))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformBase64EncodeAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::Base64Encode))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformBase64DecodeAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::Base64Decode))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformJsonPrettyAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::JsonPretty))
+            ),
+        ));
+        edit_action_group.add_action(RelmAction::<TransformJsonMinifyAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::Transform(Transform::JsonMinify))
+            ),
+        ));
         // View actions
         view_action_group.add_action(RelmAction::<ToggleFileListAction>::new_stateless(clone!(
             #[strong]
@@ -270,6 +656,78 @@ impl SimpleComponent for State {
             sender,
             move |_| sender.input(Msg::ToggleFullscreen)
         )));
+        view_action_group.add_action(RelmAction::<ShowCommandPaletteAction>::new_stateless(
+            clone!(
+                #[strong]
+                sender,
+                move |_| sender.input(Msg::ShowCommandPalette)
+            ),
+        ));
+        view_action_group.add_action(RelmAction::<ToggleProblemsAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ToggleProblems)
+        )));
+        view_action_group.add_action(RelmAction::<ToggleOutputAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ToggleOutput)
+        )));
+        view_action_group.add_action(RelmAction::<ToggleTerminalAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ToggleTerminal)
+        )));
+        view_action_group.add_action(RelmAction::<NewTerminalAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::NewTerminal)
+        )));
+        view_action_group.add_action(RelmAction::<TogglePreviewAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::TogglePreview)
+        )));
+        view_action_group.add_action(RelmAction::<SplitRightAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::SplitEditor(gtk::Orientation::Horizontal))
+        )));
+        view_action_group.add_action(RelmAction::<SplitDownAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::SplitEditor(gtk::Orientation::Vertical))
+        )));
+        view_action_group.add_action(RelmAction::<CloseSplitAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::CloseSplit)
+        )));
+        view_action_group.add_action(RelmAction::<FocusNextSplitAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::FocusNextSplit)
+        )));
+        view_action_group.add_action(RelmAction::<FoldAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::Fold)
+        )));
+        view_action_group.add_action(RelmAction::<UnfoldAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::Unfold)
+        )));
+        view_action_group.add_action(RelmAction::<FoldAllAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::FoldAll)
+        )));
+        view_action_group.add_action(RelmAction::<UnfoldAllAction>::new_stateless(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::UnfoldAll)
+        )));
         // About actions
         about_action_group.add_action(RelmAction::<ShowKeyboardShortcutsAction>::new_stateless(
             clone!(
@@ -289,34 +747,77 @@ impl SimpleComponent for State {
             move |_| sender.input(Msg::ShowAbout)
         )));
 
-        // Register action groups
-        file_action_group.register_for_widget(&root);
-        edit_action_group.register_for_widget(&root);
-        view_action_group.register_for_widget(&root);
-        about_action_group.register_for_widget(&root);
+        // Register action groups, keeping them around so the command palette can list them
+        let action_groups = vec![
+            (FileActionGroup::NAME, file_action_group.into_action_group()),
+            (EditActionGroup::NAME, edit_action_group.into_action_group()),
+            (ViewActionGroup::NAME, view_action_group.into_action_group()),
+            (
+                AboutActionGroup::NAME,
+                about_action_group.into_action_group(),
+            ),
+        ];
+        for (name, group) in &action_groups {
+            root.insert_action_group(name, Some(group));
+        }
 
         let model = State {
             // Containers
             root,
             nav_view: split_view,
+            sidebar_stack,
             // Widgets
             file_view: file_tree,
             editor,
+            completion,
+            multi_cursor,
+            folding,
+            keymap,
+            keybindings,
+            snippets,
             buffer,
             language_manager,
             open_dialog,
             folder_dialog,
+            snippet_import_dialog,
             save_as_dialog,
             title,
             file_type_label,
             cursor_position_label,
+            editorconfig_label,
+            indentation,
             mini_map,
+            splits,
+            markdown_preview,
             toast_overlay,
+            find_bar,
+            project_search,
+            picker,
+            go_to_line,
+            outline,
+            lsp,
+            problems,
+            task_runner,
+            terminal,
+            action_groups,
             // Misc
             current_file_path,
             current_folder_path,
+            file_index: Vec::new(),
+            recent_files: Vec::new(),
             buffer_style,
             view_hidden,
+            formatters,
+            brackets,
+            format_on_save: false,
+            session: load_session(),
+            indent_settings: IndentSettings {
+                use_spaces: true,
+                tab_width: 4,
+            },
+            editorconfig: EditorConfig::default(),
+            whitespace: WhitespaceOptions::default(),
+            whitespace_languages: default_language_whitespace(),
         };
         let widgets = WidgetStruct {};
         ComponentParts { model, widgets }
@@ -341,8 +842,147 @@ relm4::new_stateless_action!(SaveAsAction, FileActionGroup, "save_as");
 relm4::new_stateless_action!(SaveAction, FileActionGroup, "save");
 relm4::new_stateless_action!(OpenAction, FileActionGroup, "open");
 relm4::new_stateless_action!(OpenFolderAction, FileActionGroup, "open_folder");
+relm4::new_stateless_action!(QuickOpenAction, FileActionGroup, "quick_open");
+relm4::new_stateless_action!(ImportSnippetsAction, FileActionGroup, "import_snippets");
+relm4::new_stateless_action!(RunTaskAction, FileActionGroup, "run_task");
+relm4::new_stateless_action!(RerunTaskAction, FileActionGroup, "rerun_task");
+relm4::new_stateless_action!(CancelTaskAction, FileActionGroup, "cancel_task");
+relm4::new_stateless_action!(ExportHtmlAction, FileActionGroup, "export_html");
 // Edit
 relm4::new_stateless_action!(ClearAction, EditActionGroup, "clear");
+relm4::new_stateless_action!(FindAction, EditActionGroup, "find");
+relm4::new_stateless_action!(ReplaceAction, EditActionGroup, "replace");
+relm4::new_stateless_action!(FindNextAction, EditActionGroup, "find_next");
+relm4::new_stateless_action!(FindPreviousAction, EditActionGroup, "find_previous");
+relm4::new_stateless_action!(FindInProjectAction, EditActionGroup, "find_in_project");
+relm4::new_stateless_action!(GoToLineAction, EditActionGroup, "go_to_line");
+relm4::new_stateless_action!(GoToSymbolAction, EditActionGroup, "go_to_symbol");
+relm4::new_stateless_action!(GoToDefinitionAction, EditActionGroup, "go_to_definition");
+relm4::new_stateless_action!(FindReferencesAction, EditActionGroup, "find_references");
+relm4::new_stateless_action!(RenameSymbolAction, EditActionGroup, "rename_symbol");
+relm4::new_stateless_action!(NextProblemAction, EditActionGroup, "next_problem");
+relm4::new_stateless_action!(PreviousProblemAction, EditActionGroup, "previous_problem");
+relm4::new_stateless_action!(FormatDocumentAction, EditActionGroup, "format_document");
+relm4::new_stateless_action!(JumpToBracketAction, EditActionGroup, "jump_to_bracket");
+relm4::new_stateless_action!(SelectToBracketAction, EditActionGroup, "select_to_bracket");
+relm4::new_stateless_action!(
+    AddNextOccurrenceAction,
+    EditActionGroup,
+    "add_next_occurrence"
+);
+relm4::new_stateless_action!(MoveLinesUpAction, EditActionGroup, "move_lines_up");
+relm4::new_stateless_action!(MoveLinesDownAction, EditActionGroup, "move_lines_down");
+relm4::new_stateless_action!(DuplicateLinesAction, EditActionGroup, "duplicate_lines");
+relm4::new_stateless_action!(DeleteLinesAction, EditActionGroup, "delete_lines");
+relm4::new_stateless_action!(JoinLinesAction, EditActionGroup, "join_lines");
+relm4::new_stateless_action!(SortLinesAction, EditActionGroup, "sort_lines");
+relm4::new_stateless_action!(
+    SortLinesIgnoreCaseAction,
+    EditActionGroup,
+    "sort_lines_ignore_case"
+);
+relm4::new_stateless_action!(
+    SortLinesNumericAction,
+    EditActionGroup,
+    "sort_lines_numeric"
+);
+relm4::new_stateless_action!(SortLinesUniqueAction, EditActionGroup, "sort_lines_unique");
+relm4::new_stateless_action!(ReverseLinesAction, EditActionGroup, "reverse_lines");
+relm4::new_stateless_action!(
+    ToggleLineCommentAction,
+    EditActionGroup,
+    "toggle_line_comment"
+);
+relm4::new_stateless_action!(
+    ToggleBlockCommentAction,
+    EditActionGroup,
+    "toggle_block_comment"
+);
+relm4::new_stateless_action!(
+    TransformUpperCaseAction,
+    EditActionGroup,
+    "transform_upper_case"
+);
+relm4::new_stateless_action!(
+    TransformLowerCaseAction,
+    EditActionGroup,
+    "transform_lower_case"
+);
+relm4::new_stateless_action!(
+    TransformTitleCaseAction,
+    EditActionGroup,
+    "transform_title_case"
+);
+relm4::new_stateless_action!(
+    TransformInvertCaseAction,
+    EditActionGroup,
+    "transform_invert_case"
+);
+relm4::new_stateless_action!(
+    TransformSnakeCaseAction,
+    EditActionGroup,
+    "transform_snake_case"
+);
+relm4::new_stateless_action!(
+    TransformCamelCaseAction,
+    EditActionGroup,
+    "transform_camel_case"
+);
+relm4::new_stateless_action!(
+    TransformKebabCaseAction,
+    EditActionGroup,
+    "transform_kebab_case"
+);
+relm4::new_stateless_action!(
+    TransformPascalCaseAction,
+    EditActionGroup,
+    "transform_pascal_case"
+);
+relm4::new_stateless_action!(
+    TransformTrimWhitespaceAction,
+    EditActionGroup,
+    "transform_trim_whitespace"
+);
+relm4::new_stateless_action!(
+    TransformTabsToSpacesAction,
+    EditActionGroup,
+    "transform_tabs_to_spaces"
+);
+relm4::new_stateless_action!(
+    TransformSpacesToTabsAction,
+    EditActionGroup,
+    "transform_spaces_to_tabs"
+);
+relm4::new_stateless_action!(
+    TransformUrlEncodeAction,
+    EditActionGroup,
+    "transform_url_encode"
+);
+relm4::new_stateless_action!(
+    TransformUrlDecodeAction,
+    EditActionGroup,
+    "transform_url_decode"
+);
+relm4::new_stateless_action!(
+    TransformBase64EncodeAction,
+    EditActionGroup,
+    "transform_base64_encode"
+);
+relm4::new_stateless_action!(
+    TransformBase64DecodeAction,
+    EditActionGroup,
+    "transform_base64_decode"
+);
+relm4::new_stateless_action!(
+    TransformJsonPrettyAction,
+    EditActionGroup,
+    "transform_json_pretty"
+);
+relm4::new_stateless_action!(
+    TransformJsonMinifyAction,
+    EditActionGroup,
+    "transform_json_minify"
+);
 // View
 relm4::new_stateless_action!(ToggleFileListAction, ViewActionGroup, "toggle_file_list");
 relm4::new_stateless_action!(
@@ -357,6 +997,28 @@ relm4::new_stateless_action!(
     "toggle_buffer_style_scheme"
 );
 relm4::new_stateless_action!(ToggleFullscreenAction, ViewActionGroup, "toggle_fullscreen");
+relm4::new_stateless_action!(
+    ShowCommandPaletteAction,
+    ViewActionGroup,
+    "show_command_palette"
+);
+relm4::new_stateless_action!(ToggleProblemsAction, ViewActionGroup, "toggle_problems");
+relm4::new_stateless_action!(ToggleOutputAction, ViewActionGroup, "toggle_output");
+relm4::new_stateless_action!(ToggleTerminalAction, ViewActionGroup, "toggle_terminal");
+relm4::new_stateless_action!(NewTerminalAction, ViewActionGroup, "new_terminal");
+relm4::new_stateless_action!(SplitRightAction, ViewActionGroup, "split_right");
+relm4::new_stateless_action!(SplitDownAction, ViewActionGroup, "split_down");
+relm4::new_stateless_action!(CloseSplitAction, ViewActionGroup, "close_split");
+relm4::new_stateless_action!(FocusNextSplitAction, ViewActionGroup, "focus_next_split");
+relm4::new_stateless_action!(FoldAction, ViewActionGroup, "fold");
+relm4::new_stateless_action!(UnfoldAction, ViewActionGroup, "unfold");
+relm4::new_stateless_action!(FoldAllAction, ViewActionGroup, "fold_all");
+relm4::new_stateless_action!(UnfoldAllAction, ViewActionGroup, "unfold_all");
+relm4::new_stateless_action!(
+    TogglePreviewAction,
+    ViewActionGroup,
+    "toggle_markdown_preview"
+);
 // About
 relm4::new_stateless_action!(
     ShowKeyboardShortcutsAction,
@@ -1,3 +1,28 @@
+pub mod brackets;
+pub mod completion;
 pub mod dialogs;
+pub mod folding;
+pub mod format;
+pub mod fuzzy;
+pub mod goto;
+pub mod indentation;
+pub mod keybindings;
+pub mod keymap;
+pub mod lines;
+pub mod markdown;
 pub mod menu;
+pub mod multi_cursor;
+pub mod outline;
+pub mod palette;
+pub mod picker;
+pub mod problems;
+pub mod project_search;
+pub mod search;
+pub mod snippets;
+pub mod split;
+pub mod symbols;
+pub mod tasks;
+pub mod terminal;
+pub mod transform;
+pub mod whitespace;
 pub mod widget;
@@ -0,0 +1,363 @@
+use std::{collections::HashMap, rc::Rc};
+
+use gtk4::{
+    EventControllerKey, PropagationPhase, TextIter,
+    gdk::{Key, ModifierType},
+    glib::{self, Propagation, clone},
+    prelude::*,
+};
+use libadwaita::{Toast, ToastOverlay};
+use serde::{Deserialize, Serialize};
+use sourceview5::prelude::*;
+
+use crate::{
+    app::model::State,
+    fs::settings::load_json_config,
+    util::{keymap::KEYMAP_CLASS, multi_cursor::MULTI_CURSOR_CLASS},
+};
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BracketConfig {
+    /// Opening and closing characters, the same character twice for quotes
+    pub pairs: Vec<(char, char)>,
+    /// Type the closing character along with the opening one
+    #[serde(default = "enabled")]
+    pub auto_close: bool,
+    /// Wrap the selection when an opening character is typed over it
+    #[serde(default = "enabled")]
+    pub surround: bool,
+}
+
+/// Bracket settings keyed by sourceview language id, `default` covering the rest.
+pub type BracketsConfig = HashMap<String, BracketConfig>;
+
+fn brackets(pairs: &str) -> BracketConfig {
+    let characters: Vec<char> = pairs.chars().collect();
+    BracketConfig {
+        pairs: characters
+            .chunks(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect(),
+        auto_close: true,
+        surround: true,
+    }
+}
+
+pub fn default_brackets_config() -> BracketsConfig {
+    HashMap::from([
+        ("default".to_string(), brackets("()[]{}\"\"''")),
+        // Single quotes start lifetimes, and lisps quote with them
+        ("rust".to_string(), brackets("()[]{}\"\"")),
+        ("commonlisp".to_string(), brackets("()[]\"\"")),
+        ("scheme".to_string(), brackets("()[]\"\"")),
+        ("html".to_string(), brackets("()[]{}<>\"\"''")),
+        ("xml".to_string(), brackets("()[]{}<>\"\"''")),
+        ("markdown".to_string(), brackets("()[]``")),
+    ])
+}
+
+/// Loads the bracket config, writing out the defaults if the file doesn't exist yet.
+pub fn load_brackets_config(toast_overlay: &ToastOverlay) -> BracketsConfig {
+    load_json_config(
+        "cryptum-text-brackets.json",
+        default_brackets_config(),
+        toast_overlay,
+    )
+}
+
+/// The settings for the buffer's language.
+fn language_config<'a>(
+    config: &'a BracketsConfig,
+    buffer: &sourceview5::Buffer,
+) -> Option<&'a BracketConfig> {
+    buffer
+        .language()
+        .and_then(|language| config.get(language.id().as_str()))
+        .or_else(|| config.get("default"))
+}
+
+fn char_before(iter: &TextIter) -> Option<char> {
+    let mut before = *iter;
+    before.backward_char().then(|| before.char())
+}
+
+fn char_after(iter: &TextIter) -> Option<char> {
+    (!iter.is_end()).then(|| iter.char())
+}
+
+/// Handles a typed character, returning whether it was dealt with here.
+fn type_character(buffer: &sourceview5::Buffer, config: &BracketConfig, c: char) -> bool {
+    if let Some((start, end)) = buffer.selection_bounds() {
+        let Some(close) = config
+            .pairs
+            .iter()
+            .find_map(|(open, close)| (*open == c).then_some(*close))
+            .filter(|_| config.surround)
+        else {
+            return false;
+        };
+        let (start_offset, end_offset) = (start.offset(), end.offset());
+        buffer.begin_user_action();
+        buffer.insert(&mut buffer.iter_at_offset(end_offset), &close.to_string());
+        buffer.insert(&mut buffer.iter_at_offset(start_offset), &c.to_string());
+        buffer.end_user_action();
+        // Keep the wrapped text selected, so it can be wrapped again
+        buffer.select_range(
+            &buffer.iter_at_offset(start_offset + 1),
+            &buffer.iter_at_offset(end_offset + 1),
+        );
+        return true;
+    }
+    if !config.auto_close {
+        return false;
+    }
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let (before, after) = (char_before(&cursor), char_after(&cursor));
+
+    // Type over a closing character instead of adding another
+    if after == Some(c) && config.pairs.iter().any(|(_, close)| *close == c) {
+        let mut next = cursor;
+        next.forward_char();
+        buffer.place_cursor(&next);
+        return true;
+    }
+    let Some(close) = config
+        .pairs
+        .iter()
+        .find_map(|(open, close)| (*open == c).then_some(*close))
+    else {
+        return false;
+    };
+    // Only close before whitespace or another closer, so typing before a word still works
+    let closes_before = after.is_none_or(|after| {
+        after.is_whitespace() || config.pairs.iter().any(|(_, close)| *close == after)
+    });
+    // A quote after a word is more likely an apostrophe
+    let quote_after_word = c == close && before.is_some_and(|before| before.is_alphanumeric());
+    if !closes_before || quote_after_word {
+        return false;
+    }
+    buffer.begin_user_action();
+    buffer.insert_at_cursor(&format!("{c}{close}"));
+    buffer.end_user_action();
+    let mut between = buffer.iter_at_mark(&buffer.get_insert());
+    between.backward_char();
+    buffer.place_cursor(&between);
+    true
+}
+
+/// Deletes an empty pair at once when backspacing over its opening character.
+fn delete_pair(buffer: &sourceview5::Buffer, config: &BracketConfig) -> bool {
+    if !config.auto_close || buffer.has_selection() {
+        return false;
+    }
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let (Some(before), Some(after)) = (char_before(&cursor), char_after(&cursor)) else {
+        return false;
+    };
+    if !config.pairs.contains(&(before, after)) {
+        return false;
+    }
+    let (mut start, mut end) = (cursor, cursor);
+    start.backward_char();
+    end.forward_char();
+    buffer.delete(&mut start, &mut end);
+    true
+}
+
+/// Makes `editor` close brackets and quotes as they're typed, following `config`.
+pub fn setup_auto_close(editor: &sourceview5::View, config: &Rc<BracketsConfig>) {
+    let keys = EventControllerKey::builder()
+        .propagation_phase(PropagationPhase::Capture)
+        .build();
+    keys.connect_key_pressed(clone!(
+        #[strong]
+        config,
+        #[weak]
+        editor,
+        #[upgrade_or]
+        Propagation::Proceed,
+        move |_, key, _, modifiers| {
+            // Typing at several carets is repeated by the multi cursor instead, and Vim
+            // and Emacs modes give the keys their own meaning
+            if editor.has_css_class(MULTI_CURSOR_CLASS)
+                || editor.has_css_class(KEYMAP_CLASS)
+                || modifiers.intersects(ModifierType::CONTROL_MASK | ModifierType::ALT_MASK)
+                || !editor.is_editable()
+            {
+                return Propagation::Proceed;
+            }
+            let buffer = editor.buffer().downcast::<sourceview5::Buffer>().unwrap();
+            let Some(language_config) = language_config(&config, &buffer) else {
+                return Propagation::Proceed;
+            };
+            let handled = match key {
+                Key::BackSpace => delete_pair(&buffer, language_config),
+                _ => key
+                    .to_unicode()
+                    .is_some_and(|c| type_character(&buffer, language_config, c)),
+            };
+            if handled {
+                editor.scroll_mark_onscreen(&buffer.get_insert());
+                Propagation::Stop
+            } else {
+                Propagation::Proceed
+            }
+        }
+    ));
+    editor.add_controller(keys);
+}
+
+/// Whether a bracket at `iter` is code rather than part of a string or comment.
+fn in_code(buffer: &sourceview5::Buffer, iter: &TextIter) -> bool {
+    !buffer.iter_has_context_class(iter, "string")
+        && !buffer.iter_has_context_class(iter, "comment")
+}
+
+/// The pairs that nest, leaving out quotes.
+fn nesting_pairs(state: &State) -> Vec<(char, char)> {
+    language_config(&state.brackets, &state.buffer)
+        .map(|config| {
+            config
+                .pairs
+                .iter()
+                .copied()
+                .filter(|(open, close)| open != close)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The bracket matching the one at `iter`.
+fn find_match(
+    buffer: &sourceview5::Buffer,
+    iter: &TextIter,
+    pairs: &[(char, char)],
+) -> Option<TextIter> {
+    let c = iter.char();
+    let (open, close, forward) = pairs.iter().find_map(|(open, close)| {
+        if *open == c {
+            Some((*open, *close, true))
+        } else if *close == c {
+            Some((*open, *close, false))
+        } else {
+            None
+        }
+    })?;
+    let code = in_code(buffer, iter);
+    let mut depth = 0;
+    let mut position = *iter;
+    loop {
+        let moved = if forward {
+            position.forward_char() && !position.is_end()
+        } else {
+            position.backward_char()
+        };
+        if !moved {
+            return None;
+        }
+        let current = position.char();
+        if (current != open && current != close) || in_code(buffer, &position) != code {
+            continue;
+        }
+        if (current == open) == forward {
+            depth += 1;
+        } else if depth == 0 {
+            return Some(position);
+        } else {
+            depth -= 1;
+        }
+    }
+}
+
+/// The innermost pair of brackets around `cursor`, as the opening and closing bracket.
+fn enclosing_pair(
+    buffer: &sourceview5::Buffer,
+    cursor: &TextIter,
+    pairs: &[(char, char)],
+) -> Option<(TextIter, TextIter)> {
+    let mut position = *cursor;
+    // Closers seen on the way back, waiting for their openers
+    let mut closed: Vec<char> = Vec::new();
+    while position.backward_char() {
+        let c = position.char();
+        if !in_code(buffer, &position) {
+            continue;
+        }
+        if pairs.iter().any(|(_, close)| *close == c) {
+            closed.push(c);
+        } else if let Some((_, close)) = pairs.iter().find(|(open, _)| *open == c) {
+            if closed.last() == Some(close) {
+                closed.pop();
+            } else if closed.is_empty() {
+                let matched = find_match(buffer, &position, pairs)?;
+                return Some((position, matched));
+            }
+        }
+    }
+    None
+}
+
+/// The bracket at or just before the cursor, with its match, opening bracket first.
+fn adjacent_pair(
+    buffer: &sourceview5::Buffer,
+    cursor: &TextIter,
+    pairs: &[(char, char)],
+) -> Option<(TextIter, TextIter)> {
+    let mut before = *cursor;
+    before.backward_char();
+    [*cursor, before].into_iter().find_map(|iter| {
+        let matched = find_match(buffer, &iter, pairs)?;
+        Some(if iter < matched {
+            (iter, matched)
+        } else {
+            (matched, iter)
+        })
+    })
+}
+
+fn bracket_pair(state: &State) -> Option<(TextIter, TextIter)> {
+    let buffer = &state.buffer;
+    let pairs = nesting_pairs(state);
+    buffer.ensure_highlight(&buffer.start_iter(), &buffer.end_iter());
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let pair =
+        adjacent_pair(buffer, &cursor, &pairs).or_else(|| enclosing_pair(buffer, &cursor, &pairs));
+    if pair.is_none() {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("No matching bracket found"));
+    }
+    pair
+}
+
+/// Moves the cursor to the bracket matching the one beside it, or to the end of the
+/// brackets around it.
+pub(crate) fn jump_to_bracket(state: &mut State) {
+    let Some((open, close)) = bracket_pair(state) else {
+        return;
+    };
+    let cursor = state.buffer.iter_at_mark(&state.buffer.get_insert());
+    let target = if cursor.offset() <= open.offset() + 1 {
+        close
+    } else {
+        open
+    };
+    state.buffer.place_cursor(&target);
+    state
+        .editor
+        .scroll_mark_onscreen(&state.buffer.get_insert());
+}
+
+/// Selects the brackets beside or around the cursor and everything between them.
+pub(crate) fn select_to_bracket(state: &mut State) {
+    let Some((open, mut close)) = bracket_pair(state) else {
+        return;
+    };
+    close.forward_char();
+    state.buffer.select_range(&close, &open);
+}
@@ -0,0 +1,109 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
+
+use gtk4::{
+    TextBuffer,
+    glib::{self, ControlFlow, SignalHandlerId, SourceId, clone},
+    prelude::*,
+};
+use sourceview5::{CompletionWords, prelude::*};
+
+use crate::app::model::State;
+
+/// Word completion for the editor, and when completions pop up.
+#[derive(Debug)]
+pub struct EditorCompletion {
+    /// Milliseconds to wait after typing before completions pop up, 0 shows them right away
+    pub popup_delay: Rc<Cell<u32>>,
+}
+
+pub fn setup_completion(editor: &sourceview5::View) -> EditorCompletion {
+    let completion = EditorCompletion {
+        popup_delay: Rc::default(),
+    };
+    attach_completion(editor, &completion);
+    completion
+}
+
+/// Adds word completion and the popup delay to an editor, following it to whichever
+/// buffer it shows.
+pub fn attach_completion(editor: &sourceview5::View, editor_completion: &EditorCompletion) {
+    let words = CompletionWords::builder()
+        .title("Words")
+        .minimum_word_size(3)
+        .build();
+    let completion = editor.completion();
+    completion.add_provider(&words);
+
+    // With a delay, interactive completion is blocked and the popup is shown by hand
+    // once typing pauses in a word
+    let popup_delay = editor_completion.popup_delay.clone();
+    if popup_delay.get() > 0 {
+        completion.block_interactive();
+    }
+    let pending: Rc<RefCell<Option<SourceId>>> = Rc::default();
+    let watched: RefCell<Option<(TextBuffer, SignalHandlerId)>> = RefCell::default();
+    let watch = move |editor: &sourceview5::View| {
+        if let Some((buffer, handler)) = watched.take() {
+            words.unregister(&buffer);
+            buffer.disconnect(handler);
+        }
+        let buffer = editor.buffer();
+        words.register(&buffer);
+        let handler = buffer.connect_insert_text(clone!(
+            #[strong]
+            popup_delay,
+            #[strong]
+            pending,
+            #[weak]
+            completion,
+            move |_, _, text| {
+                if let Some(source) = pending.borrow_mut().take() {
+                    source.remove();
+                }
+                // Other splits may show the same buffer
+                if popup_delay.get() == 0
+                    || !completion.view().has_focus()
+                    || !text.chars().all(|c| c.is_alphanumeric() || c == '_')
+                {
+                    return;
+                }
+                *pending.borrow_mut() = Some(glib::timeout_add_local(
+                    Duration::from_millis(popup_delay.get() as u64),
+                    clone!(
+                        #[strong]
+                        pending,
+                        #[weak]
+                        completion,
+                        #[upgrade_or]
+                        ControlFlow::Break,
+                        move || {
+                            pending.borrow_mut().take();
+                            completion.show();
+                            ControlFlow::Break
+                        }
+                    ),
+                ));
+            }
+        ));
+        *watched.borrow_mut() = Some((buffer, handler));
+    };
+    watch(editor);
+    // A split opening another file gives its editor a new buffer
+    editor.connect_buffer_notify(watch);
+}
+
+pub(crate) fn set_popup_delay(state: &State, delay: u32) {
+    let was_delayed = state.completion.popup_delay.replace(delay) > 0;
+    for pane in &state.splits.panes {
+        let completion = pane.view.completion();
+        match (was_delayed, delay > 0) {
+            (false, true) => completion.block_interactive(),
+            (true, false) => completion.unblock_interactive(),
+            _ => {}
+        }
+    }
+}
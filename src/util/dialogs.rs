@@ -2,12 +2,18 @@ use gtk4::{
     AboutDialog, ShortcutsGroup, ShortcutsSection, ShortcutsShortcut, ShortcutsWindow, glib::clone,
 };
 use libadwaita::{
-    HeaderBar, PreferencesDialog, PreferencesGroup, PreferencesPage, PreferencesRow, SpinRow,
-    SwitchRow, ToolbarView, WindowTitle, prelude::*,
+    ComboRow, HeaderBar, PreferencesDialog, PreferencesGroup, PreferencesPage, PreferencesRow,
+    SpinRow, SwitchRow, ToolbarView, ViewStack, ViewSwitcher, ViewSwitcherPolicy, prelude::*,
 };
-use sourceview5::prelude::ViewExt;
 
-use crate::app::model::{ItemVis, Msg, State};
+use crate::{
+    app::model::{ItemVis, Msg, State},
+    util::{
+        keybindings::{SHORTCUT_GROUPS, group_shortcuts, keybindings_page},
+        keymap::Keymap,
+        whitespace::WhitespaceOption,
+    },
+};
 
 pub fn create_preferences_dialog(state: &mut State, sender: relm4::ComponentSender<State>) {
     // Editor group setup
@@ -30,12 +36,84 @@ pub fn create_preferences_dialog(state: &mut State, sender: relm4::ComponentSend
             .height_request(60)
             .build(),
     );
+    let completion_delay_spin_row = SpinRow::builder()
+        .title("Completion Popup Delay (ms)")
+        .subtitle("0 shows completions while typing")
+        .activatable(false)
+        .climb_rate(1.0)
+        .digits(0)
+        .adjustment(&gtk4::Adjustment::new(
+            state.completion.popup_delay.get() as f64,
+            0.0,
+            2000.0,
+            50.0,
+            250.0,
+            0.0,
+        ))
+        .build();
+    completion_delay_spin_row.connect_value_notify(clone!(
+        #[strong]
+        sender,
+        move |row| sender.input(Msg::UpdateCompletionDelay(row.value() as u32))
+    ));
+    editor_group.add(
+        &PreferencesRow::builder()
+            .title("Completion Popup Delay")
+            .activatable(false)
+            .child(&completion_delay_spin_row)
+            .height_request(60)
+            .build(),
+    );
+    let format_on_save_switch_row = SwitchRow::builder()
+        .title("Format on Save")
+        .subtitle("Formatters are set in cryptum-text-formatters.json")
+        .activatable(false)
+        .active(state.format_on_save)
+        .build();
+    format_on_save_switch_row.connect_active_notify(clone!(
+        #[strong]
+        sender,
+        move |row| sender.input(Msg::UpdateFormatOnSave(row.is_active()))
+    ));
+    editor_group.add(
+        &PreferencesRow::builder()
+            .title("Format on Save")
+            .activatable(false)
+            .child(&format_on_save_switch_row)
+            .height_request(60)
+            .build(),
+    );
+    let keymap_combo_row = ComboRow::builder()
+        .title("Keybindings")
+        .subtitle("Vim and Emacs modes show their state in the status bar")
+        .activatable(false)
+        .model(&gtk4::StringList::new(&Keymap::ALL.map(Keymap::name)))
+        .selected(
+            Keymap::ALL
+                .iter()
+                .position(|keymap| *keymap == state.keymap.keymap)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+    keymap_combo_row.connect_selected_notify(clone!(
+        #[strong]
+        sender,
+        move |row| sender.input(Msg::UpdateKeymap(Keymap::ALL[row.selected() as usize]))
+    ));
+    editor_group.add(
+        &PreferencesRow::builder()
+            .title("Keybindings")
+            .activatable(false)
+            .child(&keymap_combo_row)
+            .height_request(60)
+            .build(),
+    );
 
     // Tab group setup
     let tab_type_switch_row = SwitchRow::builder()
         .title("Enable Using Spaces for Tabs")
         .activatable(false)
-        .active(state.editor.is_insert_spaces_instead_of_tabs())
+        .active(state.indent_settings.use_spaces)
         .build();
     tab_type_switch_row.connect_active_notify(clone!(
         #[strong]
@@ -48,7 +126,7 @@ pub fn create_preferences_dialog(state: &mut State, sender: relm4::ComponentSend
         .climb_rate(1.0)
         .digits(0)
         .adjustment(&gtk4::Adjustment::new(
-            state.editor.tab_width() as f64,
+            state.indent_settings.tab_width as f64,
             1.0,
             32.0,
             1.0,
@@ -79,6 +157,61 @@ pub fn create_preferences_dialog(state: &mut State, sender: relm4::ComponentSend
             .build(),
     );
 
+    // Whitespace group setup
+    let whitespace_group = PreferencesGroup::builder()
+        .title("Whitespace")
+        .description(
+            "Languages can change these in \"editor_whitespace_languages\" in the settings file",
+        )
+        .build();
+    let whitespace = state.whitespace;
+    for (title, option, active) in [
+        (
+            "Trim Trailing Whitespace on Save",
+            WhitespaceOption::TrimTrailingWhitespace,
+            whitespace.trim_trailing_whitespace,
+        ),
+        (
+            "End Files with a Single Newline on Save",
+            WhitespaceOption::FinalNewline,
+            whitespace.final_newline,
+        ),
+        (
+            "Normalize Indentation on Save",
+            WhitespaceOption::NormalizeIndentation,
+            whitespace.normalize_indentation,
+        ),
+        (
+            "Show Tabs and Spaces",
+            WhitespaceOption::ShowWhitespace,
+            whitespace.show_whitespace,
+        ),
+        (
+            "Show Trailing Whitespace",
+            WhitespaceOption::ShowTrailingWhitespace,
+            whitespace.show_trailing_whitespace,
+        ),
+    ] {
+        let switch_row = SwitchRow::builder()
+            .title(title)
+            .activatable(false)
+            .active(active)
+            .build();
+        switch_row.connect_active_notify(clone!(
+            #[strong]
+            sender,
+            move |row| sender.input(Msg::UpdateWhitespace(option, row.is_active()))
+        ));
+        whitespace_group.add(
+            &PreferencesRow::builder()
+                .title(title)
+                .activatable(false)
+                .child(&switch_row)
+                .height_request(60)
+                .build(),
+        );
+    }
+
     // Visibility group setup
     let file_tree_vis_spin_row = SwitchRow::builder()
         .title
@@ -105,7 +238,7 @@ pub fn create_preferences_dialog(state: &mut State, sender: relm4::ComponentSend
     ));
     let hidden_files_visibilty_spin_row = SwitchRow::builder()
         .title("Hidden Files Visibility")
-        .subtitle("Ctrl+H")
+        .subtitle("Ctrl+Alt+H")
         .activatable(false)
         .active(state.view_hidden)
         .build();
@@ -148,14 +281,29 @@ pub fn create_preferences_dialog(state: &mut State, sender: relm4::ComponentSend
     let page = PreferencesPage::builder().title("Page").build();
     page.add(&editor_group);
     page.add(&tab_group);
+    page.add(&whitespace_group);
     page.add(&visibility_group);
+    let stack = ViewStack::new();
+    stack.add_titled_with_icon(&page, None, "General", "preferences-system-symbolic");
+    let shortcuts_page = keybindings_page(state, &sender);
+    stack.add_titled_with_icon(
+        &shortcuts_page,
+        None,
+        "Shortcuts",
+        "preferences-desktop-keyboard-shortcuts-symbolic",
+    );
     let toolbar = ToolbarView::builder().build();
     toolbar.add_top_bar(
         &HeaderBar::builder()
-            .title_widget(&WindowTitle::new("Preferences", ""))
+            .title_widget(
+                &ViewSwitcher::builder()
+                    .stack(&stack)
+                    .policy(ViewSwitcherPolicy::Wide)
+                    .build(),
+            )
             .build(),
     );
-    toolbar.set_content(Some(&page));
+    toolbar.set_content(Some(&stack));
 
     let dialog = PreferencesDialog::builder()
         .title("Preferences")
@@ -165,79 +313,24 @@ pub fn create_preferences_dialog(state: &mut State, sender: relm4::ComponentSend
     dialog.present(Some(&state.root));
 }
 
-pub fn create_keyboard_shortcut_dialog() {
-    // File shortcut group
-    let file_shortcut_array: [ShortcutsShortcut; 5] = [
-        ShortcutsShortcut::builder()
-            .title("New File")
-            .accelerator("<control><shift>n")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Open File")
-            .accelerator("<control>o")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Open Folder")
-            .accelerator("<control><shift>o")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Save File")
-            .accelerator("<control>s")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Save File As")
-            .accelerator("<control><shift>s")
-            .build(),
-    ];
-    let file_group = ShortcutsGroup::builder().title("File").build();
-    for shortcut in file_shortcut_array {
-        file_group.append(&shortcut);
-    }
-
-    // View shortcut group
-    let view_shortcut_array: [ShortcutsShortcut; 4] = [
-        ShortcutsShortcut::builder()
-            .title("Toggle File List Visibility")
-            .accelerator("<control><alt>f")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Toggle Mini Map Visibility")
-            .accelerator("<control><alt>m")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Toggle Hidden Files Visibility (UNIX)")
-            .accelerator("<control>h")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Toggle Fullscreen")
-            .accelerator("F11")
-            .build(),
-    ];
-    let edit_group = ShortcutsGroup::builder().title("View").build();
-    for shortcut in view_shortcut_array {
-        edit_group.append(&shortcut);
-    }
-
-    // About shortcut group
-    let about_shortcuts_array: [ShortcutsShortcut; 2] = [
-        ShortcutsShortcut::builder()
-            .title("Show Preferences Dialog")
-            .accelerator("<control>comma")
-            .build(),
-        ShortcutsShortcut::builder()
-            .title("Show Keyboard Shortcuts Dialog")
-            .accelerator("<control>question")
-            .build(),
-    ];
-    let about_group = ShortcutsGroup::builder().title("About").build();
-    for shortcut in about_shortcuts_array {
-        about_group.append(&shortcut);
-    }
-
+pub fn create_keyboard_shortcut_dialog(state: &State) {
     let section = ShortcutsSection::builder().build();
-    section.append(&file_group);
-    section.append(&edit_group);
-    section.append(&about_group);
+    for (group_name, title) in SHORTCUT_GROUPS {
+        let shortcuts = group_shortcuts(state, group_name);
+        if shortcuts.is_empty() {
+            continue;
+        }
+        let group = ShortcutsGroup::builder().title(title).build();
+        for (title, accelerator) in shortcuts {
+            group.append(
+                &ShortcutsShortcut::builder()
+                    .title(title)
+                    .accelerator(accelerator)
+                    .build(),
+            );
+        }
+        section.append(&group);
+    }
     ShortcutsWindow::builder().child(&section).build().show();
 }
 
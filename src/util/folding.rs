@@ -0,0 +1,380 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, time::Duration};
+
+use gtk4::{
+    TextIter, TextTag,
+    glib::{self, ControlFlow, SourceId, clone},
+    prelude::*,
+};
+use libadwaita::Toast;
+use sourceview5::{GutterRendererPixbuf, prelude::*};
+
+use crate::{
+    app::model::{Msg, State},
+    fs::session::save_session,
+};
+
+/// Foldable regions by their first line, with the last line each one hides.
+type Regions = BTreeMap<i32, i32>;
+
+/// Fold regions of the buffer and the gutter showing them. A folded region's lines are
+/// hidden with an invisible tag, so the fold moves with edits around it.
+#[derive(Debug)]
+pub struct Folding {
+    regions: RefCell<Regions>,
+    tag: TextTag,
+    /// The gutter renderer in each editor showing the buffer
+    renderers: RefCell<Vec<(sourceview5::View, GutterRendererPixbuf)>>,
+    editor: sourceview5::View,
+    buffer: sourceview5::Buffer,
+}
+
+fn line_end(buffer: &sourceview5::Buffer, line: i32) -> TextIter {
+    let mut iter = buffer
+        .iter_at_line(line)
+        .unwrap_or_else(|| buffer.end_iter());
+    if !iter.ends_line() {
+        iter.forward_to_line_end();
+    }
+    iter
+}
+
+/// The width of a line's indentation, or `None` for a blank line.
+fn indentation(line: &str, tab_width: usize) -> Option<usize> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    Some(
+        line.chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| if c == '\t' { tab_width } else { 1 })
+            .sum(),
+    )
+}
+
+/// Regions of lines indented deeper than the line before them.
+fn indentation_regions(text: &str, tab_width: usize) -> Regions {
+    let mut regions = Regions::new();
+    // Lines that may start a region, with their indentation
+    let mut open: Vec<(i32, usize)> = Vec::new();
+    let mut last_code = 0;
+    for (index, line) in (0..).zip(text.lines()) {
+        let Some(indent) = indentation(line, tab_width) else {
+            continue;
+        };
+        while let Some(&(start, start_indent)) = open.last()
+            && start_indent >= indent
+        {
+            open.pop();
+            if last_code > start {
+                regions.insert(start, last_code);
+            }
+        }
+        open.push((index, indent));
+        last_code = index;
+    }
+    for (start, _) in open {
+        if last_code > start {
+            regions.insert(start, last_code);
+        }
+    }
+    regions
+}
+
+/// Regions between brackets on different lines, leaving the closing line visible.
+/// Brackets in strings and comments are skipped.
+fn bracket_regions(buffer: &sourceview5::Buffer) -> Regions {
+    let mut regions = Regions::new();
+    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), true);
+    let mut open: Vec<(char, i32)> = Vec::new();
+    let (mut line, mut offset) = (0, 0);
+    for c in text.chars() {
+        if matches!(c, '{' | '[' | '(' | '}' | ']' | ')') {
+            let iter = buffer.iter_at_offset(offset);
+            let quoted = buffer.iter_has_context_class(&iter, "string")
+                || buffer.iter_has_context_class(&iter, "comment");
+            if !quoted {
+                match c {
+                    '{' | '[' | '(' => open.push((c, line)),
+                    _ => {
+                        let expected = match c {
+                            '}' => '{',
+                            ']' => '[',
+                            _ => '(',
+                        };
+                        if let Some(index) = open.iter().rposition(|(c, _)| *c == expected) {
+                            let (_, start) = open[index];
+                            open.truncate(index);
+                            if line - 1 > start {
+                                let end = regions.entry(start).or_insert(line - 1);
+                                *end = (*end).max(line - 1);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if c == '\n' {
+            line += 1;
+        }
+        offset += 1;
+    }
+    regions
+}
+
+impl Folding {
+    /// Works out the fold regions again, after the buffer has changed.
+    fn refresh(&self) {
+        let buffer = &self.buffer;
+        buffer.ensure_highlight(&buffer.start_iter(), &buffer.end_iter());
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), true);
+        let mut regions = indentation_regions(&text, self.editor.tab_width().max(1) as usize);
+        // Brackets describe the structure better than indentation where there are any
+        regions.extend(bracket_regions(buffer));
+        *self.regions.borrow_mut() = regions;
+        self.redraw();
+    }
+
+    fn redraw(&self) {
+        for (_, renderer) in self.renderers.borrow().iter() {
+            renderer.queue_draw();
+        }
+    }
+
+    fn starts_region(&self, line: i32) -> bool {
+        self.regions.borrow().contains_key(&line)
+    }
+
+    fn is_folded(&self, start: i32) -> bool {
+        line_end(&self.buffer, start).has_tag(&self.tag)
+    }
+
+    fn fold(&self, start: i32, end: i32) {
+        let buffer = &self.buffer;
+        let (hide_start, hide_end) = (line_end(buffer, start), line_end(buffer, end));
+        // Keep the cursor out of the hidden lines
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        if cursor.in_range(&hide_start, &hide_end) || cursor == hide_end {
+            buffer.place_cursor(&hide_start);
+        }
+        buffer.apply_tag(&self.tag, &hide_start, &hide_end);
+        self.redraw();
+    }
+
+    fn unfold(&self, start: i32, end: i32) {
+        let buffer = &self.buffer;
+        buffer.remove_tag(&self.tag, &line_end(buffer, start), &line_end(buffer, end));
+        self.redraw();
+    }
+
+    /// The innermost region holding `line`, which may be its first line.
+    fn region_at(&self, line: i32, folded: bool) -> Option<(i32, i32)> {
+        self.regions
+            .borrow()
+            .range(..=line)
+            .rev()
+            .map(|(start, end)| (*start, *end))
+            .find(|(start, end)| *end >= line && self.is_folded(*start) == folded)
+    }
+
+    /// The first line of each folded region.
+    fn folded_lines(&self) -> Vec<i32> {
+        self.regions
+            .borrow()
+            .keys()
+            .copied()
+            .filter(|start| self.is_folded(*start))
+            .collect()
+    }
+}
+
+pub fn setup_folding(
+    editor: &sourceview5::View,
+    buffer: &sourceview5::Buffer,
+    sender: &relm4::ComponentSender<State>,
+) -> Rc<Folding> {
+    let tag = buffer
+        .create_tag(Some("fold"), &[("invisible", &true)])
+        .unwrap();
+    let folding = Rc::new(Folding {
+        regions: RefCell::default(),
+        tag,
+        renderers: RefCell::default(),
+        editor: editor.clone(),
+        buffer: buffer.clone(),
+    });
+    attach_fold_gutter(editor, &folding, sender);
+
+    // Find the regions again once typing pauses
+    let pending: Rc<RefCell<Option<SourceId>>> = Rc::default();
+    buffer.connect_changed(clone!(
+        #[weak]
+        folding,
+        move |_| {
+            if let Some(source) = pending.borrow_mut().take() {
+                source.remove();
+            }
+            *pending.borrow_mut() = Some(glib::timeout_add_local(
+                Duration::from_millis(300),
+                clone!(
+                    #[weak]
+                    folding,
+                    #[strong]
+                    pending,
+                    #[upgrade_or]
+                    ControlFlow::Break,
+                    move || {
+                        pending.borrow_mut().take();
+                        folding.refresh();
+                        ControlFlow::Break
+                    }
+                ),
+            ));
+        }
+    ));
+
+    folding
+}
+
+/// Adds the fold icons to the gutter of an editor showing the folding's buffer.
+pub fn attach_fold_gutter(
+    editor: &sourceview5::View,
+    folding: &Rc<Folding>,
+    sender: &relm4::ComponentSender<State>,
+) {
+    let renderer = GutterRendererPixbuf::builder().xpad(2).build();
+    sourceview5::prelude::ViewExt::gutter(editor, gtk4::TextWindowType::Left).insert(&renderer, 0);
+    renderer.connect_query_data(clone!(
+        #[weak]
+        folding,
+        move |renderer, _, line| {
+            let line = line as i32;
+            let icon = folding.starts_region(line).then(|| {
+                if folding.is_folded(line) {
+                    "pan-end-symbolic"
+                } else {
+                    "pan-down-symbolic"
+                }
+            });
+            renderer.set_icon_name(icon);
+        }
+    ));
+    renderer.connect_query_activatable(clone!(
+        #[weak]
+        folding,
+        #[upgrade_or]
+        false,
+        move |_, iter, _| folding.starts_region(iter.line())
+    ));
+    renderer.connect_activate(clone!(
+        #[strong]
+        sender,
+        move |renderer, iter, _, _, _, _| {
+            // Focusing a split first makes its file the current one
+            renderer.view().grab_focus();
+            sender.input(Msg::ToggleFold(iter.line()));
+        }
+    ));
+    folding
+        .renderers
+        .borrow_mut()
+        .push((editor.clone(), renderer));
+}
+
+/// Takes the fold icons out of an editor that's about to show another buffer.
+pub fn detach_fold_gutter(editor: &sourceview5::View, folding: &Folding) {
+    folding.renderers.borrow_mut().retain(|(view, renderer)| {
+        if view != editor {
+            return true;
+        }
+        sourceview5::prelude::ViewExt::gutter(view, gtk4::TextWindowType::Left).remove(renderer);
+        false
+    });
+}
+
+/// Saves which regions of the current file are folded to the session data.
+pub(crate) fn remember_folds(state: &mut State) {
+    if state.current_file_path.as_os_str().is_empty() {
+        return;
+    }
+    let folded = state.folding.folded_lines();
+    let folds = &mut state.session.folds;
+    if folded.is_empty() {
+        if folds.remove(&state.current_file_path).is_none() {
+            return;
+        }
+    } else {
+        folds.insert(state.current_file_path.clone(), folded);
+    }
+    save_session(&state.session);
+}
+
+/// Folds the regions saved for the file that was just loaded.
+pub(crate) fn restore_folds(state: &mut State) {
+    state.folding.refresh();
+    let Some(folded) = state.session.folds.get(&state.current_file_path) else {
+        return;
+    };
+    // Fold outer regions last, so a saved inner fold is still found inside them
+    for start in folded.iter().rev() {
+        let end = state.folding.regions.borrow().get(start).copied();
+        if let Some(end) = end {
+            state.folding.fold(*start, end);
+        }
+    }
+}
+
+/// Folds or unfolds the region starting on `line`, from a click in the gutter.
+pub(crate) fn toggle_fold(state: &mut State, line: i32) {
+    let folding = &state.folding;
+    let Some(end) = folding.regions.borrow().get(&line).copied() else {
+        return;
+    };
+    if folding.is_folded(line) {
+        folding.unfold(line, end);
+    } else {
+        folding.fold(line, end);
+    }
+    remember_folds(state);
+}
+
+fn cursor_line(state: &State) -> i32 {
+    state.buffer.iter_at_mark(&state.buffer.get_insert()).line()
+}
+
+/// Folds the innermost unfolded region around the cursor.
+pub(crate) fn fold_at_cursor(state: &mut State) {
+    match state.folding.region_at(cursor_line(state), false) {
+        Some((start, end)) => state.folding.fold(start, end),
+        None => {
+            state
+                .toast_overlay
+                .add_toast(Toast::new("There's nothing to fold here"));
+            return;
+        }
+    }
+    remember_folds(state);
+}
+
+/// Unfolds the innermost folded region around the cursor.
+pub(crate) fn unfold_at_cursor(state: &mut State) {
+    let Some((start, end)) = state.folding.region_at(cursor_line(state), true) else {
+        return;
+    };
+    state.folding.unfold(start, end);
+    remember_folds(state);
+}
+
+pub(crate) fn fold_all(state: &mut State) {
+    let regions = state.folding.regions.borrow().clone();
+    for (start, end) in regions {
+        state.folding.fold(start, end);
+    }
+    remember_folds(state);
+}
+
+pub(crate) fn unfold_all(state: &mut State) {
+    let buffer = &state.buffer;
+    buffer.remove_tag(&state.folding.tag, &buffer.start_iter(), &buffer.end_iter());
+    state.folding.redraw();
+    remember_folds(state);
+}
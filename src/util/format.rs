@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use libadwaita::{Toast, ToastOverlay};
+use serde::{Deserialize, Serialize};
+use sourceview5::prelude::*;
+
+use crate::{
+    app::model::{Msg, State},
+    fs::{file::write_file, settings::load_json_config},
+    lsp::manager::buffer_text,
+    util::widget::replace_buffer_text,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FormatterConfig {
+    pub command: String,
+    /// Arguments for the command, `{file}` is replaced with the path of the current file
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// How long a formatter may run before it's stopped.
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Formatters keyed by sourceview language id.
+pub type FormattersConfig = HashMap<String, FormatterConfig>;
+
+fn formatter(command: &str, args: &[&str]) -> FormatterConfig {
+    FormatterConfig {
+        command: command.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    }
+}
+
+pub fn default_formatters_config() -> FormattersConfig {
+    let black = formatter("black", &["--quiet", "-"]);
+    let prettier = formatter("prettier", &["--stdin-filepath", "{file}"]);
+    HashMap::from([
+        (
+            "rust".to_string(),
+            formatter("rustfmt", &["--edition", "2024"]),
+        ),
+        ("python".to_string(), black.clone()),
+        ("python3".to_string(), black),
+        ("js".to_string(), prettier.clone()),
+        ("typescript".to_string(), prettier.clone()),
+        ("json".to_string(), prettier.clone()),
+        ("css".to_string(), prettier.clone()),
+        ("html".to_string(), prettier.clone()),
+        ("markdown".to_string(), prettier),
+    ])
+}
+
+/// Loads the formatter config, writing out the defaults if the file doesn't exist yet.
+pub fn load_formatters_config(toast_overlay: &ToastOverlay) -> FormattersConfig {
+    load_json_config(
+        "cryptum-text-formatters.json",
+        default_formatters_config(),
+        toast_overlay,
+    )
+}
+
+/// Reads all of a child's output pipe from a thread.
+fn read_pipe(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        _ = pipe.read_to_end(&mut output);
+        output
+    })
+}
+
+/// Pipes `text` through the formatter, returning its output or an error message. Formatters
+/// still running after `FORMAT_TIMEOUT` are killed.
+fn run_formatter(config: &FormatterConfig, file: &Path, text: &str) -> Result<String, String> {
+    let mut command = Command::new(&config.command);
+    command
+        .args(
+            config
+                .args
+                .iter()
+                .map(|arg| arg.replace("{file}", &file.display().to_string())),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(folder) = file.parent().filter(|folder| folder.is_dir()) {
+        // Lets formatters pick up project config like rustfmt.toml or .prettierrc
+        command.current_dir(folder);
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|error| format!("Couldn't run {}: {error}", config.command))?;
+
+    // Written and read from threads so a formatter filling its output pipe can't deadlock us
+    let mut stdin = child.stdin.take().unwrap();
+    let input = text.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let stdout = read_pipe(child.stdout.take().unwrap());
+    let stderr = read_pipe(child.stderr.take().unwrap());
+    let deadline = Instant::now() + FORMAT_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                _ = child.kill();
+                _ = child.wait();
+                return Err(format!(
+                    "{} took longer than {} seconds and was stopped",
+                    config.command,
+                    FORMAT_TIMEOUT.as_secs()
+                ));
+            }
+            Err(error) => return Err(format!("{} failed: {error}", config.command)),
+        }
+    };
+    _ = writer.join();
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+
+    if status.success() {
+        String::from_utf8(stdout).map_err(|_| format!("{} produced invalid UTF-8", config.command))
+    } else {
+        let stderr = String::from_utf8_lossy(&stderr);
+        Err(format!(
+            "{} failed: {}",
+            config.command,
+            stderr
+                .lines()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or("unknown error")
+        ))
+    }
+}
+
+/// What happens to a file once it's formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AfterFormat {
+    Nothing,
+    Save,
+    SaveAndQuit,
+}
+
+/// A formatter's output for the text of a file, sent back from its thread.
+#[derive(Debug)]
+pub struct FormatResult {
+    path: PathBuf,
+    /// The buffer's text when the formatter was started
+    text: String,
+    formatted: Result<String, String>,
+    after: AfterFormat,
+}
+
+/// Formats the buffer with the formatter for its language on a background thread, the
+/// result coming back as `Msg::DocumentFormatted`. When saving after, as formatting on save
+/// does, languages without a formatter are skipped silently.
+pub(crate) fn format_document(
+    state: &mut State,
+    after: AfterFormat,
+    sender: relm4::ComponentSender<State>,
+) {
+    let config = state
+        .buffer
+        .language()
+        .and_then(|language| state.formatters.get(language.id().as_str()).cloned());
+    let Some(config) = config else {
+        if after != AfterFormat::Nothing {
+            write_file(state, after, sender);
+            return;
+        }
+        let message = match state.buffer.language() {
+            Some(language) => format!("No formatter configured for {}", language.name()),
+            None => "No formatter for plain text".to_string(),
+        };
+        state.toast_overlay.add_toast(Toast::new(&message));
+        return;
+    };
+
+    let path = state.current_file_path.clone();
+    let text = buffer_text(&state.buffer);
+    let (result_sender, result_receiver) = relm4::channel::<FormatResult>();
+    std::thread::spawn(move || {
+        let formatted = run_formatter(&config, &path, &text);
+        result_sender.emit(FormatResult {
+            path,
+            text,
+            formatted,
+            after,
+        });
+    });
+    relm4::spawn_local(async move {
+        if let Some(result) = result_receiver.recv().await {
+            sender.input(Msg::DocumentFormatted(result));
+        }
+    });
+}
+
+/// Replaces the buffer with the formatter's output as a single undoable edit, keeping the
+/// cursor on the same line and column. The output is dropped if the file was edited or
+/// another one opened while the formatter ran.
+pub(crate) fn apply_formatting(
+    state: &mut State,
+    result: FormatResult,
+    sender: relm4::ComponentSender<State>,
+) {
+    if result.path != state.current_file_path {
+        if result.after != AfterFormat::Nothing {
+            state.toast_overlay.add_toast(Toast::new(&format!(
+                "{} wasn't saved, another file was opened while formatting it",
+                result.path.display()
+            )));
+        }
+        return;
+    }
+    match result.formatted {
+        Err(message) => state.toast_overlay.add_toast(Toast::new(&message)),
+        Ok(_) if buffer_text(&state.buffer) != result.text => {
+            state.toast_overlay.add_toast(Toast::new(
+                "The file was edited while formatting, so it wasn't formatted",
+            ));
+        }
+        Ok(formatted) if formatted != result.text => replace_buffer_text(state, &formatted),
+        Ok(_) => {}
+    }
+    if result.after != AfterFormat::Nothing {
+        write_file(state, result.after, sender);
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::Path;
+
+fn is_boundary(previous: char, current: char) -> bool {
+    matches!(previous, '/' | '\\' | '_' | '-' | '.' | ' ' | ':')
+        || (previous.is_lowercase() && current.is_uppercase())
+}
+
+/// Scores how well `query` matches `candidate`, returning `None` unless every
+/// (non whitespace) query character appears in order. Matches at the start of
+/// words and runs of consecutive characters score higher.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous_match: Option<usize> = None;
+    for query_char in query.chars().filter(|c| !c.is_whitespace()) {
+        let query_char = query_char.to_lowercase().next().unwrap_or(query_char);
+        let found = (next..candidate.len())
+            .find(|&i| candidate[i].to_lowercase().next().unwrap_or(candidate[i]) == query_char)?;
+        score += 1;
+        if found == 0 || is_boundary(candidate[found - 1], candidate[found]) {
+            score += 20;
+        }
+        match previous_match {
+            Some(previous) if previous + 1 == found => score += 15,
+            Some(previous) => score -= (found - previous - 1).min(10) as i64,
+            None => score -= found.min(10) as i64,
+        }
+        previous_match = Some(found);
+        next = found + 1;
+    }
+    // Prefer shorter candidates when everything else is equal
+    Some(score * 10 - candidate.len() as i64 / 4)
+}
+
+/// Scores a relative path, favouring matches that fall entirely within the file name.
+pub fn fuzzy_path_score(query: &str, path: &Path) -> Option<i64> {
+    let full_score = fuzzy_score(query, &path.display().to_string());
+    let name_score = path
+        .file_name()
+        .and_then(|name| fuzzy_score(query, &name.to_string_lossy()))
+        .map(|score| score + 300);
+    full_score.max(name_score)
+}
@@ -0,0 +1,161 @@
+use gtk4::{GestureClick, Popover, glib::clone, prelude::*};
+use relm4::gtk;
+
+use crate::{
+    app::model::{Msg, State},
+    util::widget::place_cursor,
+};
+
+/// The "Go to Line" popover shown from the status bar.
+#[derive(Debug)]
+pub struct GoToLine {
+    pub popover: Popover,
+    pub entry: gtk::Entry,
+}
+
+pub fn setup_go_to_line(
+    cursor_position_label: &gtk::Label,
+    sender: &relm4::ComponentSender<State>,
+) -> GoToLine {
+    let entry = gtk::Entry::builder()
+        .placeholder_text("line[:column], +n, -n or n%")
+        .width_chars(28)
+        .build();
+    let content = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    content.append(&gtk::Label::new(Some("Go to Line")));
+    content.append(&entry);
+    let popover = Popover::builder()
+        .child(&content)
+        .position(gtk4::PositionType::Top)
+        .build();
+    popover.set_parent(cursor_position_label);
+
+    // Events
+    entry.connect_activate(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::GoToLine)
+    ));
+    entry.connect_changed(|entry| entry.remove_css_class("error"));
+    let label_click = GestureClick::new();
+    label_click.connect_released(clone!(
+        #[strong]
+        sender,
+        move |_, _, _, _| sender.input(Msg::ShowGoToLine)
+    ));
+    cursor_position_label.add_controller(label_click);
+
+    GoToLine { popover, entry }
+}
+
+/// Resolves go to line input into a zero based line (clamped to the buffer) and optional column.
+///
+/// Accepts `line`, `line:column`, relative `+n`/`-n` and percentages like `50%`,
+/// with lines and columns counted from one.
+pub fn parse_go_to_line(input: &str, current_line: i32, line_count: i32) -> Option<(i32, i32)> {
+    let (line_part, column_part) = match input.trim().split_once(':') {
+        Some((line, column)) => (line.trim(), Some(column.trim())),
+        None => (input.trim(), None),
+    };
+    let line = if let Some(percent) = line_part.strip_suffix('%') {
+        let percent: f64 = percent.trim().parse().ok()?;
+        ((line_count - 1) as f64 * percent.clamp(0.0, 100.0) / 100.0).round() as i32
+    } else if let Some(offset) = line_part.strip_prefix('+') {
+        current_line.saturating_add_unsigned(parse_offset(offset)?)
+    } else if let Some(offset) = line_part.strip_prefix('-') {
+        current_line.saturating_sub_unsigned(parse_offset(offset)?)
+    } else if line_part.is_empty() {
+        current_line
+    } else {
+        line_part.parse::<i32>().ok()? - 1
+    };
+    let column = match column_part {
+        Some(column) => column.parse::<i32>().ok()?.max(1) - 1,
+        None => 0,
+    };
+    Some((line.clamp(0, (line_count - 1).max(0)), column))
+}
+
+/// The line count after a relative `+`/`-`, which has to start with a digit rather than
+/// another sign.
+fn parse_offset(offset: &str) -> Option<u32> {
+    if !offset.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    offset.parse().ok()
+}
+
+pub(crate) fn show_go_to_line(state: &mut State) {
+    let cursor = state.buffer.iter_at_mark(&state.buffer.get_insert());
+    state
+        .go_to_line
+        .entry
+        .set_text(&(cursor.line() + 1).to_string());
+    state.go_to_line.popover.popup();
+    state.go_to_line.entry.grab_focus();
+}
+
+pub(crate) fn go_to_line(state: &mut State) {
+    let current_line = state.buffer.iter_at_mark(&state.buffer.get_insert()).line();
+    match parse_go_to_line(
+        &state.go_to_line.entry.text(),
+        current_line,
+        state.buffer.line_count(),
+    ) {
+        Some((line, column)) => {
+            state.go_to_line.popover.popdown();
+            place_cursor(state, line, column);
+        }
+        None => state.go_to_line.entry.add_css_class("error"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_go_to_line;
+
+    #[test]
+    fn parses_lines_and_columns() {
+        assert_eq!(parse_go_to_line("12", 0, 100), Some((11, 0)));
+        assert_eq!(parse_go_to_line(" 12:5 ", 0, 100), Some((11, 4)));
+        assert_eq!(parse_go_to_line(":5", 7, 100), Some((7, 4)));
+        assert_eq!(parse_go_to_line("12:0", 0, 100), Some((11, 0)));
+    }
+
+    #[test]
+    fn clamps_to_the_buffer() {
+        assert_eq!(parse_go_to_line("0", 5, 100), Some((0, 0)));
+        assert_eq!(parse_go_to_line("500", 5, 100), Some((99, 0)));
+        assert_eq!(parse_go_to_line("3", 0, 0), Some((0, 0)));
+    }
+
+    #[test]
+    fn parses_relative_lines() {
+        assert_eq!(parse_go_to_line("+5", 10, 100), Some((15, 0)));
+        assert_eq!(parse_go_to_line("-5", 10, 100), Some((5, 0)));
+        assert_eq!(parse_go_to_line("-50", 10, 100), Some((0, 0)));
+        assert_eq!(parse_go_to_line("+2147483647", 10, 100), Some((99, 0)));
+    }
+
+    #[test]
+    fn parses_percentages() {
+        assert_eq!(parse_go_to_line("50%", 0, 101), Some((50, 0)));
+        assert_eq!(parse_go_to_line("0%", 40, 101), Some((0, 0)));
+        assert_eq!(parse_go_to_line("250%", 0, 101), Some((100, 0)));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(parse_go_to_line("abc", 0, 100), None);
+        assert_eq!(parse_go_to_line("12:x", 0, 100), None);
+        assert_eq!(parse_go_to_line("+", 0, 100), None);
+        assert_eq!(parse_go_to_line("x%", 0, 100), None);
+        assert_eq!(parse_go_to_line("99999999999", 0, 100), None);
+        assert_eq!(parse_go_to_line("+-5", 10, 100), None);
+        assert_eq!(parse_go_to_line("-+5", 10, 100), None);
+        assert_eq!(parse_go_to_line("--1", 10, 100), None);
+    }
+}
@@ -0,0 +1,289 @@
+use std::{cell::Cell, cmp::Reverse, collections::HashMap, rc::Rc};
+
+use gtk4::{
+    MenuButton, Popover, SpinButton, ToggleButton,
+    glib::{self, clone},
+    prelude::*,
+};
+use relm4::gtk;
+use sourceview5::prelude::*;
+
+use crate::{
+    app::model::{Msg, State},
+    fs::editorconfig::{IndentSize, IndentStyle},
+};
+
+/// Tabs or spaces, and how wide a level of indentation is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indent {
+    pub use_spaces: bool,
+    pub width: u32,
+}
+
+/// What's known about the indentation of a split's file while another split has focus.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DocumentIndentation {
+    detected: Option<Indent>,
+    chosen: Option<Indent>,
+}
+
+/// The current document's indentation, with the status bar button showing it.
+#[derive(Debug)]
+pub struct Indentation {
+    pub button: MenuButton,
+    source_label: gtk::Label,
+    spaces_button: ToggleButton,
+    tabs_button: ToggleButton,
+    width_spin: SpinButton,
+    reset_button: gtk::Button,
+    /// Set while the popover shows a new indentation, so its own signals are ignored
+    updating: Rc<Cell<bool>>,
+    /// What the document's contents use
+    detected: Option<Indent>,
+    /// Picked in the popover, for this document only
+    chosen: Option<Indent>,
+}
+
+impl Indentation {
+    /// The current document's indentation, for a new split showing it.
+    pub(crate) fn document(&self) -> DocumentIndentation {
+        DocumentIndentation {
+            detected: self.detected,
+            chosen: self.chosen,
+        }
+    }
+
+    /// Trades the current document's indentation for that of a split gaining focus.
+    pub(crate) fn swap_document(&mut self, document: &mut DocumentIndentation) {
+        std::mem::swap(&mut self.detected, &mut document.detected);
+        std::mem::swap(&mut self.chosen, &mut document.chosen);
+    }
+}
+
+pub fn setup_indentation(sender: &relm4::ComponentSender<State>) -> Indentation {
+    let source_label = gtk::Label::builder()
+        .css_classes(vec!["dim-label"])
+        .halign(gtk4::Align::Start)
+        .build();
+    let spaces_button = ToggleButton::with_label("Spaces");
+    let tabs_button = ToggleButton::builder()
+        .label("Tabs")
+        .group(&spaces_button)
+        .build();
+    let style_box = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .css_classes(vec!["linked"])
+        .homogeneous(true)
+        .build();
+    style_box.append(&spaces_button);
+    style_box.append(&tabs_button);
+    let width_spin = SpinButton::with_range(1.0, 16.0, 1.0);
+    let width_box = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .spacing(12)
+        .build();
+    width_box.append(
+        &gtk::Label::builder()
+            .label("Width")
+            .hexpand(true)
+            .halign(gtk4::Align::Start)
+            .build(),
+    );
+    width_box.append(&width_spin);
+    let reset_button = gtk::Button::with_label("Use Automatic Indentation");
+    let content = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    content.append(&gtk::Label::new(Some("Indentation")));
+    content.append(&source_label);
+    content.append(&style_box);
+    content.append(&width_box);
+    content.append(&reset_button);
+    let button = MenuButton::builder()
+        .css_classes(vec!["flat"])
+        .halign(gtk4::Align::Center)
+        .direction(gtk4::ArrowType::Up)
+        .tooltip_text("Indentation for this file")
+        .popover(&Popover::builder().child(&content).build())
+        .build();
+    let updating = Rc::new(Cell::new(false));
+
+    // Events
+    let choose = clone!(
+        #[strong]
+        sender,
+        #[strong]
+        updating,
+        #[weak]
+        spaces_button,
+        #[weak]
+        width_spin,
+        move || {
+            if !updating.get() {
+                sender.input(Msg::SetIndentation(Some(Indent {
+                    use_spaces: spaces_button.is_active(),
+                    width: width_spin.value() as u32,
+                })));
+            }
+        }
+    );
+    // Both buttons of the group notify, so only the one being turned on is followed
+    for style_button in [&spaces_button, &tabs_button] {
+        style_button.connect_toggled(clone!(
+            #[strong]
+            choose,
+            move |button| {
+                if button.is_active() {
+                    choose();
+                }
+            }
+        ));
+    }
+    width_spin.connect_value_changed(move |_| choose());
+    reset_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::SetIndentation(None))
+    ));
+
+    Indentation {
+        button,
+        source_label,
+        spaces_button,
+        tabs_button,
+        width_spin,
+        reset_button,
+        updating,
+        detected: None,
+        chosen: None,
+    }
+}
+
+/// Works out the indentation of `text` from how its lines are indented, if enough of
+/// them are. `tab_width` is used for tab indented text.
+pub fn detect_indentation(text: &str, tab_width: u32) -> Option<Indent> {
+    let (mut tab_lines, mut space_lines) = (0, 0);
+    // How often each change in indentation between lines is seen
+    let mut steps: HashMap<u32, u32> = HashMap::new();
+    let mut previous = 0;
+    for line in text.lines().take(10_000) {
+        let content = line.trim_start_matches([' ', '\t']);
+        // Block comment lines are indented by one more space to line up their stars
+        if content.is_empty() || content.starts_with('*') {
+            continue;
+        }
+        let indentation = &line[..line.len() - content.len()];
+        if indentation.starts_with('\t') {
+            tab_lines += 1;
+            previous = 0;
+            continue;
+        }
+        if indentation.contains('\t') {
+            continue;
+        }
+        let spaces = indentation.len() as u32;
+        if spaces > 0 {
+            space_lines += 1;
+        }
+        // A single space is more likely alignment than indentation
+        let step = spaces.abs_diff(previous);
+        if (2..=8).contains(&step) {
+            *steps.entry(step).or_default() += 1;
+        }
+        previous = spaces;
+    }
+    if tab_lines > space_lines {
+        return Some(Indent {
+            use_spaces: false,
+            width: tab_width,
+        });
+    }
+    steps
+        .into_iter()
+        .max_by_key(|(step, count)| (*count, Reverse(*step)))
+        .map(|(width, _)| Indent {
+            use_spaces: true,
+            width,
+        })
+}
+
+/// Detects the indentation of a document that was just loaded, dropping any chosen for
+/// the previous one.
+pub(crate) fn detect_document_indentation(state: &mut State) {
+    let buffer = &state.buffer;
+    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), true);
+    state.indentation.detected = detect_indentation(&text, state.indent_settings.tab_width);
+    state.indentation.chosen = None;
+    apply_indentation(state);
+}
+
+pub(crate) fn set_indentation(state: &mut State, indent: Option<Indent>) {
+    state.indentation.chosen = indent;
+    apply_indentation(state);
+}
+
+/// Sets up the editor's indentation. A choice from the popover comes first, then the
+/// file's EditorConfig properties, what the file uses and finally the settings.
+pub(crate) fn apply_indentation(state: &mut State) {
+    let config = &state.editorconfig;
+    let indentation = &state.indentation;
+    let settings = Indent {
+        use_spaces: state.indent_settings.use_spaces,
+        width: state.indent_settings.tab_width,
+    };
+    let fallback = indentation.detected.unwrap_or(settings);
+    let from_editorconfig =
+        config.indent_style.is_some() || config.indent_size.is_some() || config.tab_width.is_some();
+    let (indent, tab_width, source) = if let Some(chosen) = indentation.chosen {
+        (chosen, chosen.width, "Set for this file")
+    } else if from_editorconfig {
+        let width = match config.indent_size {
+            Some(IndentSize::Columns(columns)) => columns,
+            _ => config.tab_width().unwrap_or(fallback.width),
+        };
+        let indent = Indent {
+            use_spaces: config
+                .indent_style
+                .map_or(fallback.use_spaces, |style| style == IndentStyle::Space),
+            width,
+        };
+        (
+            indent,
+            config.tab_width().unwrap_or(width),
+            "From EditorConfig",
+        )
+    } else if let Some(detected) = indentation.detected {
+        (detected, detected.width, "Detected from the file")
+    } else {
+        (settings, settings.width, "From the settings")
+    };
+
+    let editor = &state.editor;
+    editor.set_insert_spaces_instead_of_tabs(indent.use_spaces);
+    editor.set_tab_width(tab_width);
+    // -1 indents by the tab width
+    editor.set_indent_width(if indent.width == tab_width {
+        -1
+    } else {
+        indent.width as i32
+    });
+
+    indentation.button.set_label(&if indent.use_spaces {
+        format!("Spaces: {}", indent.width)
+    } else {
+        format!("Tab Width: {}", indent.width)
+    });
+    indentation.source_label.set_label(source);
+    indentation.updating.set(true);
+    if indent.use_spaces {
+        indentation.spaces_button.set_active(true);
+    } else {
+        indentation.tabs_button.set_active(true);
+    }
+    indentation.width_spin.set_value(indent.width as f64);
+    indentation.updating.set(false);
+    indentation
+        .reset_button
+        .set_visible(indentation.chosen.is_some());
+}
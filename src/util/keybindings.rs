@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+
+use gtk4::{
+    EventControllerKey, PropagationPhase,
+    gdk::{Key, ModifierType},
+    glib::{self, Propagation, clone},
+    prelude::*,
+};
+use libadwaita::{
+    ActionRow, AlertDialog, PreferencesGroup, PreferencesPage, ToastOverlay, prelude::*,
+};
+use relm4::gtk;
+
+use crate::{
+    app::model::{Msg, State},
+    fs::settings::load_json_config,
+    util::{
+        keymap::{restore_accels, suspend_accels},
+        palette::action_label,
+    },
+};
+
+/// The shortcut each action starts with, in the order the shortcuts window lists them.
+const DEFAULT_KEYBINDINGS: &[(&str, &str)] = &[
+    // File
+    ("file.new_file", "<control><shift>n"),
+    ("file.open", "<control>o"),
+    ("file.open_folder", "<control><alt>o"),
+    ("file.quick_open", "<control>p"),
+    ("file.save", "<control>s"),
+    ("file.save_as", "<control><shift>s"),
+    ("file.run_task", "<control><shift>b"),
+    // Edit
+    ("edit.find", "<control>f"),
+    ("edit.replace", "<control>h"),
+    ("edit.find_next", "F3"),
+    ("edit.find_previous", "<shift>F3"),
+    ("edit.find_in_project", "<control><shift>f"),
+    ("edit.go_to_line", "<control>l"),
+    ("edit.go_to_symbol", "<control><shift>o"),
+    ("edit.go_to_definition", "F12"),
+    ("edit.find_references", "<shift>F12"),
+    ("edit.rename_symbol", "F2"),
+    ("edit.next_problem", "F8"),
+    ("edit.previous_problem", "<shift>F8"),
+    ("edit.format_document", "<control><shift>i"),
+    ("edit.jump_to_bracket", "<control>m"),
+    ("edit.select_to_bracket", "<control><shift>j"),
+    ("edit.add_next_occurrence", "<control>d"),
+    ("edit.move_lines_up", "<alt>Up"),
+    ("edit.move_lines_down", "<alt>Down"),
+    ("edit.duplicate_lines", "<control><shift>d"),
+    ("edit.delete_lines", "<control><shift>k"),
+    ("edit.join_lines", "<control>j"),
+    ("edit.toggle_line_comment", "<control>slash"),
+    ("edit.toggle_block_comment", "<shift><alt>a"),
+    // View
+    ("view.toggle_file_list", "<control><alt>f"),
+    ("view.toggle_mini_map", "<control><alt>m"),
+    ("view.toggle_hidden_files", "<control><alt>h"),
+    ("view.toggle_fullscreen", "F11"),
+    ("view.show_command_palette", "<control><shift>p"),
+    ("view.toggle_problems", "<control><shift>m"),
+    ("view.toggle_output", "<control><shift>u"),
+    ("view.toggle_terminal", "<control>grave"),
+    ("view.new_terminal", "<control><shift>grave"),
+    ("view.toggle_markdown_preview", "<control><shift>v"),
+    ("view.split_right", "<control>backslash"),
+    ("view.split_down", "<control><shift>backslash"),
+    ("view.close_split", "<control><alt>backslash"),
+    ("view.focus_next_split", "F6"),
+    ("view.fold", "<control><alt>bracketleft"),
+    ("view.unfold", "<control><alt>bracketright"),
+    ("view.fold_all", "<control><alt>minus"),
+    ("view.unfold_all", "<control><alt>equal"),
+    // About
+    ("about.show_preferences", "<control>comma"),
+    ("about.show_keyboard_shortcuts", "<control>question"),
+];
+
+/// Action group names with the titles they're listed under.
+pub const SHORTCUT_GROUPS: [(&str, &str); 4] = [
+    ("file", "File"),
+    ("edit", "Edit"),
+    ("view", "View"),
+    ("about", "About"),
+];
+
+/// A row of the shortcuts preferences page, kept to show changes while it's open.
+#[derive(Debug)]
+struct KeybindingRow {
+    action: String,
+    label: gtk::ShortcutLabel,
+    reset: gtk::Button,
+}
+
+/// The keyboard shortcut of every action: the defaults above, with the user's changes
+/// from "cryptum-text-keybindings.json" on top.
+#[derive(Debug, Default)]
+pub struct Keybindings {
+    /// Changed shortcuts by detailed action name, an empty list turning one off
+    overrides: BTreeMap<String, Vec<String>>,
+    rows: Vec<KeybindingRow>,
+}
+
+impl Keybindings {
+    pub fn accels(&self, action: &str) -> Vec<String> {
+        self.overrides
+            .get(action)
+            .cloned()
+            .unwrap_or_else(|| default_accels(action))
+    }
+}
+
+fn default_accels(action: &str) -> Vec<String> {
+    DEFAULT_KEYBINDINGS
+        .iter()
+        .filter(|(default_action, _)| *default_action == action)
+        .map(|(_, accel)| accel.to_string())
+        .collect()
+}
+
+const KEYBINDINGS_FILE: &str = "cryptum-text-keybindings.json";
+
+pub fn load_keybindings(toast_overlay: &ToastOverlay) -> Keybindings {
+    let mut overrides: BTreeMap<String, Vec<String>> =
+        load_json_config(KEYBINDINGS_FILE, BTreeMap::new(), toast_overlay);
+    for accels in overrides.values_mut() {
+        accels.retain(|accel| gtk::accelerator_parse(accel.as_str()).is_some());
+    }
+    Keybindings {
+        overrides,
+        rows: Vec::new(),
+    }
+}
+
+fn save_keybindings(keybindings: &Keybindings) {
+    if let Ok(overrides) = serde_json::to_string_pretty(&keybindings.overrides)
+        && let Some(config_dir) = dirs::config_dir()
+    {
+        _ = std::fs::write(config_dir.join(KEYBINDINGS_FILE), overrides);
+    }
+}
+
+fn set_accels(action: &str, accels: &[String]) {
+    let accels: Vec<&str> = accels.iter().map(String::as_str).collect();
+    relm4::main_application().set_accels_for_action(action, &accels);
+}
+
+/// Gives every action its shortcut.
+pub fn apply_keybindings(keybindings: &Keybindings) {
+    let actions = DEFAULT_KEYBINDINGS
+        .iter()
+        .map(|(action, _)| *action)
+        .chain(keybindings.overrides.keys().map(String::as_str));
+    for action in actions {
+        set_accels(action, &keybindings.accels(action));
+    }
+}
+
+fn label(action: &str) -> String {
+    let name = action.split_once('.').map_or(action, |(_, name)| name);
+    action_label(action, name)
+}
+
+/// The actions of a group, those with a default shortcut first.
+fn group_actions(state: &State, group_name: &str) -> Vec<String> {
+    let Some((_, group)) = state
+        .action_groups
+        .iter()
+        .find(|(name, _)| *name == group_name)
+    else {
+        return Vec::new();
+    };
+    let mut actions: Vec<String> = group
+        .list_actions()
+        .iter()
+        .map(|name| format!("{group_name}.{name}"))
+        .collect();
+    actions.sort_by_cached_key(|action| {
+        let position = DEFAULT_KEYBINDINGS
+            .iter()
+            .position(|(default_action, _)| default_action == action);
+        (position.unwrap_or(usize::MAX), label(action))
+    });
+    actions
+}
+
+/// The titles and shortcuts of a group's actions that have a shortcut, for the
+/// shortcuts window.
+pub(crate) fn group_shortcuts(state: &State, group_name: &str) -> Vec<(String, String)> {
+    group_actions(state, group_name)
+        .into_iter()
+        .map(|action| (label(&action), state.keybindings.accels(&action).join(" ")))
+        .filter(|(_, accels)| !accels.is_empty())
+        .collect()
+}
+
+fn refresh_rows(keybindings: &Keybindings) {
+    for row in &keybindings.rows {
+        row.label
+            .set_accelerator(&keybindings.accels(&row.action).join(" "));
+        row.reset
+            .set_visible(keybindings.overrides.contains_key(&row.action));
+    }
+}
+
+/// Gives `action` new shortcuts, taking them away from any other action using them.
+fn assign(state: &mut State, action: &str, accels: Vec<String>) {
+    let parsed: Vec<_> = accels
+        .iter()
+        .filter_map(|accel| gtk::accelerator_parse(accel.as_str()))
+        .collect();
+    let mut changes: Vec<(String, Vec<String>)> = conflicts(state, action, &parsed)
+        .into_iter()
+        .map(|other| {
+            let mut other_accels = state.keybindings.accels(&other);
+            other_accels.retain(|accel| {
+                gtk::accelerator_parse(accel.as_str()).is_none_or(|accel| !parsed.contains(&accel))
+            });
+            (other, other_accels)
+        })
+        .collect();
+    changes.push((action.to_string(), accels));
+
+    // The keymap's hold on some shortcuts is let go while they change, then taken again
+    restore_accels(&mut state.keymap);
+    for (action, accels) in changes {
+        set_accels(&action, &accels);
+        if accels == default_accels(&action) {
+            state.keybindings.overrides.remove(&action);
+        } else {
+            state.keybindings.overrides.insert(action, accels);
+        }
+    }
+    suspend_accels(&mut state.keymap);
+    save_keybindings(&state.keybindings);
+    refresh_rows(&state.keybindings);
+}
+
+/// Other actions with any of the shortcuts in `parsed`.
+fn conflicts(state: &State, action: &str, parsed: &[(Key, ModifierType)]) -> Vec<String> {
+    SHORTCUT_GROUPS
+        .iter()
+        .flat_map(|(group_name, _)| group_actions(state, group_name))
+        .filter(|other| other != action)
+        .filter(|other| {
+            state
+                .keybindings
+                .accels(other)
+                .iter()
+                .filter_map(|accel| gtk::accelerator_parse(accel.as_str()))
+                .any(|accel| parsed.contains(&accel))
+        })
+        .collect()
+}
+
+/// Sets the shortcut of `action`, or turns it off. A shortcut another action uses is
+/// only moved over once the user confirms it.
+pub(crate) fn set_keybinding(
+    state: &mut State,
+    action: String,
+    accel: Option<String>,
+    sender: &relm4::ComponentSender<State>,
+) {
+    let Some(accel) = accel else {
+        assign(state, &action, Vec::new());
+        return;
+    };
+    let Some(parsed) = gtk::accelerator_parse(accel.as_str()) else {
+        return;
+    };
+    let others = conflicts(state, &action, &[parsed]);
+    if others.is_empty() {
+        assign(state, &action, vec![accel]);
+        return;
+    }
+    let others: Vec<String> = others.iter().map(|other| label(other)).collect();
+    let dialog = AlertDialog::builder()
+        .heading("Shortcut Already in Use")
+        .body(format!(
+            "{} is used by “{}”. Use it for “{}” instead?",
+            gtk::accelerator_get_label(parsed.0, parsed.1),
+            others.join("”, “"),
+            label(&action)
+        ))
+        .close_response("cancel")
+        .default_response("reassign")
+        .build();
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("reassign", "Reassign");
+    dialog.set_response_appearance("reassign", libadwaita::ResponseAppearance::Destructive);
+    dialog.connect_response(
+        Some("reassign"),
+        clone!(
+            #[strong]
+            sender,
+            move |_, _| sender.input(Msg::ReassignKeybinding(action.clone(), accel.clone()))
+        ),
+    );
+    dialog.present(Some(&state.root));
+}
+
+pub(crate) fn reassign_keybinding(state: &mut State, action: String, accel: String) {
+    assign(state, &action, vec![accel]);
+}
+
+pub(crate) fn reset_keybinding(state: &mut State, action: String) {
+    let accels = default_accels(&action);
+    assign(state, &action, accels);
+}
+
+/// Takes every shortcut off the app's actions, so the keys can be recorded.
+fn clear_accels() {
+    let app = relm4::main_application();
+    for action in app.list_action_descriptions() {
+        app.set_accels_for_action(&action, &[]);
+    }
+}
+
+/// Gives every action its shortcuts from the keybindings again once recording is done,
+/// including one that was just set.
+pub(crate) fn reapply_keybindings(state: &mut State) {
+    restore_accels(&mut state.keymap);
+    apply_keybindings(&state.keybindings);
+    suspend_accels(&mut state.keymap);
+}
+
+/// Asks for a new shortcut for `action` by recording the next key combination.
+fn record_keybinding(row: &ActionRow, action: &str, sender: &relm4::ComponentSender<State>) {
+    let dialog = AlertDialog::builder()
+        .heading("Set Shortcut")
+        .body(format!(
+            "Press the new shortcut for “{}”, Backspace to turn it off or Escape to cancel",
+            label(action)
+        ))
+        .close_response("cancel")
+        .build();
+    dialog.add_response("cancel", "Cancel");
+    let keys = EventControllerKey::builder()
+        .propagation_phase(PropagationPhase::Capture)
+        .build();
+    keys.connect_key_pressed(clone!(
+        #[strong]
+        sender,
+        #[weak]
+        dialog,
+        #[to_owned]
+        action,
+        #[upgrade_or]
+        Propagation::Proceed,
+        move |_, key, _, modifiers| {
+            let modifiers = modifiers & gtk::accelerator_get_default_mod_mask();
+            let accel = match key {
+                Key::Escape if modifiers.is_empty() => None,
+                Key::BackSpace if modifiers.is_empty() => Some(None),
+                _ => {
+                    // Keys that type a character need a modifier, so typing still works
+                    let typed = key.to_unicode().is_some_and(|c| !c.is_control())
+                        && !modifiers.intersects(
+                            ModifierType::CONTROL_MASK
+                                | ModifierType::ALT_MASK
+                                | ModifierType::SUPER_MASK,
+                        );
+                    if typed || !gtk::accelerator_valid(key, modifiers) {
+                        return Propagation::Stop;
+                    }
+                    Some(Some(
+                        gtk::accelerator_name(key.to_lower(), modifiers).to_string(),
+                    ))
+                }
+            };
+            if let Some(accel) = accel {
+                sender.input(Msg::SetKeybinding(action.clone(), accel));
+            }
+            dialog.close();
+            Propagation::Stop
+        }
+    ));
+    dialog.add_controller(keys);
+
+    // Without this, pressing a shortcut that's in use would run its action. The shortcuts
+    // come back from the keybindings after any new one is set, as closing queues its
+    // message after `Msg::SetKeybinding`
+    clear_accels();
+    dialog.connect_closed(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ReapplyKeybindings)
+    ));
+    dialog.present(Some(row));
+}
+
+/// A preferences page listing every action, where its shortcut can be changed.
+pub(crate) fn keybindings_page(
+    state: &mut State,
+    sender: &relm4::ComponentSender<State>,
+) -> PreferencesPage {
+    let page = PreferencesPage::builder()
+        .title("Shortcuts")
+        .icon_name("preferences-desktop-keyboard-shortcuts-symbolic")
+        .description("Click an action to record a new shortcut for it")
+        .build();
+    state.keybindings.rows.clear();
+    for (group_name, title) in SHORTCUT_GROUPS {
+        let group = PreferencesGroup::builder().title(title).build();
+        for action in group_actions(state, group_name) {
+            let label = gtk::ShortcutLabel::builder()
+                .disabled_text("Disabled")
+                .valign(gtk::Align::Center)
+                .build();
+            let reset = gtk::Button::builder()
+                .icon_name("edit-undo-symbolic")
+                .tooltip_text("Reset to Default")
+                .valign(gtk::Align::Center)
+                .css_classes(vec!["flat"])
+                .build();
+            reset.connect_clicked(clone!(
+                #[strong]
+                sender,
+                #[to_owned]
+                action,
+                move |_| sender.input(Msg::ResetKeybinding(action.clone()))
+            ));
+            let row = ActionRow::builder()
+                .title(self::label(&action))
+                .subtitle(&action)
+                .activatable(true)
+                .build();
+            row.add_suffix(&label);
+            row.add_suffix(&reset);
+            row.connect_activated(clone!(
+                #[strong]
+                sender,
+                #[to_owned]
+                action,
+                move |row| record_keybinding(row, &action, &sender)
+            ));
+            group.add(&row);
+            state.keybindings.rows.push(KeybindingRow {
+                action,
+                label,
+                reset,
+            });
+        }
+        page.add(&group);
+    }
+    refresh_rows(&state.keybindings);
+    page
+}
@@ -0,0 +1,397 @@
+use std::{cell::Cell, rc::Rc};
+
+use gtk4::{
+    DeleteType, EventControllerKey, MovementStep, PropagationPhase,
+    gdk::{Key, ModifierType},
+    glib::{self, Propagation, clone},
+    prelude::*,
+};
+use relm4::gtk;
+use serde::{Deserialize, Serialize};
+use sourceview5::VimIMContext;
+
+use crate::app::model::{Msg, State};
+
+/// CSS class on editors handling keys through a keymap, so other key handlers stay out
+/// of its way.
+pub const KEYMAP_CLASS: &str = "modal-keymap";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Keymap {
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+impl Keymap {
+    pub const ALL: [Keymap; 3] = [Keymap::Default, Keymap::Vim, Keymap::Emacs];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Keymap::Default => "Default",
+            Keymap::Vim => "Vim",
+            Keymap::Emacs => "Emacs",
+        }
+    }
+
+    /// The accelerators the keymap uses itself, which the app's actions give up.
+    fn claimed_accelerators(self) -> &'static [&'static str] {
+        match self {
+            Keymap::Default => &[],
+            Keymap::Vim => &[
+                "<Control>a",
+                "<Control>b",
+                "<Control>d",
+                "<Control>e",
+                "<Control>f",
+                "<Control>i",
+                "<Control>o",
+                "<Control>r",
+                "<Control>u",
+                "<Control>v",
+                "<Control>w",
+                "<Control>x",
+                "<Control>y",
+            ],
+            Keymap::Emacs => &[
+                "<Control>a",
+                "<Control>b",
+                "<Control>d",
+                "<Control>e",
+                "<Control>f",
+                "<Control>g",
+                "<Control>k",
+                "<Control>n",
+                "<Control>p",
+                "<Control>r",
+                "<Control>s",
+                "<Control>v",
+                "<Control>w",
+                "<Control>x",
+                "<Control>y",
+                "<Control>space",
+                "<Control>slash",
+                "<Control>underscore",
+                "<Alt>b",
+                "<Alt>d",
+                "<Alt>f",
+                "<Alt>g",
+                "<Alt>v",
+                "<Alt>w",
+                "<Alt>x",
+                "<Alt>less",
+                "<Alt>greater",
+                "<Alt>percent",
+            ],
+        }
+    }
+}
+
+/// The active keymap, with its status bar indicator and the handlers it added.
+#[derive(Debug)]
+pub struct KeymapState {
+    pub keymap: Keymap,
+    /// Shows the mode, like `-- INSERT --` in Vim
+    pub indicator: gtk::Box,
+    mode_label: gtk::Label,
+    command_label: gtk::Label,
+    controllers: Vec<(sourceview5::View, EventControllerKey)>,
+    /// Accelerators taken from actions while the keymap uses their keys
+    suspended_accels: Vec<(String, Vec<glib::GString>)>,
+}
+
+pub fn setup_keymap() -> KeymapState {
+    let mode_label = gtk::Label::new(None);
+    let command_label = gtk::Label::builder()
+        .css_classes(vec!["monospace", "dim-label"])
+        .build();
+    let indicator = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .halign(gtk4::Align::Center)
+        .spacing(6)
+        .visible(false)
+        .build();
+    indicator.append(&mode_label);
+    indicator.append(&command_label);
+    KeymapState {
+        keymap: Keymap::Default,
+        indicator,
+        mode_label,
+        command_label,
+        controllers: Vec::new(),
+        suspended_accels: Vec::new(),
+    }
+}
+
+/// Removes the keymap's accelerators from the app's actions, remembering them.
+pub(crate) fn suspend_accels(keymap_state: &mut KeymapState) {
+    let claimed: Vec<_> = keymap_state
+        .keymap
+        .claimed_accelerators()
+        .iter()
+        .filter_map(|accel| gtk::accelerator_parse(*accel))
+        .collect();
+    if claimed.is_empty() {
+        return;
+    }
+    let app = relm4::main_application();
+    for action in app.list_action_descriptions() {
+        let accels = app.accels_for_action(&action);
+        let kept: Vec<&str> = accels
+            .iter()
+            .filter(|accel| {
+                gtk::accelerator_parse(accel.as_str())
+                    .is_none_or(|parsed| !claimed.contains(&parsed))
+            })
+            .map(|accel| accel.as_str())
+            .collect();
+        if kept.len() != accels.len() {
+            app.set_accels_for_action(&action, &kept);
+            keymap_state
+                .suspended_accels
+                .push((action.to_string(), accels.to_vec()));
+        }
+    }
+}
+
+pub(crate) fn restore_accels(keymap_state: &mut KeymapState) {
+    let app = relm4::main_application();
+    for (action, accels) in keymap_state.suspended_accels.drain(..) {
+        let accels: Vec<&str> = accels.iter().map(|accel| accel.as_str()).collect();
+        app.set_accels_for_action(&action, &accels);
+    }
+}
+
+/// Vim emulation through GtkSourceView's Vim input method.
+fn vim_controller(
+    view: &sourceview5::View,
+    keymap_state: &KeymapState,
+    sender: &relm4::ComponentSender<State>,
+) -> EventControllerKey {
+    let context = VimIMContext::new();
+    context.set_client_widget(Some(view));
+    context
+        .bind_property("command-bar-text", &keymap_state.mode_label, "label")
+        .sync_create()
+        .build();
+    context
+        .bind_property("command-text", &keymap_state.command_label, "label")
+        .sync_create()
+        .build();
+    context.connect_write(clone!(
+        #[strong]
+        sender,
+        move |_, _, path| match path {
+            Some(path) => sender.input(Msg::SaveAsResponse(path.into())),
+            None => sender.input(Msg::SaveFile),
+        }
+    ));
+    context.connect_edit(clone!(
+        #[strong]
+        sender,
+        move |_, _, path| match path {
+            Some(path) => sender.input(Msg::OpenResponse(path.into())),
+            None => sender.input(Msg::OpenRequest),
+        }
+    ));
+    // `:w` and `:e` are handled by the signals above
+    context.connect_execute_command(clone!(
+        #[strong]
+        sender,
+        move |_, command| match command.trim() {
+            ":q" | ":q!" | ":quit" | ":qa" | ":qa!" => {
+                sender.input(Msg::Quit);
+                true
+            }
+            ":wq" | ":x" | ":wqa" | ":xa" => {
+                sender.input(Msg::SaveAndQuit);
+                true
+            }
+            ":new" | ":enew" => {
+                sender.input(Msg::NewFile);
+                true
+            }
+            ":sp" | ":split" => {
+                sender.input(Msg::SplitEditor(gtk4::Orientation::Vertical));
+                true
+            }
+            ":vs" | ":vsplit" => {
+                sender.input(Msg::SplitEditor(gtk4::Orientation::Horizontal));
+                true
+            }
+            _ => false,
+        }
+    ));
+    let controller = EventControllerKey::builder()
+        .propagation_phase(PropagationPhase::Capture)
+        .build();
+    controller.set_im_context(Some(&context));
+    controller
+}
+
+/// Emacs style editing keys, with a `C-x` prefix for file and window commands.
+fn emacs_controller(
+    view: &sourceview5::View,
+    keymap_state: &KeymapState,
+    sender: &relm4::ComponentSender<State>,
+) -> EventControllerKey {
+    let controller = EventControllerKey::builder()
+        .propagation_phase(PropagationPhase::Capture)
+        .build();
+    let prefix = Rc::new(Cell::new(false));
+    let mark_active = Rc::new(Cell::new(false));
+    keymap_state.mode_label.set_label("Emacs");
+    controller.connect_key_pressed(clone!(
+        #[strong]
+        sender,
+        #[weak]
+        view,
+        #[weak(rename_to = command_label)]
+        keymap_state.command_label,
+        #[upgrade_or]
+        Propagation::Proceed,
+        move |_, key, _, modifiers| {
+            let control = modifiers.contains(ModifierType::CONTROL_MASK);
+            let meta = modifiers.contains(ModifierType::ALT_MASK);
+            let key = key.to_lower();
+            let buffer = view.buffer();
+            let extend = mark_active.get();
+            let move_cursor = |step, count| view.emit_move_cursor(step, count, extend);
+
+            if prefix.replace(false) {
+                command_label.set_label("");
+                match (control, key) {
+                    (true, Key::s) => sender.input(Msg::SaveFile),
+                    (true, Key::f) => sender.input(Msg::OpenRequest),
+                    (true, Key::w) => sender.input(Msg::SaveAsRequest),
+                    (true, Key::c) => sender.input(Msg::Quit),
+                    (false, Key::h) => {
+                        buffer.select_range(&buffer.end_iter(), &buffer.start_iter())
+                    }
+                    (false, Key::u) => view.emit_by_name::<()>("undo", &[]),
+                    (false, Key::b) => sender.input(Msg::QuickOpen),
+                    (false, Key::k) => sender.input(Msg::NewFile),
+                    (false, Key::_0) => sender.input(Msg::CloseSplit),
+                    (false, Key::_2) => sender.input(Msg::SplitEditor(gtk4::Orientation::Vertical)),
+                    (false, Key::_3) => {
+                        sender.input(Msg::SplitEditor(gtk4::Orientation::Horizontal))
+                    }
+                    (false, Key::o) => sender.input(Msg::FocusNextSplit),
+                    _ => {}
+                }
+                return Propagation::Stop;
+            }
+
+            match (control, meta, key) {
+                (true, false, Key::x) => {
+                    prefix.set(true);
+                    command_label.set_label("C-x-");
+                }
+                (true, false, Key::f) => move_cursor(MovementStep::VisualPositions, 1),
+                (true, false, Key::b) => move_cursor(MovementStep::VisualPositions, -1),
+                (true, false, Key::n) => move_cursor(MovementStep::DisplayLines, 1),
+                (true, false, Key::p) => move_cursor(MovementStep::DisplayLines, -1),
+                (true, false, Key::a) => move_cursor(MovementStep::DisplayLineEnds, -1),
+                (true, false, Key::e) => move_cursor(MovementStep::DisplayLineEnds, 1),
+                (true, false, Key::v) => move_cursor(MovementStep::Pages, 1),
+                (false, true, Key::v) => move_cursor(MovementStep::Pages, -1),
+                (false, true, Key::f) => move_cursor(MovementStep::Words, 1),
+                (false, true, Key::b) => move_cursor(MovementStep::Words, -1),
+                (false, true, Key::less) => move_cursor(MovementStep::BufferEnds, -1),
+                (false, true, Key::greater) => move_cursor(MovementStep::BufferEnds, 1),
+                (true, false, Key::d) => view.emit_delete_from_cursor(DeleteType::Chars, 1),
+                (false, true, Key::d) => view.emit_delete_from_cursor(DeleteType::WordEnds, 1),
+                (true, false, Key::k) => {
+                    // Kill to the end of the line, or the line break at the end of one
+                    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                    let mut end = cursor;
+                    if end.ends_line() {
+                        end.forward_char();
+                    } else {
+                        end.forward_to_line_end();
+                    }
+                    buffer.select_range(&cursor, &end);
+                    view.emit_cut_clipboard();
+                    mark_active.set(false);
+                }
+                (true, false, Key::space) => {
+                    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                    buffer.place_cursor(&cursor);
+                    mark_active.set(true);
+                }
+                (true, false, Key::g) => {
+                    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                    buffer.place_cursor(&cursor);
+                    mark_active.set(false);
+                }
+                (true, false, Key::w) => {
+                    view.emit_cut_clipboard();
+                    mark_active.set(false);
+                }
+                (false, true, Key::w) => {
+                    view.emit_copy_clipboard();
+                    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                    buffer.place_cursor(&cursor);
+                    mark_active.set(false);
+                }
+                (true, false, Key::y) => view.emit_paste_clipboard(),
+                (true, false, Key::slash | Key::underscore) => view.emit_by_name::<()>("undo", &[]),
+                (true, false, Key::s | Key::r) => sender.input(Msg::ShowFind),
+                (false, true, Key::percent) => sender.input(Msg::ShowReplace),
+                (false, true, Key::x) => sender.input(Msg::ShowCommandPalette),
+                (false, true, Key::g) => sender.input(Msg::ShowGoToLine),
+                _ => return Propagation::Proceed,
+            }
+            Propagation::Stop
+        }
+    ));
+    controller
+}
+
+/// Gives `view` the handlers for the active keymap.
+pub(crate) fn attach_keymap(
+    keymap_state: &mut KeymapState,
+    view: &sourceview5::View,
+    sender: &relm4::ComponentSender<State>,
+) {
+    let controller = match keymap_state.keymap {
+        Keymap::Default => return,
+        Keymap::Vim => vim_controller(view, keymap_state, sender),
+        Keymap::Emacs => emacs_controller(view, keymap_state, sender),
+    };
+    view.add_controller(controller.clone());
+    view.add_css_class(KEYMAP_CLASS);
+    keymap_state.controllers.push((view.clone(), controller));
+}
+
+/// Drops the handlers of a view that is going away.
+pub(crate) fn detach_keymap(keymap_state: &mut KeymapState, view: &sourceview5::View) {
+    keymap_state
+        .controllers
+        .retain(|(controller_view, _)| controller_view != view);
+}
+
+/// Switches every editor to `keymap`.
+pub(crate) fn set_keymap(
+    state: &mut State,
+    keymap: Keymap,
+    sender: &relm4::ComponentSender<State>,
+) {
+    let keymap_state = &mut state.keymap;
+    for (view, controller) in keymap_state.controllers.drain(..) {
+        view.remove_controller(&controller);
+        view.remove_css_class(KEYMAP_CLASS);
+    }
+    restore_accels(keymap_state);
+    keymap_state.keymap = keymap;
+    keymap_state.mode_label.set_label("");
+    keymap_state.command_label.set_label("");
+    keymap_state
+        .indicator
+        .set_visible(keymap != Keymap::Default);
+    suspend_accels(keymap_state);
+    for pane in &state.splits.panes {
+        attach_keymap(&mut state.keymap, &pane.view, sender);
+    }
+}
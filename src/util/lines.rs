@@ -0,0 +1,363 @@
+use std::cmp::Ordering;
+
+use gtk4::{TextIter, prelude::*};
+use libadwaita::Toast;
+use sourceview5::prelude::*;
+
+use crate::app::model::State;
+
+/// How `sort_lines` compares and filters lines.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SortOptions {
+    pub ignore_case: bool,
+    /// Compare the number each line starts with before the text
+    pub numeric: bool,
+    /// Drop lines that compare equal to the line before them
+    pub unique: bool,
+}
+
+/// The first and last line touched by the selection, or the cursor's line.
+fn selected_lines(buffer: &sourceview5::Buffer) -> (i32, i32) {
+    let (start, end) = buffer.selection_bounds().unwrap_or_else(|| {
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        (cursor, cursor)
+    });
+    // A selection ending at the start of a line doesn't include that line
+    if end.line() > start.line() && end.starts_line() {
+        (start.line(), end.line() - 1)
+    } else {
+        (start.line(), end.line())
+    }
+}
+
+fn line_start(buffer: &sourceview5::Buffer, line: i32) -> TextIter {
+    buffer
+        .iter_at_line(line)
+        .unwrap_or_else(|| buffer.end_iter())
+}
+
+fn line_end(buffer: &sourceview5::Buffer, line: i32) -> TextIter {
+    let mut iter = line_start(buffer, line);
+    if !iter.ends_line() {
+        iter.forward_to_line_end();
+    }
+    iter
+}
+
+/// The text of the lines from `first` to `last`, without their line endings.
+fn lines_text(buffer: &sourceview5::Buffer, first: i32, last: i32) -> Vec<String> {
+    (first..=last)
+        .map(|line| {
+            buffer
+                .text(&line_start(buffer, line), &line_end(buffer, line), true)
+                .to_string()
+        })
+        .collect()
+}
+
+/// Swaps the lines from `first` to `last` for `lines`, leaving line endings outside alone.
+fn replace_lines(buffer: &sourceview5::Buffer, first: i32, last: i32, lines: &[String]) {
+    let mut start = line_start(buffer, first);
+    let mut end = line_end(buffer, last);
+    buffer.delete(&mut start, &mut end);
+    buffer.insert(&mut start, &lines.join("\n"));
+}
+
+/// The cursor and selection bound as (line, column) pairs.
+fn selection_positions(buffer: &sourceview5::Buffer) -> [(i32, i32); 2] {
+    [buffer.get_insert(), buffer.selection_bound()].map(|mark| {
+        let iter = buffer.iter_at_mark(&mark);
+        (iter.line(), iter.line_offset())
+    })
+}
+
+/// Puts the cursor and selection bound back, `delta` lines from where they were.
+fn restore_selection(buffer: &sourceview5::Buffer, positions: [(i32, i32); 2], delta: i32) {
+    let [cursor, bound] = positions.map(|(line, column)| {
+        let mut iter = line_start(buffer, line + delta);
+        iter.set_line_offset(column.min(line_end(buffer, line + delta).line_offset()));
+        iter
+    });
+    buffer.select_range(&cursor, &bound);
+}
+
+/// Selects the lines from `first` to `last`.
+fn select_lines(buffer: &sourceview5::Buffer, first: i32, last: i32) {
+    buffer.select_range(&line_end(buffer, last), &line_start(buffer, first));
+}
+
+/// Runs `edit` as a single undo step, after dropping any extra carets.
+fn edit_lines(state: &State, edit: impl FnOnce(&sourceview5::Buffer)) {
+    state.multi_cursor.clear();
+    state.buffer.begin_user_action();
+    edit(&state.buffer);
+    state.buffer.end_user_action();
+    state
+        .editor
+        .scroll_to_mark(&state.buffer.get_insert(), 0.0, false, 0.0, 0.0);
+}
+
+/// Moves the selected lines past the line above or below them.
+pub(crate) fn move_lines(state: &mut State, up: bool) {
+    let buffer = &state.buffer;
+    let (first, last) = selected_lines(buffer);
+    if (up && first == 0) || (!up && last == buffer.line_count() - 1) {
+        return;
+    }
+    let positions = selection_positions(buffer);
+    edit_lines(state, |buffer| {
+        if up {
+            let mut lines = lines_text(buffer, first - 1, last);
+            lines.rotate_left(1);
+            replace_lines(buffer, first - 1, last, &lines);
+            restore_selection(buffer, positions, -1);
+        } else {
+            let mut lines = lines_text(buffer, first, last + 1);
+            lines.rotate_right(1);
+            replace_lines(buffer, first, last + 1, &lines);
+            restore_selection(buffer, positions, 1);
+        }
+    });
+}
+
+/// Duplicates the selection, or the cursor's line when nothing is selected.
+pub(crate) fn duplicate_lines(state: &mut State) {
+    if let Some((start, end)) = state.buffer.selection_bounds() {
+        let text = state.buffer.text(&start, &end, true);
+        let offset = end.offset();
+        edit_lines(state, |buffer| {
+            let mut iter = buffer.iter_at_offset(offset);
+            buffer.insert(&mut iter, &text);
+            buffer.select_range(&iter, &buffer.iter_at_offset(offset));
+        });
+        return;
+    }
+    let (first, last) = selected_lines(&state.buffer);
+    let positions = selection_positions(&state.buffer);
+    edit_lines(state, |buffer| {
+        let lines = lines_text(buffer, first, last);
+        let mut iter = line_end(buffer, last);
+        buffer.insert(&mut iter, &format!("\n{}", lines.join("\n")));
+        restore_selection(buffer, positions, last - first + 1);
+    });
+}
+
+/// Deletes the selected lines, line endings included.
+pub(crate) fn delete_lines(state: &mut State) {
+    let (first, last) = selected_lines(&state.buffer);
+    let column = selection_positions(&state.buffer)[0].1;
+    edit_lines(state, |buffer| {
+        let (mut start, mut end) = if last < buffer.line_count() - 1 {
+            (line_start(buffer, first), line_start(buffer, last + 1))
+        } else if first > 0 {
+            (line_end(buffer, first - 1), buffer.end_iter())
+        } else {
+            (buffer.start_iter(), buffer.end_iter())
+        };
+        buffer.delete(&mut start, &mut end);
+        let line = first.min(buffer.line_count() - 1);
+        restore_selection(buffer, [(line, column); 2], 0);
+    });
+}
+
+/// Joins the selected lines into one, or the cursor's line with the next, replacing
+/// each line break and the indentation after it with a single space.
+pub(crate) fn join_lines(state: &mut State) {
+    let buffer = &state.buffer;
+    let (first, mut last) = selected_lines(buffer);
+    if first == last {
+        last += 1;
+    }
+    if last >= buffer.line_count() {
+        return;
+    }
+    edit_lines(state, |buffer| {
+        let lines = lines_text(buffer, first, last);
+        let mut joined = lines[0].trim_end().to_string();
+        let mut join_column = joined.chars().count();
+        for line in &lines[1..] {
+            let line = line.trim();
+            join_column = joined.chars().count();
+            if !line.is_empty() {
+                if !joined.is_empty() {
+                    joined.push(' ');
+                }
+                joined.push_str(line);
+            }
+        }
+        replace_lines(buffer, first, last, &[joined]);
+        restore_selection(buffer, [(first, join_column as i32); 2], 0);
+    });
+}
+
+/// The number at the start of `line`, if it starts with one.
+fn leading_number(line: &str) -> Option<f64> {
+    let line = line.trim_start();
+    let length = line
+        .char_indices()
+        .take_while(|(index, c)| {
+            c.is_ascii_digit() || *c == '.' || (*index == 0 && (*c == '-' || *c == '+'))
+        })
+        .count();
+    line[..length].parse().ok()
+}
+
+fn compare_lines(a: &str, b: &str, options: SortOptions) -> Ordering {
+    let numbers = if options.numeric {
+        match (leading_number(a), leading_number(b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            // Lines without a number go first
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        }
+    } else {
+        Ordering::Equal
+    };
+    numbers.then_with(|| {
+        if options.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        }
+    })
+}
+
+/// The lines to sort or reverse: the selected lines, or the whole document when the
+/// selection doesn't span more than one line.
+fn lines_to_reorder(buffer: &sourceview5::Buffer) -> (i32, i32) {
+    match selected_lines(buffer) {
+        (first, last) if first != last => (first, last),
+        _ => (0, buffer.line_count() - 1),
+    }
+}
+
+/// Sorts the selected lines, or the whole document.
+pub(crate) fn sort_lines(state: &mut State, options: SortOptions) {
+    let (first, last) = lines_to_reorder(&state.buffer);
+    edit_lines(state, |buffer| {
+        let mut lines = lines_text(buffer, first, last);
+        lines.sort_by(|a, b| compare_lines(a, b, options));
+        if options.unique {
+            lines.dedup_by(|a, b| compare_lines(a, b, options) == Ordering::Equal);
+        }
+        let removed = (last - first + 1) - lines.len() as i32;
+        replace_lines(buffer, first, last, &lines);
+        select_lines(buffer, first, last - removed);
+    });
+}
+
+/// Reverses the order of the selected lines, or the whole document.
+pub(crate) fn reverse_lines(state: &mut State) {
+    let (first, last) = lines_to_reorder(&state.buffer);
+    edit_lines(state, |buffer| {
+        let mut lines = lines_text(buffer, first, last);
+        lines.reverse();
+        replace_lines(buffer, first, last, &lines);
+        select_lines(buffer, first, last);
+    });
+}
+
+/// A comment marker from the buffer language's metadata, toasting when there isn't one.
+fn comment_metadata(state: &State, keys: &[&str]) -> Option<Vec<String>> {
+    let Some(language) = state.buffer.language() else {
+        state.toast_overlay.add_toast(Toast::new(
+            "Comments need a language to be set for the file",
+        ));
+        return None;
+    };
+    let markers: Option<Vec<String>> = keys
+        .iter()
+        .map(|key| language.metadata(key).map(|marker| marker.to_string()))
+        .collect();
+    if markers.is_none() {
+        state.toast_overlay.add_toast(Toast::new(&format!(
+            "{} doesn't have this kind of comment",
+            language.name()
+        )));
+    }
+    markers
+}
+
+fn indentation(line: &str) -> usize {
+    line.chars().take_while(|c| c.is_whitespace()).count()
+}
+
+/// Comments out the selected lines with the language's line comment, or uncomments them
+/// when every non-blank line is already commented.
+pub(crate) fn toggle_line_comment(state: &mut State) {
+    let Some(markers) = comment_metadata(state, &["line-comment-start"]) else {
+        return;
+    };
+    let marker = &markers[0];
+    let (first, last) = selected_lines(&state.buffer);
+    let lines = lines_text(&state.buffer, first, last);
+    let code_lines = || {
+        (first..)
+            .zip(&lines)
+            .filter(|(_, text)| !text.trim().is_empty())
+    };
+    let commented = code_lines().all(|(_, text)| text.trim_start().starts_with(marker.as_str()));
+    let column = code_lines()
+        .map(|(_, text)| indentation(text))
+        .min()
+        .unwrap_or(0) as i32;
+
+    edit_lines(state, |buffer| {
+        for (line, text) in code_lines() {
+            if commented {
+                let indent = indentation(text) as i32;
+                let after = &text.trim_start()[marker.len()..];
+                let length = marker.chars().count() as i32 + after.starts_with(' ') as i32;
+                let mut start = line_start(buffer, line);
+                start.set_line_offset(indent);
+                let mut end = start;
+                end.forward_chars(length);
+                buffer.delete(&mut start, &mut end);
+            } else {
+                let mut iter = line_start(buffer, line);
+                iter.set_line_offset(column);
+                buffer.insert(&mut iter, &format!("{marker} "));
+            }
+        }
+    });
+}
+
+/// Wraps the selection, or the cursor's line, in the language's block comment, or
+/// unwraps it when it's already a block comment.
+pub(crate) fn toggle_block_comment(state: &mut State) {
+    let Some(markers) = comment_metadata(state, &["block-comment-start", "block-comment-end"])
+    else {
+        return;
+    };
+    let (open, close) = (&markers[0], &markers[1]);
+    let buffer = &state.buffer;
+    let (start, end) = buffer.selection_bounds().unwrap_or_else(|| {
+        let line = buffer.iter_at_mark(&buffer.get_insert()).line();
+        let text = &lines_text(buffer, line, line)[0];
+        let mut start = line_start(buffer, line);
+        start.set_line_offset(indentation(text) as i32);
+        let mut end = line_end(buffer, line);
+        end.backward_chars((text.chars().count() - text.trim_end().chars().count()) as i32);
+        (start, end)
+    });
+    let text = buffer.text(&start, &end, true).to_string();
+    let (start_offset, end_offset) = (start.offset(), end.offset());
+
+    edit_lines(state, |buffer| {
+        let commented = text
+            .strip_prefix(open.as_str())
+            .and_then(|text| text.strip_suffix(close.as_str()));
+        let replacement = match commented {
+            Some(inner) => inner
+                .strip_prefix(' ')
+                .and_then(|inner| inner.strip_suffix(' '))
+                .unwrap_or(inner)
+                .to_string(),
+            None => format!("{open} {text} {close}"),
+        };
+        let mut start = buffer.iter_at_offset(start_offset);
+        let mut end = buffer.iter_at_offset(end_offset);
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &replacement);
+        buffer.select_range(&start, &buffer.iter_at_offset(start_offset));
+    });
+}
@@ -0,0 +1,538 @@
+use std::{cell::RefCell, path::Path, rc::Rc, time::Duration};
+
+use gtk4::{
+    GestureClick, ScrolledWindow, TextBuffer, TextMark, TextView,
+    gdk::{self, RGBA},
+    gdk_pixbuf::Pixbuf,
+    glib::{self, ControlFlow, SourceId, clone},
+    prelude::*,
+};
+use libadwaita::{AlertDialog, ResponseAppearance, Toast, prelude::*};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use sourceview5::prelude::*;
+
+use crate::app::model::{Msg, State};
+
+/// Wider images are scaled down to fit
+const MAX_IMAGE_WIDTH: i32 = 640;
+
+/// The rendered Markdown pane beside the editor.
+#[derive(Debug)]
+pub struct MarkdownPreview {
+    pub container: ScrolledWindow,
+    pub view: TextView,
+    /// The start of each rendered block, by the source line it came from
+    anchors: Vec<(i32, TextMark)>,
+    /// Link ranges in the rendered text, as character offsets
+    links: Vec<(i32, i32, String)>,
+}
+
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES
+}
+
+fn create_tags(buffer: &TextBuffer) {
+    let shade = RGBA::new(0.5, 0.5, 0.5, 0.15);
+    let dim = RGBA::new(0.5, 0.5, 0.5, 1.0);
+    for (level, scale) in [2.0, 1.6, 1.35, 1.15, 1.0, 0.9].iter().enumerate() {
+        buffer.create_tag(
+            Some(&format!("h{}", level + 1)),
+            &[
+                ("weight", &700),
+                ("scale", scale),
+                ("pixels-above-lines", &8),
+            ],
+        );
+    }
+    buffer.create_tag(Some("strong"), &[("weight", &700)]);
+    buffer.create_tag(Some("emphasis"), &[("style", &gtk4::pango::Style::Italic)]);
+    buffer.create_tag(Some("strikethrough"), &[("strikethrough", &true)]);
+    buffer.create_tag(
+        Some("code"),
+        &[("family", &"monospace"), ("background-rgba", &shade)],
+    );
+    buffer.create_tag(
+        Some("code-block"),
+        &[
+            ("family", &"monospace"),
+            ("paragraph-background-rgba", &shade),
+            ("left-margin", &24),
+            ("wrap-mode", &gtk4::WrapMode::None),
+        ],
+    );
+    buffer.create_tag(
+        Some("quote"),
+        &[
+            ("left-margin", &24),
+            ("style", &gtk4::pango::Style::Italic),
+            ("foreground-rgba", &dim),
+        ],
+    );
+    buffer.create_tag(
+        Some("link"),
+        &[
+            ("underline", &gtk4::pango::Underline::Single),
+            ("foreground-rgba", &RGBA::new(0.21, 0.52, 0.89, 1.0)),
+        ],
+    );
+    buffer.create_tag(Some("dim"), &[("foreground-rgba", &dim)]);
+    buffer.create_tag(Some("table"), &[("family", &"monospace")]);
+}
+
+pub fn setup_markdown_preview(
+    editor: &sourceview5::View,
+    buffer: &sourceview5::Buffer,
+    sender: &relm4::ComponentSender<State>,
+) -> MarkdownPreview {
+    let view = TextView::builder()
+        .editable(false)
+        .cursor_visible(false)
+        .wrap_mode(gtk4::WrapMode::WordChar)
+        .left_margin(24)
+        .right_margin(24)
+        .top_margin(12)
+        .bottom_margin(12)
+        .build();
+    create_tags(&view.buffer());
+    let container = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .hexpand(true)
+        .visible(false)
+        .child(&view)
+        .build();
+
+    // Events
+    let click = GestureClick::new();
+    click.connect_released(clone!(
+        #[strong]
+        sender,
+        #[weak]
+        view,
+        move |_, _, x, y| {
+            let (x, y) =
+                view.window_to_buffer_coords(gtk4::TextWindowType::Widget, x as i32, y as i32);
+            if let Some(iter) = view.iter_at_location(x, y) {
+                sender.input(Msg::PreviewClicked(iter.offset()));
+            }
+        }
+    ));
+    view.add_controller(click);
+    attach_markdown_preview(editor, buffer, &container, sender);
+
+    MarkdownPreview {
+        container,
+        view,
+        anchors: Vec::new(),
+        links: Vec::new(),
+    }
+}
+
+/// Re-renders the preview when the editor's buffer changes, and follows its scrolling.
+pub fn attach_markdown_preview(
+    editor: &sourceview5::View,
+    buffer: &sourceview5::Buffer,
+    container: &ScrolledWindow,
+    sender: &relm4::ComponentSender<State>,
+) {
+    watch_preview_buffer(buffer, container, sender);
+    attach_preview_scroll(editor, container, sender);
+}
+
+/// Re-renders the preview when a buffer changes.
+pub fn watch_preview_buffer(
+    buffer: &sourceview5::Buffer,
+    container: &ScrolledWindow,
+    sender: &relm4::ComponentSender<State>,
+) {
+    // Re-render once typing pauses
+    let pending: Rc<RefCell<Option<SourceId>>> = Rc::default();
+    buffer.connect_changed(clone!(
+        #[strong]
+        sender,
+        #[weak]
+        container,
+        move |_| {
+            if !container.is_visible() {
+                return;
+            }
+            if let Some(source) = pending.borrow_mut().take() {
+                source.remove();
+            }
+            *pending.borrow_mut() = Some(glib::timeout_add_local(
+                Duration::from_millis(200),
+                clone!(
+                    #[strong]
+                    sender,
+                    #[strong]
+                    pending,
+                    move || {
+                        pending.borrow_mut().take();
+                        sender.input(Msg::RefreshPreview);
+                        ControlFlow::Break
+                    }
+                ),
+            ));
+        }
+    ));
+}
+
+/// Keeps the preview scrolled along with an editor.
+pub fn attach_preview_scroll(
+    editor: &sourceview5::View,
+    container: &ScrolledWindow,
+    sender: &relm4::ComponentSender<State>,
+) {
+    if let Some(adjustment) = editor.vadjustment() {
+        adjustment.connect_value_changed(clone!(
+            #[strong]
+            sender,
+            #[weak]
+            container,
+            move |_| {
+                if container.is_visible() {
+                    sender.input(Msg::SyncPreviewScroll);
+                }
+            }
+        ));
+    }
+}
+
+fn is_markdown(state: &State) -> bool {
+    state
+        .buffer
+        .language()
+        .is_some_and(|language| language.id() == "markdown")
+}
+
+/// Loads a local image, scaled down to fit the preview.
+fn load_image(document_dir: &Path, url: &str) -> Option<gdk::Texture> {
+    if url.contains("://") && !url.starts_with("file://") {
+        return None;
+    }
+    let path = document_dir.join(url.trim_start_matches("file://"));
+    let (_, width, _) = Pixbuf::file_info(&path)?;
+    let pixbuf = if width > MAX_IMAGE_WIDTH {
+        Pixbuf::from_file_at_scale(&path, MAX_IMAGE_WIDTH, -1, true).ok()?
+    } else {
+        Pixbuf::from_file(&path).ok()?
+    };
+    Some(gdk::Texture::for_pixbuf(&pixbuf))
+}
+
+/// Makes sure the rendered text ends with a line break, or an empty line with `blank`.
+fn end_block(buffer: &TextBuffer, blank: bool) {
+    let end = buffer.end_iter();
+    if end.offset() == 0 {
+        return;
+    }
+    let mut tail = end;
+    tail.backward_chars(2);
+    let tail = buffer.text(&tail, &end, false);
+    let needed = if blank { 2 } else { 1 };
+    let present = tail.chars().rev().take_while(|c| *c == '\n').count();
+    for _ in present..needed {
+        buffer.insert(&mut buffer.end_iter(), "\n");
+    }
+}
+
+/// Renders `markdown` into the preview buffer, recording where each source block starts.
+fn render(preview: &mut MarkdownPreview, markdown: &str, document_dir: &Path) {
+    let buffer = preview.view.buffer();
+    for (_, mark) in preview.anchors.drain(..) {
+        buffer.delete_mark(&mark);
+    }
+    preview.links.clear();
+    buffer.set_text("");
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(markdown.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    let source_line =
+        |offset: usize| line_starts.partition_point(|start| *start <= offset) as i32 - 1;
+
+    let mut tags: Vec<&'static str> = Vec::new();
+    // The next number of each ordered list, None for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut link_start: Option<(i32, String)> = None;
+    let mut in_image = false;
+    let insert = |text: &str, tags: &[&str]| {
+        buffer.insert_with_tags_by_name(&mut buffer.end_iter(), text, tags);
+    };
+
+    for (event, range) in Parser::new_ext(markdown, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(tag) => {
+                let is_block = matches!(
+                    tag,
+                    Tag::Paragraph
+                        | Tag::Heading { .. }
+                        | Tag::CodeBlock(_)
+                        | Tag::BlockQuote(_)
+                        | Tag::List(_)
+                        | Tag::Item
+                        | Tag::Table(_)
+                );
+                if is_block {
+                    match tag {
+                        Tag::Item => end_block(&buffer, false),
+                        // Paragraphs in list items stay tight
+                        Tag::Paragraph if !lists.is_empty() => {}
+                        Tag::List(_) if !lists.is_empty() => end_block(&buffer, false),
+                        _ => end_block(&buffer, true),
+                    }
+                    let mark = buffer.create_mark(None, &buffer.end_iter(), true);
+                    preview.anchors.push((source_line(range.start), mark));
+                }
+                match tag {
+                    Tag::Heading { level, .. } => tags.push(match level {
+                        HeadingLevel::H1 => "h1",
+                        HeadingLevel::H2 => "h2",
+                        HeadingLevel::H3 => "h3",
+                        HeadingLevel::H4 => "h4",
+                        HeadingLevel::H5 => "h5",
+                        HeadingLevel::H6 => "h6",
+                    }),
+                    Tag::Strong => tags.push("strong"),
+                    Tag::Emphasis => tags.push("emphasis"),
+                    Tag::Strikethrough => tags.push("strikethrough"),
+                    Tag::CodeBlock(kind) => {
+                        tags.push("code-block");
+                        if let CodeBlockKind::Fenced(language) = kind
+                            && !language.is_empty()
+                        {
+                            insert(&format!("{language}\n"), &["code-block", "dim"]);
+                        }
+                    }
+                    Tag::BlockQuote(_) => tags.push("quote"),
+                    Tag::List(start) => lists.push(start),
+                    Tag::Item => {
+                        let indent = "    ".repeat(lists.len().saturating_sub(1));
+                        let marker = match lists.last_mut() {
+                            Some(Some(number)) => {
+                                *number += 1;
+                                format!("{indent}{}. ", *number - 1)
+                            }
+                            _ => format!("{indent}•  "),
+                        };
+                        insert(&marker, &tags);
+                    }
+                    Tag::Link { dest_url, .. } => {
+                        link_start = Some((buffer.end_iter().offset(), dest_url.to_string()));
+                        tags.push("link");
+                    }
+                    Tag::Image { dest_url, .. } => match load_image(document_dir, &dest_url) {
+                        Some(texture) => {
+                            buffer.insert_paintable(&mut buffer.end_iter(), &texture);
+                            in_image = true;
+                        }
+                        None => {
+                            insert("[image: ", &["dim"]);
+                            tags.push("dim");
+                        }
+                    },
+                    Tag::TableCell => insert("│ ", &["table"]),
+                    _ => {}
+                }
+            }
+            Event::End(tag_end) => match tag_end {
+                TagEnd::Heading(_)
+                | TagEnd::Strong
+                | TagEnd::Emphasis
+                | TagEnd::Strikethrough
+                | TagEnd::BlockQuote(_) => {
+                    tags.pop();
+                }
+                TagEnd::CodeBlock => {
+                    tags.pop();
+                    // Drop the code block's own trailing newline
+                    let mut end = buffer.end_iter();
+                    let mut last = end;
+                    if last.backward_char() && last.char() == '\n' {
+                        buffer.delete(&mut last, &mut end);
+                    }
+                }
+                TagEnd::List(_) => {
+                    lists.pop();
+                }
+                TagEnd::Link => {
+                    tags.pop();
+                    if let Some((start, url)) = link_start.take() {
+                        preview.links.push((start, buffer.end_iter().offset(), url));
+                    }
+                }
+                TagEnd::Image => {
+                    if in_image {
+                        in_image = false;
+                    } else {
+                        tags.pop();
+                        insert("]", &["dim"]);
+                    }
+                }
+                TagEnd::TableHead | TagEnd::TableRow => insert("│\n", &["table"]),
+                TagEnd::TableCell => insert(" ", &["table"]),
+                _ => {}
+            },
+            // Alt text is only shown when the image couldn't be loaded
+            Event::Text(_) if in_image => {}
+            Event::Text(text) => insert(&text, &tags),
+            Event::Code(code) => {
+                let mut code_tags = tags.clone();
+                code_tags.push("code");
+                insert(&code, &code_tags);
+            }
+            Event::Html(html) | Event::InlineHtml(html) => insert(&html, &["dim"]),
+            Event::SoftBreak => insert(" ", &tags),
+            Event::HardBreak => insert("\n", &tags),
+            Event::Rule => {
+                end_block(&buffer, true);
+                insert(&"─".repeat(40), &["dim"]);
+            }
+            Event::TaskListMarker(checked) => {
+                insert(if checked { "☑ " } else { "☐ " }, &tags);
+            }
+            Event::FootnoteReference(name) => insert(&format!("[{name}]"), &["dim"]),
+            _ => {}
+        }
+    }
+}
+
+/// Re-renders the preview from the buffer, hiding it when the document isn't Markdown.
+pub(crate) fn refresh_preview(state: &mut State) {
+    if !state.markdown_preview.container.is_visible() {
+        return;
+    }
+    if !is_markdown(state) {
+        state.markdown_preview.container.set_visible(false);
+        return;
+    }
+    let markdown = state
+        .buffer
+        .text(&state.buffer.start_iter(), &state.buffer.end_iter(), true);
+    let document_dir = state
+        .current_file_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    render(&mut state.markdown_preview, &markdown, &document_dir);
+    sync_preview_scroll(state);
+}
+
+pub(crate) fn toggle_preview(state: &mut State) {
+    let container = &state.markdown_preview.container;
+    if container.is_visible() {
+        container.set_visible(false);
+    } else if is_markdown(state) {
+        container.set_visible(true);
+        refresh_preview(state);
+    } else {
+        state.toast_overlay.add_toast(Toast::new(
+            "The preview is only available for Markdown files",
+        ));
+    }
+}
+
+/// Scrolls the preview to the block shown at the top of the editor.
+pub(crate) fn sync_preview_scroll(state: &State) {
+    let preview = &state.markdown_preview;
+    let top = state.editor.visible_rect().y();
+    let (top_line, _) = state.editor.line_at_y(top);
+    let target = preview
+        .anchors
+        .iter()
+        .take_while(|(line, _)| *line <= top_line.line())
+        .last()
+        .or(preview.anchors.first());
+    if let Some((_, mark)) = target {
+        preview.view.scroll_to_mark(mark, 0.0, true, 0.0, 0.0);
+    }
+}
+
+/// Opens the link under a click in the preview, if there is one.
+pub(crate) fn open_preview_link(state: &mut State, offset: i32) {
+    let Some((_, _, url)) = state
+        .markdown_preview
+        .links
+        .iter()
+        .find(|(start, end, _)| (*start..*end).contains(&offset))
+    else {
+        return;
+    };
+    let target = if url.contains("://") || url.starts_with("mailto:") {
+        url.clone()
+    } else {
+        // Relative links point at files next to the document
+        state
+            .current_file_path
+            .parent()
+            .map(|folder| folder.join(url).display().to_string())
+            .unwrap_or_else(|| url.clone())
+    };
+    if open::that_detached(&target).is_err() {
+        state
+            .toast_overlay
+            .add_toast(Toast::new(&format!("Couldn't open {target}")));
+    }
+}
+
+/// Writes the rendered Markdown to an HTML file next to the document, asking first when
+/// that would replace an existing file unless `replace` is set.
+pub(crate) fn export_html(state: &mut State, replace: bool, sender: relm4::ComponentSender<State>) {
+    if !is_markdown(state) {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("Only Markdown files can be exported to HTML"));
+        return;
+    }
+    if state.current_file_path.as_os_str().is_empty() {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("Save the file before exporting it"));
+        return;
+    }
+    let html_path = state.current_file_path.with_extension("html");
+    let file_name = html_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if html_path.exists() && !replace {
+        let dialog = AlertDialog::builder()
+            .heading("Replace Existing File?")
+            .body(format!(
+                "{file_name} already exists. Exporting will overwrite it."
+            ))
+            .default_response("cancel")
+            .close_response("cancel")
+            .build();
+        dialog.add_responses(&[("cancel", "Cancel"), ("replace", "Replace")]);
+        dialog.set_response_appearance("replace", ResponseAppearance::Destructive);
+        dialog.connect_response(None, move |_, response| {
+            if response == "replace" {
+                sender.input(Msg::ExportHtml(true));
+            }
+        });
+        dialog.present(Some(&state.root));
+        return;
+    }
+    let markdown = state
+        .buffer
+        .text(&state.buffer.start_iter(), &state.buffer.end_iter(), true);
+    let mut body = String::new();
+    pulldown_cmark::html::push_html(&mut body, Parser::new_ext(&markdown, markdown_options()));
+    let title = state
+        .current_file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        glib::markup_escape_text(&title)
+    );
+    let message = match std::fs::write(&html_path, html) {
+        Ok(_) => format!("Exported to {file_name}"),
+        Err(_) => "Error when exporting to HTML!".to_string(),
+    };
+    state.toast_overlay.add_toast(Toast::new(&message));
+}
@@ -12,6 +12,14 @@ pub fn menu_bar() -> MenuModel {
         1,
         &MenuItem::new(Some("Load Folder"), Some("file.open_folder")),
     );
+    load_section.insert_item(
+        2,
+        &MenuItem::new(Some("Quick Open..."), Some("file.quick_open")),
+    );
+    load_section.insert_item(
+        3,
+        &MenuItem::new(Some("Import Snippets..."), Some("file.import_snippets")),
+    );
     menu.insert_section(1, None, &load_section);
 
     // Save
@@ -22,7 +30,26 @@ pub fn menu_bar() -> MenuModel {
 
     // Edit
     let edit_section = Menu::new();
-    edit_section.insert_item(0, &MenuItem::new(Some("Clear"), Some("edit.clear")));
+    edit_section.insert_item(0, &MenuItem::new(Some("Find"), Some("edit.find")));
+    edit_section.insert_item(
+        1,
+        &MenuItem::new(Some("Find and Replace"), Some("edit.replace")),
+    );
+    edit_section.insert_item(
+        2,
+        &MenuItem::new(Some("Find in Folder"), Some("edit.find_in_project")),
+    );
+    edit_section.insert_item(
+        3,
+        &MenuItem::new(Some("Go to Line..."), Some("edit.go_to_line")),
+    );
+    edit_section.insert_item(
+        4,
+        &MenuItem::new(Some("Format Document"), Some("edit.format_document")),
+    );
+    edit_section.insert_submenu(5, Some("Lines"), &lines_menu());
+    edit_section.insert_submenu(6, Some("Transform"), &transform_menu());
+    edit_section.insert_item(7, &MenuItem::new(Some("Clear"), Some("edit.clear")));
     menu.insert_section(3, None, &edit_section);
 
     // Toggle
@@ -43,20 +70,110 @@ pub fn menu_bar() -> MenuModel {
     let extras_section = Menu::new();
     extras_section.insert_item(
         0,
-        &MenuItem::new(Some("Preferences"), Some("about.show_preferences")),
+        &MenuItem::new(Some("Command Palette"), Some("view.show_command_palette")),
     );
     extras_section.insert_item(
         1,
+        &MenuItem::new(Some("Preferences"), Some("about.show_preferences")),
+    );
+    extras_section.insert_item(
+        2,
         &MenuItem::new(
             Some("Keyboard Shortcuts"),
             Some("about.show_keyboard_shortcuts"),
         ),
     );
     extras_section.insert_item(
-        2,
+        3,
         &MenuItem::new(Some("About Cryptum Text"), Some("about.show_about")),
     );
     menu.insert_section(5, None, &extras_section);
 
     menu.into()
 }
+
+/// The line editing commands, shown as a submenu of the edit section.
+fn lines_menu() -> Menu {
+    let menu = Menu::new();
+    let move_section = Menu::new();
+    move_section.append(Some("Move Lines Up"), Some("edit.move_lines_up"));
+    move_section.append(Some("Move Lines Down"), Some("edit.move_lines_down"));
+    move_section.append(
+        Some("Duplicate Line or Selection"),
+        Some("edit.duplicate_lines"),
+    );
+    move_section.append(Some("Delete Lines"), Some("edit.delete_lines"));
+    move_section.append(Some("Join Lines"), Some("edit.join_lines"));
+    menu.append_section(None, &move_section);
+
+    let sort_section = Menu::new();
+    sort_section.append(Some("Sort Lines"), Some("edit.sort_lines"));
+    sort_section.append(
+        Some("Sort Lines (Ignore Case)"),
+        Some("edit.sort_lines_ignore_case"),
+    );
+    sort_section.append(
+        Some("Sort Lines (Numeric)"),
+        Some("edit.sort_lines_numeric"),
+    );
+    sort_section.append(Some("Sort Lines (Unique)"), Some("edit.sort_lines_unique"));
+    sort_section.append(Some("Reverse Lines"), Some("edit.reverse_lines"));
+    menu.append_section(None, &sort_section);
+
+    let comment_section = Menu::new();
+    comment_section.append(
+        Some("Toggle Line Comment"),
+        Some("edit.toggle_line_comment"),
+    );
+    comment_section.append(
+        Some("Toggle Block Comment"),
+        Some("edit.toggle_block_comment"),
+    );
+    menu.append_section(None, &comment_section);
+    menu
+}
+
+/// The text transformations, shown as a submenu of the edit section.
+fn transform_menu() -> Menu {
+    let menu = Menu::new();
+    let case_section = Menu::new();
+    case_section.append(Some("UPPER CASE"), Some("edit.transform_upper_case"));
+    case_section.append(Some("lower case"), Some("edit.transform_lower_case"));
+    case_section.append(Some("Title Case"), Some("edit.transform_title_case"));
+    case_section.append(Some("iNVERT cASE"), Some("edit.transform_invert_case"));
+    menu.append_section(None, &case_section);
+    let naming_section = Menu::new();
+    naming_section.append(Some("snake_case"), Some("edit.transform_snake_case"));
+    naming_section.append(Some("camelCase"), Some("edit.transform_camel_case"));
+    naming_section.append(Some("kebab-case"), Some("edit.transform_kebab_case"));
+    naming_section.append(Some("PascalCase"), Some("edit.transform_pascal_case"));
+    menu.append_section(None, &naming_section);
+    let whitespace_section = Menu::new();
+    whitespace_section.append(
+        Some("Trim Whitespace"),
+        Some("edit.transform_trim_whitespace"),
+    );
+    whitespace_section.append(
+        Some("Tabs to Spaces"),
+        Some("edit.transform_tabs_to_spaces"),
+    );
+    whitespace_section.append(
+        Some("Spaces to Tabs"),
+        Some("edit.transform_spaces_to_tabs"),
+    );
+    menu.append_section(None, &whitespace_section);
+    let encoding_section = Menu::new();
+    encoding_section.append(Some("URL Encode"), Some("edit.transform_url_encode"));
+    encoding_section.append(Some("URL Decode"), Some("edit.transform_url_decode"));
+    encoding_section.append(Some("Base64 Encode"), Some("edit.transform_base64_encode"));
+    encoding_section.append(Some("Base64 Decode"), Some("edit.transform_base64_decode"));
+    menu.append_section(None, &encoding_section);
+    let json_section = Menu::new();
+    json_section.append(
+        Some("Pretty Print JSON"),
+        Some("edit.transform_json_pretty"),
+    );
+    json_section.append(Some("Minify JSON"), Some("edit.transform_json_minify"));
+    menu.append_section(None, &json_section);
+    menu
+}
@@ -0,0 +1,468 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use gtk4::{
+    EventControllerKey, EventSequenceState, GestureClick, GestureDrag, PropagationPhase, TextIter,
+    TextMark, TextSearchFlags,
+    gdk::{Key, ModifierType},
+    glib::{self, Propagation, clone},
+    prelude::*,
+};
+use libadwaita::Toast;
+use relm4::gtk;
+
+use crate::app::model::State;
+
+/// A caret besides the buffer's own cursor, with an optional selection.
+#[derive(Debug)]
+struct Caret {
+    position: TextMark,
+    /// The other end of the selection, at `position` when nothing is selected
+    anchor: TextMark,
+    /// The line drawn over the editor where the caret is
+    line: gtk::Separator,
+}
+
+/// An editor with the handlers the multi cursor added to it.
+type AttachedView = (sourceview5::View, Vec<gtk::EventController>);
+
+/// CSS class on the editor showing extra carets, so other key handlers leave typing to
+/// the multi cursor.
+pub const MULTI_CURSOR_CLASS: &str = "multi-cursor";
+
+/// An edit at the cursor to repeat at the other carets.
+#[derive(Debug, Clone)]
+enum PendingEdit {
+    Insert(String),
+    /// Characters before and after the cursor
+    DeleteAroundCursor(i32, i32),
+    DeleteSelection,
+}
+
+/// Extra carets in the editor. Typing, deleting and pasting at the cursor is repeated at
+/// each of them as the user action ends, within it, so one undo reverts every caret's edit.
+/// Waiting until then keeps the iters of the buffer's own edit handlers valid.
+#[derive(Debug, Clone)]
+pub struct MultiCursor {
+    carets: Rc<RefCell<Vec<Caret>>>,
+    /// Set while edits are being repeated, so they aren't repeated again
+    replaying: Rc<Cell<bool>>,
+    in_user_action: Rc<Cell<bool>>,
+    /// Edits at the cursor in the current user action, in order
+    pending_edits: Rc<RefCell<Vec<PendingEdit>>>,
+    /// The editor the carets are drawn in, the last one to add one
+    editor: Rc<RefCell<sourceview5::View>>,
+    /// Every editor showing the buffer, with the click, drag and key handlers added to it
+    views: Rc<RefCell<Vec<AttachedView>>>,
+    buffer: sourceview5::Buffer,
+}
+
+impl MultiCursor {
+    fn add_caret(&self, position: &TextIter, anchor: &TextIter) {
+        if self.has_caret_at(position.offset()) {
+            return;
+        }
+        let line = gtk::Separator::new(gtk4::Orientation::Vertical);
+        line.set_can_target(false);
+        let editor = self.editor.borrow();
+        editor.add_overlay(&line, 0, 0);
+        editor.add_css_class(MULTI_CURSOR_CLASS);
+        drop(editor);
+        self.carets.borrow_mut().push(Caret {
+            position: self.buffer.create_mark(None, position, false),
+            anchor: self.buffer.create_mark(None, anchor, true),
+            line,
+        });
+        self.refresh();
+    }
+
+    pub fn clear(&self) {
+        let editor = self.editor.borrow();
+        for caret in self.carets.borrow_mut().drain(..) {
+            self.buffer.delete_mark(&caret.position);
+            self.buffer.delete_mark(&caret.anchor);
+            editor.remove(&caret.line);
+        }
+        editor.remove_css_class(MULTI_CURSOR_CLASS);
+        drop(editor);
+        self.refresh();
+    }
+
+    /// Draws the carets in `editor`, one of the editors showing the buffer.
+    pub fn set_editor(&self, editor: &sourceview5::View) {
+        let previous = self.editor.replace(editor.clone());
+        if previous == *editor {
+            return;
+        }
+        for caret in self.carets.borrow().iter() {
+            previous.remove(&caret.line);
+            editor.add_overlay(&caret.line, 0, 0);
+        }
+        if self.is_active() {
+            previous.remove_css_class(MULTI_CURSOR_CLASS);
+            editor.add_css_class(MULTI_CURSOR_CLASS);
+        }
+        self.refresh();
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.carets.borrow().is_empty()
+    }
+
+    fn has_caret_at(&self, offset: i32) -> bool {
+        self.carets
+            .borrow()
+            .iter()
+            .any(|caret| self.buffer.iter_at_mark(&caret.position).offset() == offset)
+    }
+
+    /// Whether an edit at the cursor should be repeated at the carets.
+    fn records_edits(&self) -> bool {
+        !self.replaying.get() && self.in_user_action.get() && self.is_active()
+    }
+
+    /// Moves the caret lines into place and highlights the caret selections.
+    fn refresh(&self) {
+        let buffer = &self.buffer;
+        buffer.remove_tag_by_name(
+            "multi-cursor-selection",
+            &buffer.start_iter(),
+            &buffer.end_iter(),
+        );
+        for caret in self.carets.borrow().iter() {
+            let position = buffer.iter_at_mark(&caret.position);
+            let anchor = buffer.iter_at_mark(&caret.anchor);
+            buffer.apply_tag_by_name("multi-cursor-selection", &position, &anchor);
+            let editor = self.editor.borrow();
+            let location = editor.iter_location(&position);
+            caret.line.set_size_request(1, location.height());
+            editor.move_overlay(&caret.line, location.x(), location.y());
+        }
+    }
+
+    fn marks(&self) -> Vec<(TextMark, TextMark)> {
+        self.carets
+            .borrow()
+            .iter()
+            .map(|caret| (caret.position.clone(), caret.anchor.clone()))
+            .collect()
+    }
+
+    /// Repeats `edits` at every caret, in a user action of their own that joins the one
+    /// being ended.
+    fn replay(&self, edits: &[PendingEdit]) {
+        let buffer = &self.buffer;
+        self.replaying.set(true);
+        buffer.begin_user_action();
+        for edit in edits {
+            for (position, anchor) in self.marks() {
+                let mut start = buffer.iter_at_mark(&anchor);
+                let mut end = buffer.iter_at_mark(&position);
+                match edit {
+                    PendingEdit::Insert(text) => {
+                        let mut iter = end;
+                        buffer.insert(&mut iter, text);
+                        buffer.move_mark(&anchor, &iter);
+                        continue;
+                    }
+                    // Carets with a selection always lose it
+                    _ if start != end => {}
+                    PendingEdit::DeleteSelection => continue,
+                    PendingEdit::DeleteAroundCursor(before, after) => {
+                        start.backward_chars(*before);
+                        end.forward_chars(*after);
+                    }
+                }
+                buffer.delete(&mut start, &mut end);
+                buffer.move_mark(&anchor, &start);
+                buffer.move_mark(&position, &start);
+            }
+        }
+        buffer.end_user_action();
+        self.replaying.set(false);
+        self.refresh();
+    }
+
+    fn cursor_offset(&self) -> i32 {
+        self.buffer.iter_at_mark(&self.buffer.get_insert()).offset()
+    }
+}
+
+/// The buffer position at a point in the editor widget.
+fn iter_at_point(editor: &sourceview5::View, x: f64, y: f64) -> Option<TextIter> {
+    let (x, y) = editor.window_to_buffer_coords(gtk4::TextWindowType::Widget, x as i32, y as i32);
+    editor.iter_at_location(x, y)
+}
+
+fn iter_at_line_column(buffer: &sourceview5::Buffer, line: i32, column: i32) -> TextIter {
+    let mut iter = buffer
+        .iter_at_line(line)
+        .unwrap_or_else(|| buffer.end_iter());
+    let mut line_end = iter;
+    if !line_end.ends_line() {
+        line_end.forward_to_line_end();
+    }
+    iter.set_line_offset(column.min(line_end.line_offset()));
+    iter
+}
+
+pub fn setup_multi_cursor(editor: &sourceview5::View, buffer: &sourceview5::Buffer) -> MultiCursor {
+    buffer.create_tag(
+        Some("multi-cursor-selection"),
+        &[(
+            "background-rgba",
+            &gtk4::gdk::RGBA::new(0.21, 0.52, 0.89, 0.3),
+        )],
+    );
+    let multi_cursor = MultiCursor {
+        carets: Rc::default(),
+        replaying: Rc::default(),
+        in_user_action: Rc::default(),
+        pending_edits: Rc::default(),
+        editor: Rc::new(RefCell::new(editor.clone())),
+        views: Rc::default(),
+        buffer: buffer.clone(),
+    };
+
+    // Typing and pasting
+    buffer.connect_insert_text(clone!(
+        #[strong]
+        multi_cursor,
+        move |_, location, text| {
+            if multi_cursor.records_edits() && location.offset() == multi_cursor.cursor_offset() {
+                multi_cursor
+                    .pending_edits
+                    .borrow_mut()
+                    .push(PendingEdit::Insert(text.to_string()));
+            }
+        }
+    ));
+    // Backspace, delete and replacing a selection
+    buffer.connect_delete_range(clone!(
+        #[strong]
+        multi_cursor,
+        move |buffer, start, end| {
+            if !multi_cursor.records_edits() {
+                return;
+            }
+            let cursor = multi_cursor.cursor_offset();
+            let bound = buffer.iter_at_mark(&buffer.selection_bound()).offset();
+            let edit = if cursor != bound
+                && start.offset() == cursor.min(bound)
+                && end.offset() == cursor.max(bound)
+            {
+                PendingEdit::DeleteSelection
+            } else if end.offset() == cursor || start.offset() == cursor {
+                PendingEdit::DeleteAroundCursor(cursor - start.offset(), end.offset() - cursor)
+            } else {
+                return;
+            };
+            multi_cursor.pending_edits.borrow_mut().push(edit);
+        }
+    ));
+    buffer.connect_begin_user_action(clone!(
+        #[strong]
+        multi_cursor,
+        move |_| multi_cursor.in_user_action.set(true)
+    ));
+    // Runs before the undo history closes the user action, so the edits join it
+    buffer.connect_end_user_action(clone!(
+        #[strong]
+        multi_cursor,
+        move |_| {
+            multi_cursor.in_user_action.set(false);
+            let edits = multi_cursor.pending_edits.take();
+            if !edits.is_empty() && multi_cursor.is_active() {
+                multi_cursor.replay(&edits);
+            }
+        }
+    ));
+    buffer.connect_changed(clone!(
+        #[strong]
+        multi_cursor,
+        move |_| {
+            if !multi_cursor.replaying.get() {
+                multi_cursor.refresh();
+            }
+        }
+    ));
+    multi_cursor.attach(editor);
+
+    multi_cursor
+}
+
+impl MultiCursor {
+    /// Adds the handlers for placing and dropping carets to an editor showing the buffer.
+    pub fn attach(&self, editor: &sourceview5::View) {
+        let multi_cursor = self;
+        // Ctrl+click leaves a caret where the cursor was, a plain click clears them
+        let click = GestureClick::builder()
+            .button(1)
+            .propagation_phase(PropagationPhase::Capture)
+            .build();
+        click.connect_pressed(clone!(
+            #[strong]
+            multi_cursor,
+            #[weak]
+            editor,
+            move |gesture, _, x, y| {
+                let modifiers = gesture.current_event_state();
+                if modifiers.contains(ModifierType::CONTROL_MASK) {
+                    let buffer = &multi_cursor.buffer;
+                    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                    let bound = buffer.iter_at_mark(&buffer.selection_bound());
+                    let clicked = iter_at_point(&editor, x, y);
+                    if clicked.is_some_and(|clicked| clicked != cursor) {
+                        multi_cursor.set_editor(&editor);
+                        multi_cursor.add_caret(&cursor, &bound);
+                    }
+                } else if !modifiers.contains(ModifierType::ALT_MASK) && multi_cursor.is_active() {
+                    multi_cursor.clear();
+                }
+            }
+        ));
+        editor.add_controller(click.clone());
+
+        // Alt+drag selects a block, with one caret per line
+        let drag = GestureDrag::builder()
+            .button(1)
+            .propagation_phase(PropagationPhase::Capture)
+            .build();
+        let block_start: Rc<Cell<Option<(i32, i32)>>> = Rc::default();
+        drag.connect_drag_begin(clone!(
+            #[strong]
+            multi_cursor,
+            #[strong]
+            block_start,
+            #[weak]
+            editor,
+            move |gesture, x, y| {
+                block_start.set(None);
+                if !gesture
+                    .current_event_state()
+                    .contains(ModifierType::ALT_MASK)
+                {
+                    return;
+                }
+                if let Some(iter) = iter_at_point(&editor, x, y) {
+                    gesture.set_state(EventSequenceState::Claimed);
+                    block_start.set(Some((iter.line(), iter.line_offset())));
+                    multi_cursor.clear();
+                    multi_cursor.set_editor(&editor);
+                    multi_cursor.buffer.place_cursor(&iter);
+                }
+            }
+        ));
+        drag.connect_drag_update(clone!(
+            #[strong]
+            multi_cursor,
+            #[weak]
+            editor,
+            move |gesture, offset_x, offset_y| {
+                let (Some((start_line, start_column)), Some((x, y))) =
+                    (block_start.get(), gesture.start_point())
+                else {
+                    return;
+                };
+                let Some(end) = iter_at_point(&editor, x + offset_x, y + offset_y) else {
+                    return;
+                };
+                let buffer = &multi_cursor.buffer;
+                let (end_line, end_column) = (end.line(), end.line_offset());
+                multi_cursor.clear();
+                for line in start_line.min(end_line)..=start_line.max(end_line) {
+                    let anchor = iter_at_line_column(buffer, line, start_column);
+                    let position = iter_at_line_column(buffer, line, end_column);
+                    if line == end_line {
+                        buffer.select_range(&position, &anchor);
+                    } else {
+                        multi_cursor.add_caret(&position, &anchor);
+                    }
+                }
+            }
+        ));
+        editor.add_controller(drag.clone());
+
+        // Escape drops the extra carets
+        let keys = EventControllerKey::new();
+        keys.connect_key_pressed(clone!(
+            #[strong]
+            multi_cursor,
+            move |_, key, _, _| {
+                if key == Key::Escape && multi_cursor.is_active() {
+                    multi_cursor.clear();
+                    return Propagation::Stop;
+                }
+                Propagation::Proceed
+            }
+        ));
+        editor.add_controller(keys.clone());
+
+        self.views.borrow_mut().push((
+            editor.clone(),
+            vec![click.upcast(), drag.upcast(), keys.upcast()],
+        ));
+    }
+
+    /// Removes the handlers from an editor that's about to show another buffer, dropping
+    /// the carets if they're drawn in it.
+    pub fn detach(&self, editor: &sourceview5::View) {
+        if *self.editor.borrow() == *editor {
+            self.clear();
+        }
+        self.views.borrow_mut().retain(|(view, controllers)| {
+            if view != editor {
+                return true;
+            }
+            for controller in controllers {
+                view.remove_controller(controller);
+            }
+            false
+        });
+    }
+}
+
+/// Selects the word at the cursor, or with a selection, adds a caret for the next
+/// occurrence of the selected text.
+pub(crate) fn add_next_occurrence(state: &mut State) {
+    let buffer = &state.buffer;
+    let Some((start, end)) = buffer.selection_bounds() else {
+        let mut start = buffer.iter_at_mark(&buffer.get_insert());
+        let mut end = start;
+        if !start.starts_word() {
+            start.backward_word_start();
+        }
+        if !end.ends_word() {
+            end.forward_word_end();
+        }
+        if start != end {
+            buffer.select_range(&end, &start);
+        }
+        return;
+    };
+    let needle = buffer.text(&start, &end, true);
+    let found = end
+        .forward_search(&needle, TextSearchFlags::TEXT_ONLY, None)
+        .or_else(|| {
+            buffer
+                .start_iter()
+                .forward_search(&needle, TextSearchFlags::TEXT_ONLY, Some(&start))
+        });
+    match found {
+        // Once every occurrence has a caret, the search comes back around to them
+        Some((found_start, found_end))
+            if found_start != start && !state.multi_cursor.has_caret_at(found_end.offset()) =>
+        {
+            state.multi_cursor.add_caret(&end, &start);
+            buffer.select_range(&found_end, &found_start);
+            state
+                .editor
+                .scroll_to_mark(&buffer.get_insert(), 0.1, false, 0.0, 0.0);
+        }
+        _ => state
+            .toast_overlay
+            .add_toast(Toast::new("No more occurrences")),
+    }
+}
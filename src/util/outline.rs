@@ -0,0 +1,128 @@
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use gtk4::{
+    ListBox, ScrolledWindow,
+    glib::{self, ControlFlow, SourceId, clone, markup_escape_text},
+    prelude::*,
+};
+use relm4::{RelmRemoveAllExt, gtk};
+use sourceview5::{Buffer, prelude::BufferExt};
+
+use crate::{
+    app::model::{Msg, State},
+    util::{
+        picker::{PickerAction, PickerItem},
+        symbols::{Symbol, extract_symbols},
+    },
+};
+
+/// The document outline page in the sidebar.
+#[derive(Debug)]
+pub struct Outline {
+    pub container: ScrolledWindow,
+    pub list: ListBox,
+    pub symbols: Vec<Symbol>,
+}
+
+pub fn setup_outline(buffer: &Buffer, sender: &relm4::ComponentSender<State>) -> Outline {
+    let list = ListBox::builder()
+        .css_classes(vec!["navigation-sidebar"])
+        .build();
+    list.set_placeholder(Some(
+        &gtk::Label::builder()
+            .label("No symbols")
+            .css_classes(vec!["dim-label"])
+            .build(),
+    ));
+    let container = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .vexpand(true)
+        .child(&list)
+        .build();
+
+    // Events
+    list.connect_row_activated(clone!(
+        #[strong]
+        sender,
+        move |_, row| sender.input(Msg::OutlineActivate(row.index()))
+    ));
+    attach_outline(buffer, sender);
+
+    Outline {
+        container,
+        list,
+        symbols: Vec::new(),
+    }
+}
+
+/// Refreshes the outline when the buffer changes.
+pub fn attach_outline(buffer: &Buffer, sender: &relm4::ComponentSender<State>) {
+    // Wait for a pause in typing before re-extracting the symbols
+    let pending: Rc<RefCell<Option<SourceId>>> = Rc::default();
+    buffer.connect_changed(clone!(
+        #[strong]
+        sender,
+        move |_| {
+            if let Some(source) = pending.borrow_mut().take() {
+                source.remove();
+            }
+            *pending.borrow_mut() = Some(glib::timeout_add_local(
+                Duration::from_millis(300),
+                clone!(
+                    #[strong]
+                    sender,
+                    #[strong]
+                    pending,
+                    move || {
+                        pending.borrow_mut().take();
+                        sender.input(Msg::RefreshOutline);
+                        ControlFlow::Break
+                    }
+                ),
+            ));
+        }
+    ));
+}
+
+/// Re-extracts the symbols for the current buffer's language and rebuilds the outline list.
+pub(crate) fn refresh_outline(state: &mut State) {
+    let outline = &mut state.outline;
+    outline.symbols = match state.buffer.language() {
+        Some(language) => extract_symbols(
+            &language.id(),
+            &state
+                .buffer
+                .text(&state.buffer.start_iter(), &state.buffer.end_iter(), true),
+        ),
+        None => Vec::new(),
+    };
+    outline.list.remove_all();
+    for symbol in &outline.symbols {
+        let label = gtk::Label::builder()
+            .label(format!(
+                "<small>{}</small>  {}",
+                symbol.kind.label(),
+                markup_escape_text(&symbol.name)
+            ))
+            .use_markup(true)
+            .halign(gtk4::Align::Start)
+            .margin_start(symbol.depth as i32 * 12)
+            .ellipsize(gtk4::pango::EllipsizeMode::End)
+            .build();
+        outline.list.append(&label);
+    }
+}
+
+pub(crate) fn symbol_picker_items(state: &State) -> Vec<PickerItem> {
+    state
+        .outline
+        .symbols
+        .iter()
+        .map(|symbol| PickerItem {
+            title: symbol.name.clone(),
+            subtitle: format!("{}  ·  line {}", symbol.kind.label(), symbol.line + 1),
+            action: PickerAction::GoToLine(symbol.line),
+            recent: false,
+        })
+        .collect()
+}
@@ -0,0 +1,158 @@
+use gtk4::{
+    accelerator_get_label, accelerator_parse,
+    gio::{SimpleActionGroup, prelude::*},
+    prelude::GtkApplicationExt,
+};
+
+use crate::{
+    app::model::State,
+    util::picker::{PickerAction, PickerItem},
+};
+
+/// Human readable names for actions, keyed by their detailed name.
+/// Actions missing from here fall back to a label generated from their name.
+const ACTION_LABELS: &[(&str, &str)] = &[
+    // File
+    ("file.new_file", "New File"),
+    ("file.open", "Open File"),
+    ("file.open_folder", "Open Folder"),
+    ("file.quick_open", "Quick Open File in Folder"),
+    ("file.save", "Save File"),
+    ("file.save_as", "Save File As"),
+    ("file.import_snippets", "Import VS Code Snippets"),
+    ("file.run_task", "Run Task"),
+    ("file.rerun_task", "Re-run Last Task"),
+    ("file.cancel_task", "Cancel Running Task"),
+    ("file.export_html", "Export Markdown to HTML"),
+    // Edit
+    ("edit.clear", "Clear Editor"),
+    ("edit.find", "Find"),
+    ("edit.replace", "Find and Replace"),
+    ("edit.find_next", "Find Next"),
+    ("edit.find_previous", "Find Previous"),
+    ("edit.find_in_project", "Find in Folder"),
+    ("edit.go_to_line", "Go to Line"),
+    ("edit.go_to_symbol", "Go to Symbol in File"),
+    ("edit.go_to_definition", "Go to Definition"),
+    ("edit.find_references", "Find References"),
+    ("edit.rename_symbol", "Rename Symbol"),
+    ("edit.next_problem", "Go to Next Problem"),
+    ("edit.previous_problem", "Go to Previous Problem"),
+    ("edit.format_document", "Format Document"),
+    ("edit.jump_to_bracket", "Jump to Matching Bracket"),
+    ("edit.select_to_bracket", "Select to Matching Bracket"),
+    ("edit.add_next_occurrence", "Add Cursor at Next Occurrence"),
+    ("edit.move_lines_up", "Move Lines Up"),
+    ("edit.move_lines_down", "Move Lines Down"),
+    ("edit.duplicate_lines", "Duplicate Line or Selection"),
+    ("edit.delete_lines", "Delete Lines"),
+    ("edit.join_lines", "Join Lines"),
+    ("edit.sort_lines", "Sort Lines"),
+    ("edit.sort_lines_ignore_case", "Sort Lines (Ignore Case)"),
+    ("edit.sort_lines_numeric", "Sort Lines (Numeric)"),
+    ("edit.sort_lines_unique", "Sort Lines (Unique)"),
+    ("edit.reverse_lines", "Reverse Lines"),
+    ("edit.toggle_line_comment", "Toggle Line Comment"),
+    ("edit.toggle_block_comment", "Toggle Block Comment"),
+    ("edit.transform_upper_case", "Transform to UPPER CASE"),
+    ("edit.transform_lower_case", "Transform to lower case"),
+    ("edit.transform_title_case", "Transform to Title Case"),
+    ("edit.transform_invert_case", "Transform to iNVERT cASE"),
+    ("edit.transform_snake_case", "Transform to snake_case"),
+    ("edit.transform_camel_case", "Transform to camelCase"),
+    ("edit.transform_kebab_case", "Transform to kebab-case"),
+    ("edit.transform_pascal_case", "Transform to PascalCase"),
+    ("edit.transform_trim_whitespace", "Trim Whitespace"),
+    ("edit.transform_tabs_to_spaces", "Tabs to Spaces"),
+    ("edit.transform_spaces_to_tabs", "Spaces to Tabs"),
+    ("edit.transform_url_encode", "URL Encode"),
+    ("edit.transform_url_decode", "URL Decode"),
+    ("edit.transform_base64_encode", "Base64 Encode"),
+    ("edit.transform_base64_decode", "Base64 Decode"),
+    ("edit.transform_json_pretty", "Pretty Print JSON"),
+    ("edit.transform_json_minify", "Minify JSON"),
+    // View
+    ("view.toggle_file_list", "Toggle File List Visibility"),
+    ("view.toggle_hidden_files", "Toggle Hidden Files Visibility"),
+    ("view.toggle_mini_map", "Toggle Mini Map Visibility"),
+    (
+        "view.toggle_buffer_style_scheme",
+        "Toggle Editor Theme (Light/Dark)",
+    ),
+    ("view.toggle_fullscreen", "Toggle Fullscreen"),
+    ("view.show_command_palette", "Show Command Palette"),
+    ("view.toggle_problems", "Toggle Problems Panel"),
+    ("view.toggle_output", "Toggle Output Panel"),
+    ("view.toggle_terminal", "Toggle Terminal"),
+    ("view.new_terminal", "New Terminal"),
+    ("view.toggle_markdown_preview", "Toggle Markdown Preview"),
+    ("view.split_right", "Split Editor Right"),
+    ("view.split_down", "Split Editor Down"),
+    ("view.close_split", "Close Split"),
+    ("view.focus_next_split", "Focus Next Split"),
+    ("view.fold", "Fold Region"),
+    ("view.unfold", "Unfold Region"),
+    ("view.fold_all", "Fold All"),
+    ("view.unfold_all", "Unfold All"),
+    // About
+    (
+        "about.show_keyboard_shortcuts",
+        "Show Keyboard Shortcuts Dialog",
+    ),
+    ("about.show_preferences", "Show Preferences Dialog"),
+    ("about.show_about", "About Cryptum Text"),
+];
+
+pub(crate) fn action_label(detailed_name: &str, name: &str) -> String {
+    match ACTION_LABELS
+        .iter()
+        .find(|(action, _)| *action == detailed_name)
+    {
+        Some((_, label)) => label.to_string(),
+        None => {
+            let mut label = name.replace('_', " ");
+            if let Some(first) = label.get_mut(0..1) {
+                first.make_ascii_uppercase();
+            }
+            label
+        }
+    }
+}
+
+/// Lists every enabled action in the registered action groups, along with its accelerators.
+pub(crate) fn command_palette_items(state: &State) -> Vec<PickerItem> {
+    let program = relm4::main_application();
+    let mut items: Vec<PickerItem> = state
+        .action_groups
+        .iter()
+        .flat_map(|(group_name, group): &(&str, SimpleActionGroup)| {
+            group
+                .list_actions()
+                .into_iter()
+                .filter(|name| group.is_action_enabled(name))
+                .map(|name| (format!("{group_name}.{name}"), name))
+                .filter(|(detailed_name, _)| detailed_name != "view.show_command_palette")
+                .map(|(detailed_name, name)| {
+                    let accelerators: Vec<String> = program
+                        .accels_for_action(&detailed_name)
+                        .iter()
+                        .filter_map(accelerator_parse)
+                        .map(|(key, modifiers)| accelerator_get_label(key, modifiers).to_string())
+                        .collect();
+                    PickerItem {
+                        title: action_label(&detailed_name, &name),
+                        subtitle: if accelerators.is_empty() {
+                            detailed_name.clone()
+                        } else {
+                            format!("{}  ·  {}", accelerators.join(", "), detailed_name)
+                        },
+                        action: PickerAction::ActivateAction(detailed_name),
+                        recent: false,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    items.sort_by(|a, b| a.title.cmp(&b.title));
+    items
+}
@@ -0,0 +1,277 @@
+use std::path::{Path, PathBuf};
+
+use gtk4::{
+    EventControllerKey, ListBox, Orientation, ScrolledWindow, SearchEntry,
+    gdk::Key,
+    glib::{self, Propagation, clone, markup_escape_text},
+    prelude::*,
+};
+use libadwaita::{Dialog, HeaderBar, ToolbarView, prelude::*};
+use relm4::{RelmRemoveAllExt, gtk};
+
+use crate::{
+    app::model::{Msg, State},
+    fs::file::open_file,
+    util::{
+        fuzzy::{fuzzy_path_score, fuzzy_score},
+        tasks::run_task,
+        widget::place_cursor,
+    },
+};
+
+const MAX_RESULTS: usize = 200;
+
+/// What happens when an item in the picker is chosen.
+#[derive(Debug, Clone)]
+pub enum PickerAction {
+    OpenFile(PathBuf),
+    /// A detailed action name such as `file.save`
+    ActivateAction(String),
+    /// A zero based line in the current buffer
+    GoToLine(i32),
+    /// A file with a zero based line and column to move the cursor to
+    OpenLocation(PathBuf, i32, i32),
+    /// A task loaded by the task picker, by index
+    RunTask(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct PickerItem {
+    pub title: String,
+    pub subtitle: String,
+    pub action: PickerAction,
+    /// Shown before everything else when the query is empty, and ranked higher otherwise
+    pub recent: bool,
+}
+
+/// A fuzzy filtered popup list, shared by the quick open and similar pickers.
+#[derive(Debug)]
+pub struct Picker {
+    pub dialog: Dialog,
+    pub entry: SearchEntry,
+    pub list: ListBox,
+    pub items: Vec<PickerItem>,
+    // Indices into `items`, in the order they're shown in `list`
+    pub filtered: Vec<usize>,
+}
+
+pub fn setup_picker(sender: &relm4::ComponentSender<State>) -> Picker {
+    let entry = SearchEntry::builder().hexpand(true).build();
+    let list = ListBox::builder()
+        .css_classes(vec!["navigation-sidebar"])
+        .selection_mode(gtk4::SelectionMode::Browse)
+        .build();
+    let list_scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .vexpand(true)
+        .child(&list)
+        .build();
+    let content = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_start(6)
+        .margin_end(6)
+        .margin_bottom(6)
+        .build();
+    content.append(&entry);
+    content.append(&list_scroll);
+    let toolbar = ToolbarView::builder().content(&content).build();
+    toolbar.add_top_bar(&HeaderBar::new());
+    let dialog = Dialog::builder()
+        .content_width(600)
+        .content_height(450)
+        .child(&toolbar)
+        .build();
+
+    // Let the arrow keys move through the list while typing in the entry
+    let key_controller = EventControllerKey::new();
+    key_controller.connect_key_pressed(clone!(
+        #[weak]
+        list,
+        #[weak]
+        entry,
+        #[upgrade_or]
+        Propagation::Proceed,
+        move |_, key, _, _| {
+            let offset = match key {
+                Key::Down => 1,
+                Key::Up => -1,
+                _ => return Propagation::Proceed,
+            };
+            let index = list.selected_row().map_or(-1, |row| row.index()) + offset;
+            if let Some(row) = list.row_at_index(index.max(0)) {
+                list.select_row(Some(&row));
+                // Focusing the row scrolls it into view, then typing goes back to the entry
+                row.grab_focus();
+                entry.grab_focus();
+                entry.set_position(-1);
+            }
+            Propagation::Stop
+        }
+    ));
+    entry.add_controller(key_controller);
+    entry.connect_search_changed(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::PickerFilter)
+    ));
+    entry.connect_activate(clone!(
+        #[strong]
+        sender,
+        #[weak]
+        list,
+        move |_| {
+            if let Some(row) = list.selected_row() {
+                sender.input(Msg::PickerActivate(row.index()))
+            }
+        }
+    ));
+    list.connect_row_activated(clone!(
+        #[strong]
+        sender,
+        move |_, row| sender.input(Msg::PickerActivate(row.index()))
+    ));
+
+    Picker {
+        dialog,
+        entry,
+        list,
+        items: Vec::new(),
+        filtered: Vec::new(),
+    }
+}
+
+/// Replaces the picker's items and presents it over the main window.
+pub(crate) fn show_picker(
+    state: &mut State,
+    title: &str,
+    placeholder: &str,
+    items: Vec<PickerItem>,
+) {
+    let picker = &mut state.picker;
+    picker.items = items;
+    picker.dialog.set_title(title);
+    picker.entry.set_placeholder_text(Some(placeholder));
+    picker.entry.set_text("");
+    filter_picker(state);
+    state.picker.dialog.present(Some(&state.root));
+    state.picker.entry.grab_focus();
+}
+
+/// Ranks the items against the entry text and rebuilds the list.
+pub(crate) fn filter_picker(state: &mut State) {
+    let picker = &mut state.picker;
+    let query = picker.entry.text();
+    let mut scored: Vec<(i64, usize)> = picker
+        .items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let score = if query.is_empty() {
+                Some(0)
+            } else {
+                match &item.action {
+                    PickerAction::OpenFile(_) => {
+                        fuzzy_path_score(&query, Path::new(&item.subtitle))
+                    }
+                    PickerAction::ActivateAction(_)
+                    | PickerAction::GoToLine(_)
+                    | PickerAction::OpenLocation(..)
+                    | PickerAction::RunTask(_) => None,
+                }
+                .or_else(|| fuzzy_score(&query, &item.title))
+            }?;
+            Some((score + if item.recent { 500 } else { 0 }, index))
+        })
+        .collect();
+    // Stable sort keeps the original (recency) order for equal scores
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    picker.filtered = scored
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, index)| index)
+        .collect();
+
+    picker.list.remove_all();
+    for index in &picker.filtered {
+        let item = &picker.items[*index];
+        let label = gtk::Label::builder()
+            .label(format!(
+                "{}\n<small>{}</small>",
+                markup_escape_text(&item.title),
+                markup_escape_text(&item.subtitle)
+            ))
+            .use_markup(true)
+            .halign(gtk4::Align::Start)
+            .ellipsize(gtk4::pango::EllipsizeMode::Middle)
+            .build();
+        picker.list.append(&label);
+    }
+    picker.list.select_row(picker.list.row_at_index(0).as_ref());
+}
+
+/// Runs the action for the chosen row and closes the picker.
+pub(crate) fn activate_picker(state: &mut State, row: i32) {
+    let Some(item) = state
+        .picker
+        .filtered
+        .get(row as usize)
+        .and_then(|index| state.picker.items.get(*index))
+        .cloned()
+    else {
+        return;
+    };
+    state.picker.dialog.close();
+    match item.action {
+        PickerAction::OpenFile(path) => open_file(state, path),
+        PickerAction::ActivateAction(name) => {
+            _ = WidgetExt::activate_action(&state.root, &name, None);
+        }
+        PickerAction::GoToLine(line) => place_cursor(state, line, 0),
+        PickerAction::OpenLocation(path, line, column) => {
+            if path != state.current_file_path {
+                open_file(state, path);
+            }
+            place_cursor(state, line, column);
+        }
+        PickerAction::RunTask(index) => {
+            if let Some(task) = state.task_runner.tasks.get(index).cloned() {
+                run_task(state, task);
+            }
+        }
+    }
+}
+
+/// Builds the quick open items from the folder index, with recently used files first.
+pub(crate) fn quick_open_items(state: &State) -> Vec<PickerItem> {
+    let to_item = |path: &PathBuf, recent: bool| {
+        let relative_path = path
+            .strip_prefix(&state.current_folder_path)
+            .unwrap_or(path)
+            .display()
+            .to_string();
+        PickerItem {
+            title: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            subtitle: relative_path,
+            action: PickerAction::OpenFile(path.clone()),
+            recent,
+        }
+    };
+    let mut items: Vec<PickerItem> = state
+        .recent_files
+        .iter()
+        .filter(|path| path.exists())
+        .map(|path| to_item(path, true))
+        .collect();
+    items.extend(
+        state
+            .file_index
+            .iter()
+            .filter(|path| !state.recent_files.contains(path))
+            .map(|path| to_item(path, false)),
+    );
+    items
+}
@@ -0,0 +1,227 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use gtk4::{
+    ListBox, Revealer, ScrolledWindow,
+    glib::{clone, markup_escape_text},
+    prelude::*,
+};
+use libadwaita::Toast;
+use relm4::{RelmRemoveAllExt, gtk};
+
+use crate::{
+    app::model::{Msg, State},
+    lsp::{
+        client::{path_to_uri, uri_to_path},
+        diagnostics::Diagnostic,
+        handlers::open_location,
+        manager::{char_column, line_text, utf16_column},
+    },
+    util::widget::place_cursor,
+};
+
+/// The "Problems" panel below the editor, listing the diagnostics of every file from the
+/// language servers and from the output of the last task.
+#[derive(Debug)]
+pub struct Problems {
+    pub revealer: Revealer,
+    pub list: ListBox,
+    /// Error and warning counts in the status bar, toggling the panel
+    pub summary_button: gtk::Button,
+    /// The listed problems, in the order they're shown in `list`
+    pub entries: Vec<(String, Diagnostic)>,
+    /// Errors and warnings printed by the last task, keyed by document uri
+    pub task_diagnostics: HashMap<String, Vec<Diagnostic>>,
+}
+
+pub fn setup_problems(sender: &relm4::ComponentSender<State>) -> Problems {
+    let list = ListBox::builder()
+        .css_classes(vec!["navigation-sidebar"])
+        .build();
+    list.set_placeholder(Some(
+        &gtk::Label::builder()
+            .label("No problems")
+            .css_classes(vec!["dim-label"])
+            .build(),
+    ));
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .height_request(160)
+        .child(&list)
+        .build();
+    let header = gtk::Label::builder()
+        .label("Problems")
+        .halign(gtk4::Align::Start)
+        .margin_start(12)
+        .margin_top(6)
+        .css_classes(vec!["heading"])
+        .build();
+    let content = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .build();
+    content.append(&gtk::Separator::new(gtk4::Orientation::Horizontal));
+    content.append(&header);
+    content.append(&scroll);
+    let revealer = Revealer::builder()
+        .transition_type(gtk4::RevealerTransitionType::SlideUp)
+        .child(&content)
+        .build();
+    let summary_button = gtk::Button::builder()
+        .css_classes(vec!["flat"])
+        .halign(gtk4::Align::Center)
+        .tooltip_text("Toggle Problems Panel (Ctrl+Shift+M)")
+        .build();
+
+    // Events
+    list.connect_row_activated(clone!(
+        #[strong]
+        sender,
+        move |_, row| sender.input(Msg::ProblemActivate(row.index()))
+    ));
+    summary_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ToggleProblems)
+    ));
+
+    let problems = Problems {
+        revealer,
+        list,
+        summary_button,
+        entries: Vec::new(),
+        task_diagnostics: HashMap::new(),
+    };
+    update_summary(&problems);
+    problems
+}
+
+fn severity_icon(severity: u64) -> &'static str {
+    match severity {
+        1 => "dialog-error-symbolic",
+        2 => "dialog-warning-symbolic",
+        _ => "dialog-information-symbolic",
+    }
+}
+
+fn update_summary(problems: &Problems) {
+    let count = |severity: u64| {
+        problems
+            .entries
+            .iter()
+            .filter(|(_, diagnostic)| diagnostic.severity == severity)
+            .count()
+    };
+    problems
+        .summary_button
+        .set_label(&format!("✖ {}   ⚠ {}", count(1), count(2)));
+}
+
+/// The diagnostics of every file from every source, with their document's uri.
+fn all_diagnostics(state: &State) -> impl Iterator<Item = (&String, &Diagnostic)> {
+    [&state.lsp.diagnostics, &state.problems.task_diagnostics]
+        .into_iter()
+        .flatten()
+        .flat_map(|(uri, diagnostics)| diagnostics.iter().map(move |diagnostic| (uri, diagnostic)))
+}
+
+/// Rebuilds the panel from the diagnostics of every file, with the current file first.
+pub(crate) fn refresh_problems(state: &mut State) {
+    let current_uri = path_to_uri(&state.current_file_path);
+    let mut entries: Vec<(String, Diagnostic)> = all_diagnostics(state)
+        .map(|(uri, diagnostic)| (uri.clone(), diagnostic.clone()))
+        .collect();
+    entries.sort_by(|(uri_a, a), (uri_b, b)| {
+        (*uri_b == current_uri)
+            .cmp(&(*uri_a == current_uri))
+            .then_with(|| uri_a.cmp(uri_b))
+            .then_with(|| a.start.cmp(&b.start))
+    });
+
+    let problems = &mut state.problems;
+    problems.entries = entries;
+    problems.list.remove_all();
+    for (uri, diagnostic) in &problems.entries {
+        let path = uri_to_path(uri).unwrap_or_else(|| PathBuf::from(uri));
+        let location = format!(
+            "{}:{}",
+            path.strip_prefix(&state.current_folder_path)
+                .unwrap_or(&path)
+                .display(),
+            diagnostic.start.0 + 1
+        );
+        let row = gtk::Box::builder()
+            .orientation(gtk4::Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        row.append(&gtk::Image::from_icon_name(severity_icon(
+            diagnostic.severity,
+        )));
+        row.append(
+            &gtk::Label::builder()
+                .label(format!(
+                    "{}  <small>{}{}</small>",
+                    markup_escape_text(diagnostic.message.lines().next().unwrap_or_default()),
+                    markup_escape_text(&location),
+                    if diagnostic.source.is_empty() {
+                        String::new()
+                    } else {
+                        format!("  ·  {}", markup_escape_text(&diagnostic.source))
+                    }
+                ))
+                .use_markup(true)
+                .halign(gtk4::Align::Start)
+                .ellipsize(gtk4::pango::EllipsizeMode::End)
+                .build(),
+        );
+        problems.list.append(&row);
+    }
+    update_summary(problems);
+}
+
+pub(crate) fn open_problem(state: &mut State, index: i32) {
+    if let Some((uri, diagnostic)) = state.problems.entries.get(index as usize).cloned() {
+        open_location(state, &uri, diagnostic.start.0, diagnostic.start.1);
+        state.editor.grab_focus();
+    }
+}
+
+/// Moves the cursor to the next (or previous) problem in the current file, wrapping around.
+pub(crate) fn go_to_problem(state: &mut State, backwards: bool) {
+    let current_uri = path_to_uri(&state.current_file_path);
+    let mut starts: Vec<(u32, u32)> = all_diagnostics(state)
+        .filter(|(uri, _)| **uri == current_uri)
+        .map(|(_, diagnostic)| diagnostic.start)
+        .collect();
+    starts.sort();
+    starts.dedup();
+    if starts.is_empty() {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("No problems in this file"));
+        return;
+    }
+
+    let cursor = state.buffer.iter_at_mark(&state.buffer.get_insert());
+    let cursor = (
+        cursor.line() as u32,
+        utf16_column(
+            &line_text(&state.buffer, cursor.line()),
+            cursor.line_offset(),
+        ),
+    );
+    let target = if backwards {
+        starts
+            .iter()
+            .rev()
+            .find(|start| **start < cursor)
+            .or(starts.last())
+    } else {
+        starts
+            .iter()
+            .find(|start| **start > cursor)
+            .or(starts.first())
+    };
+    if let Some((line, character)) = target {
+        let column = char_column(&line_text(&state.buffer, *line as i32), *character);
+        place_cursor(state, *line as i32, column);
+    }
+}
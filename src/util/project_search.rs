@@ -0,0 +1,503 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use gtk4::{
+    CheckButton, ListBox, ListBoxRow, Orientation, ScrolledWindow, SearchEntry, ToggleButton,
+    glib::{clone, markup_escape_text},
+    prelude::*,
+};
+use libadwaita::Toast;
+use relm4::{RelmRemoveAllExt, gtk};
+
+use crate::{
+    app::model::{Msg, State},
+    fs::{
+        editorconfig::{Charset, read_text, write_text},
+        file::open_file,
+        search::{FileMatches, LineMatch, SearchQuery, replace_selected, search_folder},
+    },
+    lsp::manager::buffer_text,
+    util::widget::{place_cursor, replace_buffer_text, set_buffer_text},
+};
+
+/// Widgets and results for the project wide search page in the sidebar.
+#[derive(Debug)]
+pub struct ProjectSearch {
+    pub container: gtk::Box,
+    pub search_entry: SearchEntry,
+    pub replace_entry: gtk::Entry,
+    pub case_button: ToggleButton,
+    pub regex_button: ToggleButton,
+    pub results_list: ListBox,
+    pub status_label: gtk::Label,
+    pub undo_button: gtk::Button,
+    // One entry per row in `results_list`
+    pub rows: Vec<ResultRow>,
+    pub query: Option<SearchQuery>,
+    pub search_id: u32,
+    pub cancelled: Arc<AtomicBool>,
+    // Original file contents from the last replace, with their charsets, for undo
+    pub undo: Vec<(PathBuf, String, Option<Charset>)>,
+}
+
+#[derive(Debug)]
+pub enum ResultRow {
+    File,
+    Match {
+        path: PathBuf,
+        line_match: LineMatch,
+        check: CheckButton,
+        label: gtk::Label,
+    },
+}
+
+pub fn setup_project_search(sender: &relm4::ComponentSender<State>) -> ProjectSearch {
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Search in Folder")
+        .hexpand(true)
+        .build();
+    let case_button = ToggleButton::builder()
+        .label("Aa")
+        .tooltip_text("Match Case")
+        .build();
+    let regex_button = ToggleButton::builder()
+        .label(".*")
+        .tooltip_text("Use Regular Expressions")
+        .build();
+    let replace_entry = gtk::Entry::builder()
+        .placeholder_text("Replace (use $1 for capture groups)")
+        .hexpand(true)
+        .build();
+    let replace_button = gtk::Button::builder()
+        .label("Replace Selected")
+        .hexpand(true)
+        .build();
+    let undo_button = gtk::Button::builder()
+        .icon_name("edit-undo-symbolic")
+        .tooltip_text("Undo Last Replace")
+        .sensitive(false)
+        .build();
+    let status_label = gtk::Label::builder()
+        .halign(gtk4::Align::Start)
+        .css_classes(vec!["dim-label"])
+        .build();
+    let results_list = ListBox::builder()
+        .css_classes(vec!["navigation-sidebar"])
+        .selection_mode(gtk4::SelectionMode::Single)
+        .build();
+    let results_scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .vexpand(true)
+        .child(&results_list)
+        .build();
+
+    // Containers
+    let search_box = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    let replace_box = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    let container = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_start(6)
+        .margin_end(6)
+        .margin_top(6)
+        .build();
+    search_box.append(&search_entry);
+    search_box.append(&case_button);
+    search_box.append(&regex_button);
+    replace_box.append(&replace_button);
+    replace_box.append(&undo_button);
+    container.append(&search_box);
+    container.append(&replace_entry);
+    container.append(&replace_box);
+    container.append(&status_label);
+    container.append(&results_scroll);
+
+    // Events
+    search_entry.connect_activate(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ProjectSearchStart)
+    ));
+    for button in [&case_button, &regex_button] {
+        button.connect_toggled(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::ProjectSearchStart)
+        ));
+    }
+    replace_entry.connect_changed(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ProjectSearchPreview)
+    ));
+    replace_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ProjectReplaceSelected)
+    ));
+    undo_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ProjectReplaceUndo)
+    ));
+    results_list.connect_row_activated(clone!(
+        #[strong]
+        sender,
+        move |_, row| sender.input(Msg::ProjectSearchActivate(row.index()))
+    ));
+
+    ProjectSearch {
+        container,
+        search_entry,
+        replace_entry,
+        case_button,
+        regex_button,
+        results_list,
+        status_label,
+        undo_button,
+        rows: Vec::new(),
+        query: None,
+        search_id: 0,
+        cancelled: Arc::new(AtomicBool::new(false)),
+        undo: Vec::new(),
+    }
+}
+
+/// Cancels any running search, then walks the current folder on a background thread,
+/// streaming each file's matches back as `Msg::ProjectSearchResult`.
+pub(crate) fn start_project_search(state: &mut State, sender: relm4::ComponentSender<State>) {
+    let project_search = &mut state.project_search;
+    project_search.cancelled.store(true, Ordering::Relaxed);
+    project_search.search_id += 1;
+    project_search.results_list.remove_all();
+    project_search.rows.clear();
+    project_search.query = None;
+
+    let query = SearchQuery {
+        pattern: project_search.search_entry.text().to_string(),
+        case_sensitive: project_search.case_button.is_active(),
+        regex: project_search.regex_button.is_active(),
+        include_hidden: state.view_hidden,
+    };
+    if query.pattern.is_empty() {
+        project_search.status_label.set_label("");
+        return;
+    }
+    if state.current_folder_path.as_os_str().is_empty() {
+        project_search
+            .status_label
+            .set_label("Open a folder to search in");
+        return;
+    }
+    if query.to_regex().is_err() {
+        project_search
+            .status_label
+            .set_label("Invalid regular expression");
+        return;
+    }
+    project_search.status_label.set_label("Searching…");
+    project_search.query = Some(query.clone());
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    project_search.cancelled = cancelled.clone();
+    let search_id = project_search.search_id;
+    let folder = state.current_folder_path.clone();
+    let (result_sender, result_receiver) = relm4::channel::<FileMatches>();
+    std::thread::spawn(move || {
+        search_folder(&folder, &query, &cancelled, |file_matches| {
+            result_sender.emit(file_matches)
+        });
+    });
+    relm4::spawn_local(async move {
+        while let Some(file_matches) = result_receiver.recv().await {
+            sender.input(Msg::ProjectSearchResult(search_id, file_matches));
+        }
+        sender.input(Msg::ProjectSearchFinished(search_id));
+    });
+}
+
+/// Appends a file header row followed by one checkable row per match.
+pub(crate) fn add_project_search_result(
+    state: &mut State,
+    search_id: u32,
+    file_matches: FileMatches,
+) {
+    let project_search = &mut state.project_search;
+    if search_id != project_search.search_id {
+        return;
+    }
+    let relative_path = file_matches
+        .path
+        .strip_prefix(&state.current_folder_path)
+        .unwrap_or(&file_matches.path)
+        .display()
+        .to_string();
+    let header = gtk::Label::builder()
+        .label(format!(
+            "<b>{}</b> ({})",
+            markup_escape_text(&relative_path),
+            file_matches.matches.len()
+        ))
+        .use_markup(true)
+        .halign(gtk4::Align::Start)
+        .ellipsize(gtk4::pango::EllipsizeMode::Start)
+        .build();
+    project_search.results_list.append(
+        &ListBoxRow::builder()
+            .child(&header)
+            .activatable(false)
+            .selectable(false)
+            .build(),
+    );
+    project_search.rows.push(ResultRow::File);
+
+    for line_match in file_matches.matches {
+        let check = CheckButton::builder().active(true).build();
+        let label = gtk::Label::builder()
+            .halign(gtk4::Align::Start)
+            .hexpand(true)
+            .ellipsize(gtk4::pango::EllipsizeMode::End)
+            .use_markup(true)
+            .build();
+        let row_box = gtk::Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(6)
+            .build();
+        row_box.append(&check);
+        row_box.append(&label);
+        project_search.results_list.append(&row_box);
+        project_search.rows.push(ResultRow::Match {
+            path: file_matches.path.clone(),
+            line_match,
+            check,
+            label,
+        });
+    }
+    update_project_search_preview(state);
+}
+
+pub(crate) fn finish_project_search(state: &mut State, search_id: u32) {
+    let project_search = &mut state.project_search;
+    if search_id != project_search.search_id {
+        return;
+    }
+    let file_count = project_search
+        .rows
+        .iter()
+        .filter(|row| matches!(row, ResultRow::File))
+        .count();
+    let match_count = project_search.rows.len() - file_count;
+    project_search
+        .status_label
+        .set_label(&format!("{match_count} results in {file_count} files"));
+}
+
+/// Shows each match with its replacement applied inline when there is replacement text.
+pub(crate) fn update_project_search_preview(state: &mut State) {
+    let project_search = &state.project_search;
+    let Some(query) = &project_search.query else {
+        return;
+    };
+    let Ok(regex) = query.to_regex() else {
+        return;
+    };
+    let replace = project_search.replace_entry.text();
+    let replacement = query.replacement(&replace);
+    for row in &project_search.rows {
+        if let ResultRow::Match {
+            line_match, label, ..
+        } = row
+        {
+            let location = format!("{}:{}", line_match.line + 1, line_match.column + 1);
+            let preview = match line_match.preview.split_once(&line_match.matched) {
+                Some((before, after)) if !replace.is_empty() => format!(
+                    "<small>{location}</small>  {}<s>{}</s><b>{}</b>{}",
+                    markup_escape_text(before),
+                    markup_escape_text(&line_match.matched),
+                    markup_escape_text(&regex.replace(&line_match.matched, &replacement)),
+                    markup_escape_text(after),
+                ),
+                Some((before, after)) => format!(
+                    "<small>{location}</small>  {}<b>{}</b>{}",
+                    markup_escape_text(before),
+                    markup_escape_text(&line_match.matched),
+                    markup_escape_text(after),
+                ),
+                None => format!(
+                    "<small>{location}</small>  {}",
+                    markup_escape_text(&line_match.preview)
+                ),
+            };
+            label.set_label(&preview);
+        }
+    }
+}
+
+/// Opens the file for the activated result and moves the cursor to the match.
+pub(crate) fn open_project_search_result(state: &mut State, index: i32) {
+    if let Some(ResultRow::Match {
+        path, line_match, ..
+    }) = state.project_search.rows.get(index as usize)
+    {
+        let (line, column) = (line_match.line, line_match.column);
+        if &state.current_file_path != path {
+            open_file(state, path.clone());
+        }
+        place_cursor(state, line as i32, column as i32);
+    }
+}
+
+/// Applies the replacement to every checked match, remembering the original files for undo.
+/// Open files are only changed when they have no unsaved edits, and their buffers are
+/// edited rather than reloaded. Files are written back in the charset they were read in.
+pub(crate) fn replace_in_project(state: &mut State, sender: relm4::ComponentSender<State>) {
+    let project_search = &mut state.project_search;
+    let Some(query) = &project_search.query else {
+        return;
+    };
+    let Ok(regex) = query.to_regex() else {
+        return;
+    };
+    let replacement = query.replacement(&project_search.replace_entry.text());
+
+    // Group the checked matches by file, keeping the order they were found in
+    let mut selected: Vec<(PathBuf, Vec<(u32, usize)>)> = Vec::new();
+    for row in &project_search.rows {
+        if let ResultRow::Match {
+            path,
+            line_match,
+            check,
+            ..
+        } = row
+            && check.is_active()
+        {
+            let location = (line_match.line, line_match.index);
+            match selected.last_mut() {
+                Some((last_path, locations)) if last_path == path => locations.push(location),
+                _ => selected.push((path.clone(), vec![location])),
+            }
+        }
+    }
+
+    let mut undo = Vec::new();
+    let mut failed = 0;
+    let mut open_files = Vec::new();
+    for (path, locations) in selected {
+        match read_text(&path) {
+            Some((content, charset)) => {
+                if has_unsaved_edits(state, &path, &content) {
+                    state.toast_overlay.add_toast(Toast::new(&format!(
+                        "Skipped {}, it has unsaved changes",
+                        path.display()
+                    )));
+                    continue;
+                }
+                let replaced = replace_selected(&content, &regex, &replacement, &locations);
+                if write_text(&path, &replaced, charset).is_ok() {
+                    if open_text(state, &path).is_some() {
+                        open_files.push((path.clone(), replaced));
+                    }
+                    undo.push((path, content, charset));
+                } else {
+                    failed += 1;
+                }
+            }
+            None => failed += 1,
+        }
+    }
+    if failed > 0 {
+        state.toast_overlay.add_toast(Toast::new(&format!(
+            "Error when replacing in {failed} files!"
+        )));
+    } else {
+        state
+            .toast_overlay
+            .add_toast(Toast::new(&format!("Replaced in {} files", undo.len())));
+    }
+    state
+        .project_search
+        .undo_button
+        .set_sensitive(!undo.is_empty());
+    state.project_search.undo = undo;
+    for (path, text) in open_files {
+        patch_open_file(state, &path, &text);
+    }
+    // The lines and indexes of the results are out of date now
+    start_project_search(state, sender);
+}
+
+/// Restores the files changed by the last replace, leaving open files alone if they were
+/// edited since.
+pub(crate) fn undo_project_replace(state: &mut State, sender: relm4::ComponentSender<State>) {
+    let undo = std::mem::take(&mut state.project_search.undo);
+    let mut open_files = Vec::new();
+    for (path, content, charset) in undo {
+        if read_text(&path).is_some_and(|(replaced, _)| has_unsaved_edits(state, &path, &replaced))
+        {
+            state.toast_overlay.add_toast(Toast::new(&format!(
+                "Didn't restore {}, it has unsaved changes",
+                path.display()
+            )));
+            continue;
+        }
+        if write_text(&path, &content, charset).is_err() {
+            state.toast_overlay.add_toast(Toast::new(&format!(
+                "Error when restoring {}!",
+                path.display()
+            )));
+        } else if open_text(state, &path).is_some() {
+            open_files.push((path, content));
+        }
+    }
+    state.project_search.undo_button.set_sensitive(false);
+    for (path, text) in open_files {
+        patch_open_file(state, &path, &text);
+    }
+    start_project_search(state, sender);
+}
+
+/// The text of the buffer `path` is open in, in any split.
+fn open_text(state: &State, path: &Path) -> Option<String> {
+    if path == state.current_file_path {
+        return Some(buffer_text(&state.buffer));
+    }
+    state
+        .splits
+        .documents
+        .iter()
+        .find(|document| document.file_path == path)
+        .map(|document| buffer_text(&document.buffer))
+}
+
+/// Whether `path` is open with text that differs from `saved`, what's on disk.
+fn has_unsaved_edits(state: &State, path: &Path, saved: &str) -> bool {
+    open_text(state, path).is_some_and(|text| text != saved)
+}
+
+/// Edits the buffer `path` is open in to match what was written to it.
+fn patch_open_file(state: &mut State, path: &Path, text: &str) {
+    if path == state.current_file_path {
+        replace_buffer_text(state, text);
+        state.buffer.set_modified(false);
+    } else if let Some(document) = state
+        .splits
+        .documents
+        .iter()
+        .find(|document| document.file_path == path)
+    {
+        document.multi_cursor.clear();
+        set_buffer_text(&document.buffer, text);
+        document.buffer.set_modified(false);
+    }
+}
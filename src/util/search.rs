@@ -0,0 +1,437 @@
+use gtk4::{
+    Align, Orientation, SearchBar, SearchEntry, TextIter, TextMark, ToggleButton, glib::clone,
+    prelude::*,
+};
+use libadwaita::Toast;
+use relm4::gtk;
+use sourceview5::{Buffer, SearchContext, SearchSettings, prelude::SearchSettingsExt};
+
+use crate::app::model::{Msg, State};
+
+/// Widgets and search state backing the find/replace bar above the editor.
+#[derive(Debug)]
+pub struct FindBar {
+    pub search_bar: SearchBar,
+    pub search_entry: SearchEntry,
+    pub replace_entry: gtk::Entry,
+    pub replace_box: gtk::Box,
+    pub match_label: gtk::Label,
+    pub case_button: ToggleButton,
+    pub word_button: ToggleButton,
+    pub regex_button: ToggleButton,
+    pub selection_button: ToggleButton,
+    pub context: SearchContext,
+    pub scope: Option<(TextMark, TextMark)>,
+    /// Matches inside the scope, or in the whole buffer, -1 while it's being scanned
+    match_count: i32,
+    /// Matches in the buffer ahead of the scope
+    matches_before_scope: i32,
+}
+
+/// A search of `buffer` with the find bar's settings, counting its matches for the bar.
+pub fn search_context(
+    buffer: &Buffer,
+    settings: &SearchSettings,
+    sender: &relm4::ComponentSender<State>,
+) -> SearchContext {
+    let context = SearchContext::new(buffer, Some(settings));
+    context.set_highlight(false);
+    context.connect_occurrences_count_notify(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::SearchCountChanged)
+    ));
+    context
+}
+
+pub fn setup_find_bar(buffer: &Buffer, sender: &relm4::ComponentSender<State>) -> FindBar {
+    let settings = SearchSettings::builder().wrap_around(true).build();
+    let context = search_context(buffer, &settings, sender);
+
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Find")
+        .hexpand(true)
+        .build();
+    let match_label = gtk::Label::builder()
+        .width_chars(10)
+        .css_classes(vec!["dim-label"])
+        .build();
+    let case_button = ToggleButton::builder()
+        .label("Aa")
+        .tooltip_text("Match Case")
+        .build();
+    let word_button = ToggleButton::builder()
+        .label("W")
+        .tooltip_text("Match Whole Word")
+        .build();
+    let regex_button = ToggleButton::builder()
+        .label(".*")
+        .tooltip_text("Use Regular Expressions")
+        .build();
+    let selection_button = ToggleButton::builder()
+        .icon_name("edit-select-all-symbolic")
+        .tooltip_text("Find in Selection")
+        .build();
+    let previous_button = gtk::Button::builder()
+        .icon_name("go-up-symbolic")
+        .tooltip_text("Previous Match (Shift+F3)")
+        .build();
+    let next_button = gtk::Button::builder()
+        .icon_name("go-down-symbolic")
+        .tooltip_text("Next Match (F3)")
+        .build();
+    let replace_entry = gtk::Entry::builder()
+        .placeholder_text("Replace (use \\1 for capture groups)")
+        .hexpand(true)
+        .build();
+    let replace_button = gtk::Button::builder().label("Replace").build();
+    let replace_all_button = gtk::Button::builder().label("Replace All").build();
+
+    // Containers
+    let find_box = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    let replace_box = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .visible(false)
+        .build();
+    let bar_box = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .width_request(600)
+        .halign(Align::Center)
+        .build();
+    find_box.append(&search_entry);
+    find_box.append(&match_label);
+    find_box.append(&case_button);
+    find_box.append(&word_button);
+    find_box.append(&regex_button);
+    find_box.append(&selection_button);
+    find_box.append(&previous_button);
+    find_box.append(&next_button);
+    replace_box.append(&replace_entry);
+    replace_box.append(&replace_button);
+    replace_box.append(&replace_all_button);
+    bar_box.append(&find_box);
+    bar_box.append(&replace_box);
+    let search_bar = SearchBar::builder()
+        .child(&bar_box)
+        .show_close_button(true)
+        .build();
+    search_bar.connect_entry(&search_entry);
+
+    // Events
+    search_entry.connect_search_changed(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::SearchChanged)
+    ));
+    search_entry.connect_activate(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::FindNext)
+    ));
+    search_entry.connect_next_match(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::FindNext)
+    ));
+    search_entry.connect_previous_match(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::FindPrevious)
+    ));
+    for button in [&case_button, &word_button, &regex_button, &selection_button] {
+        button.connect_toggled(clone!(
+            #[strong]
+            sender,
+            move |_| sender.input(Msg::SearchChanged)
+        ));
+    }
+    previous_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::FindPrevious)
+    ));
+    next_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::FindNext)
+    ));
+    replace_entry.connect_activate(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ReplaceMatch)
+    ));
+    replace_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ReplaceMatch)
+    ));
+    replace_all_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::ReplaceAll)
+    ));
+    search_bar.connect_search_mode_enabled_notify(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::SearchChanged)
+    ));
+
+    FindBar {
+        search_bar,
+        search_entry,
+        replace_entry,
+        replace_box,
+        match_label,
+        case_button,
+        word_button,
+        regex_button,
+        selection_button,
+        context,
+        scope: None,
+        match_count: -1,
+        matches_before_scope: 0,
+    }
+}
+
+/// Reveals the search bar, pre-filling it with the current single line selection.
+pub(crate) fn show_find_bar(state: &mut State, with_replace: bool) {
+    let find_bar = &state.find_bar;
+    if let Some((start, end)) = state.buffer.selection_bounds()
+        && start.line() == end.line()
+    {
+        find_bar
+            .search_entry
+            .set_text(&state.buffer.text(&start, &end, true));
+    }
+    find_bar.replace_box.set_visible(with_replace);
+    find_bar.search_bar.set_search_mode(true);
+    find_bar.search_entry.grab_focus();
+    find_bar.search_entry.select_region(0, -1);
+}
+
+/// Syncs the search settings with the entry and toggle buttons.
+pub(crate) fn update_search(state: &mut State) {
+    let find_bar = &mut state.find_bar;
+    let settings = find_bar.context.settings();
+    let text = find_bar.search_entry.text();
+    settings.set_search_text(if text.is_empty() {
+        None
+    } else {
+        Some(text.as_str())
+    });
+    settings.set_case_sensitive(find_bar.case_button.is_active());
+    settings.set_at_word_boundaries(find_bar.word_button.is_active());
+    settings.set_regex_enabled(find_bar.regex_button.is_active());
+    find_bar
+        .context
+        .set_highlight(find_bar.search_bar.is_search_mode());
+
+    // Remember the selection as the search scope when "Find in Selection" is toggled on
+    match (find_bar.selection_button.is_active(), &find_bar.scope) {
+        (true, None) => {
+            if let Some((start, end)) = state.buffer.selection_bounds() {
+                find_bar.scope = Some((
+                    state.buffer.create_mark(None, &start, true),
+                    state.buffer.create_mark(None, &end, false),
+                ));
+            } else {
+                find_bar.selection_button.set_active(false);
+            }
+        }
+        (false, Some((start, end))) => {
+            state.buffer.delete_mark(start);
+            state.buffer.delete_mark(end);
+            find_bar.scope = None;
+        }
+        _ => {}
+    }
+    update_match_count(state);
+}
+
+/// Counts the matches again, once the search or the buffer's occurrences change.
+pub(crate) fn update_match_count(state: &mut State) {
+    let (count, before) = if state.find_bar.scope.is_some() {
+        let matches = scoped_matches(state);
+        let before = matches.first().map_or(0, |(start, end)| {
+            state.find_bar.context.occurrence_position(start, end) - 1
+        });
+        (matches.len() as i32, before.max(0))
+    } else {
+        (state.find_bar.context.occurrences_count(), 0)
+    };
+    state.find_bar.match_count = count;
+    state.find_bar.matches_before_scope = before;
+}
+
+fn scope_bounds(state: &State) -> Option<(TextIter, TextIter)> {
+    state.find_bar.scope.as_ref().map(|(start, end)| {
+        (
+            state.buffer.iter_at_mark(start),
+            state.buffer.iter_at_mark(end),
+        )
+    })
+}
+
+fn in_scope(scope: &Option<(TextIter, TextIter)>, start: &TextIter, end: &TextIter) -> bool {
+    match scope {
+        Some((scope_start, scope_end)) => start >= scope_start && end <= scope_end,
+        None => true,
+    }
+}
+
+/// Collects every match inside the search scope, or the whole buffer when there is none.
+fn scoped_matches(state: &State) -> Vec<(TextIter, TextIter)> {
+    let scope = scope_bounds(state);
+    let mut iter = match &scope {
+        Some((start, _)) => *start,
+        None => state.buffer.start_iter(),
+    };
+    let mut matches = Vec::new();
+    while let Some((start, end, wrapped)) = state.find_bar.context.forward(&iter) {
+        if wrapped || !in_scope(&scope, &start, &end) {
+            break;
+        }
+        iter = end;
+        if start == end && !iter.forward_char() {
+            matches.push((start, end));
+            break;
+        }
+        matches.push((start, end));
+    }
+    matches
+}
+
+/// Selects the next (or previous) match relative to the cursor, wrapping within the scope.
+pub(crate) fn find_match(state: &mut State, backwards: bool) {
+    if state.find_bar.search_entry.text().is_empty() {
+        return;
+    }
+    let (cursor_start, cursor_end) = state.buffer.selection_bounds().unwrap_or_else(|| {
+        let cursor = state.buffer.iter_at_mark(&state.buffer.get_insert());
+        (cursor, cursor)
+    });
+    let found = if state.find_bar.scope.is_some() {
+        let matches = scoped_matches(state);
+        if backwards {
+            matches
+                .iter()
+                .rev()
+                .find(|(_, end)| *end < cursor_end)
+                .or(matches.last())
+                .copied()
+        } else {
+            matches
+                .iter()
+                .find(|(start, _)| *start > cursor_start)
+                .or(matches.first())
+                .copied()
+        }
+    } else if backwards {
+        state
+            .find_bar
+            .context
+            .backward(&cursor_start)
+            .map(|(start, end, _)| (start, end))
+    } else {
+        state
+            .find_bar
+            .context
+            .forward(&cursor_end)
+            .map(|(start, end, _)| (start, end))
+    };
+    if let Some((start, end)) = found {
+        state.buffer.select_range(&start, &end);
+        state
+            .editor
+            .scroll_to_mark(&state.buffer.get_insert(), 0.1, false, 0.0, 0.0);
+    }
+}
+
+/// Replaces the selected match, then moves on to the next one.
+pub(crate) fn replace_match(state: &mut State) {
+    let scope = scope_bounds(state);
+    if let Some((mut start, mut end)) = state.buffer.selection_bounds()
+        && state.find_bar.context.occurrence_position(&start, &end) > 0
+        && in_scope(&scope, &start, &end)
+        && let Err(e) = state.find_bar.context.replace(
+            &mut start,
+            &mut end,
+            &state.find_bar.replace_entry.text(),
+        )
+    {
+        state.toast_overlay.add_toast(Toast::new(&format!(
+            "Error when replacing: {}",
+            e.message()
+        )));
+        return;
+    }
+    find_match(state, false);
+}
+
+/// Replaces every match in the scope as a single undoable action.
+pub(crate) fn replace_all(state: &mut State) {
+    let replacement = state.find_bar.replace_entry.text();
+    let marks: Vec<(TextMark, TextMark)> = scoped_matches(state)
+        .iter()
+        .map(|(start, end)| {
+            (
+                state.buffer.create_mark(None, start, true),
+                state.buffer.create_mark(None, end, false),
+            )
+        })
+        .collect();
+    let mut error = None;
+    state.buffer.begin_user_action();
+    for (start_mark, end_mark) in &marks {
+        let mut start = state.buffer.iter_at_mark(start_mark);
+        let mut end = state.buffer.iter_at_mark(end_mark);
+        if error.is_none()
+            && let Err(e) = state
+                .find_bar
+                .context
+                .replace(&mut start, &mut end, &replacement)
+        {
+            error = Some(e);
+        }
+        state.buffer.delete_mark(start_mark);
+        state.buffer.delete_mark(end_mark);
+    }
+    state.buffer.end_user_action();
+    match error {
+        Some(e) => state.toast_overlay.add_toast(Toast::new(&format!(
+            "Error when replacing: {}",
+            e.message()
+        ))),
+        None => state
+            .toast_overlay
+            .add_toast(Toast::new(&format!("Replaced {} matches", marks.len()))),
+    }
+}
+
+/// Returns the "n of m" label text for the search bar.
+pub(crate) fn match_count_text(state: &State) -> String {
+    let context = &state.find_bar.context;
+    if state.find_bar.search_entry.text().is_empty() {
+        return String::new();
+    }
+    if context.regex_error().is_some() {
+        return "Invalid".to_string();
+    }
+    let count = state.find_bar.match_count;
+    // Matches ahead of the scope are left out, as are those after it
+    let position = state.buffer.selection_bounds().map_or(0, |(start, end)| {
+        let position =
+            context.occurrence_position(&start, &end) - state.find_bar.matches_before_scope;
+        if position > count { 0 } else { position.max(0) }
+    });
+    match count {
+        -1 => "…".to_string(),
+        0 => "No results".to_string(),
+        _ => format!("{position} of {count}"),
+    }
+}
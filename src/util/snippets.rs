@@ -0,0 +1,695 @@
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    rc::Rc,
+};
+
+use gtk4::{
+    gio,
+    glib::{self, clone},
+    prelude::*,
+    subclass::prelude::*,
+};
+use libadwaita::Toast;
+use serde_json::Value;
+use sourceview5::{
+    CompletionCell, CompletionColumn, CompletionContext, CompletionProposal, Snippet,
+    SnippetManager, prelude::*, subclass::prelude::*,
+};
+
+use crate::app::model::{Msg, State};
+
+const DEFAULT_SNIPPETS: &[(&str, &str)] = &[
+    (
+        "rust.json",
+        r##"{
+  "Function": {
+    "prefix": "fn",
+    "body": ["fn ${1:name}(${2}) {", "    $0", "}"],
+    "description": "Function"
+  },
+  "Impl block": {
+    "prefix": "impl",
+    "body": ["impl ${1:Type} {", "    $0", "}"],
+    "description": "Impl block"
+  },
+  "Match expression": {
+    "prefix": "match",
+    "body": ["match ${1:value} {", "    ${2:pattern} => $0,", "}"],
+    "description": "Match expression"
+  },
+  "Test function": {
+    "prefix": "test",
+    "body": ["#[test]", "fn ${1:name}() {", "    $0", "}"],
+    "description": "Test function"
+  }
+}
+"##,
+    ),
+    (
+        "python.json",
+        r#"{
+  "Function": {
+    "prefix": "def",
+    "body": ["def ${1:name}(${2}):", "    ${0:pass}"],
+    "description": "Function"
+  },
+  "Class": {
+    "prefix": "class",
+    "body": ["class ${1:Name}:", "    def __init__(self${2}):", "        ${0:pass}"],
+    "description": "Class"
+  },
+  "Main guard": {
+    "prefix": "main",
+    "body": ["if __name__ == \"__main__\":", "    ${0:main()}"],
+    "description": "Main guard"
+  }
+}
+"#,
+    ),
+];
+
+/// A snippet from a JSON snippet file, in the VS Code snippet format.
+#[derive(Debug, Clone)]
+pub struct SnippetDefinition {
+    pub name: String,
+    pub prefixes: Vec<String>,
+    pub body: String,
+    pub description: String,
+    /// Sourceview language ids the snippet applies to, empty for every language
+    pub languages: Vec<String>,
+}
+
+impl SnippetDefinition {
+    fn applies_to(&self, language_id: Option<&str>) -> bool {
+        self.languages.is_empty()
+            || language_id.is_some_and(|id| self.languages.iter().any(|language| language == id))
+    }
+}
+
+/// The snippet being filled in, with the choices offered for its tab stops.
+#[derive(Debug)]
+pub struct ActiveSnippet {
+    pub snippet: Snippet,
+    pub choices: HashMap<i32, Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct SnippetLibrary {
+    pub snippets: Vec<SnippetDefinition>,
+    pub active: Option<ActiveSnippet>,
+}
+
+/// Maps VS Code language ids to sourceview ones, where they differ.
+fn sourceview_language_id(language_id: &str) -> &str {
+    match language_id {
+        "javascript" => "js",
+        "python" => "python3",
+        "shellscript" => "sh",
+        "javascriptreact" => "jsx",
+        "typescriptreact" => "typescript-jsx",
+        other => other,
+    }
+}
+
+fn string_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(text) => vec![text.clone()],
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Parses a VS Code style snippet file. Files named after a language apply to that
+/// language, `.code-snippets` files and `global.json` use each snippet's `scope` instead.
+pub fn parse_snippet_file(path: &Path, contents: &str) -> Vec<SnippetDefinition> {
+    let Ok(Value::Object(snippets)) = serde_json::from_str::<Value>(contents) else {
+        return Vec::new();
+    };
+    let file_language = match (
+        path.extension().and_then(|extension| extension.to_str()),
+        path.file_stem().and_then(|stem| stem.to_str()),
+    ) {
+        (Some("json"), Some(stem)) if stem != "global" => {
+            Some(sourceview_language_id(stem).to_string())
+        }
+        _ => None,
+    };
+    snippets
+        .iter()
+        .filter_map(|(name, snippet)| {
+            let languages = match &file_language {
+                Some(language) => vec![language.clone()],
+                None => snippet["scope"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|scope| sourceview_language_id(scope.trim()).to_string())
+                    .filter(|scope| !scope.is_empty())
+                    .collect(),
+            };
+            let prefixes = string_list(&snippet["prefix"]);
+            let body = string_list(&snippet["body"]).join("\n");
+            (!prefixes.is_empty()).then(|| SnippetDefinition {
+                name: name.clone(),
+                prefixes,
+                body,
+                description: snippet["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                languages,
+            })
+        })
+        .collect()
+}
+
+/// The folder holding the user's snippet files, created with a few defaults on first run.
+pub(crate) fn snippets_dir() -> PathBuf {
+    let mut snippets_path = dirs::config_dir().unwrap();
+    snippets_path.push("cryptum-text-snippets");
+    if !snippets_path.exists() && std::fs::create_dir_all(&snippets_path).is_ok() {
+        for (file_name, contents) in DEFAULT_SNIPPETS {
+            _ = std::fs::write(snippets_path.join(file_name), contents);
+        }
+    }
+    snippets_path
+}
+
+/// Loads every JSON snippet file from the snippets folder.
+pub fn load_snippets() -> Vec<SnippetDefinition> {
+    let Ok(entries) = std::fs::read_dir(snippets_dir()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("json" | "code-snippets")
+            )
+        })
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .flat_map(|path| match std::fs::read_to_string(path) {
+            Ok(contents) => parse_snippet_file(path, &contents),
+            Err(_) => Vec::new(),
+        })
+        .collect()
+}
+
+/// Escapes text so the sourceview snippet parser inserts it literally.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('$', "\\$")
+        .replace('}', "\\}")
+}
+
+/// Converts a VS Code snippet body to sourceview's snippet syntax. Variables are replaced
+/// with their values (or defaults), and choice lists become a placeholder with the first
+/// choice, with every choice collected into `choices`. Tab stops, placeholders and
+/// mirrors use the same syntax in both.
+pub fn convert_snippet_body(
+    body: &str,
+    variables: &HashMap<&str, String>,
+    choices: &mut HashMap<i32, Vec<String>>,
+) -> String {
+    let chars: Vec<char> = body.chars().collect();
+    let mut position = 0;
+    convert_until_brace(&chars, &mut position, variables, choices, false)
+}
+
+fn read_while(chars: &[char], position: &mut usize, test: impl Fn(char) -> bool) -> String {
+    let start = *position;
+    while *position < chars.len() && test(chars[*position]) {
+        *position += 1;
+    }
+    chars[start..*position].iter().collect()
+}
+
+/// Converts until the end of the body, or inside a placeholder (`nested`) until its
+/// closing `}`, which is consumed.
+fn convert_until_brace(
+    chars: &[char],
+    position: &mut usize,
+    variables: &HashMap<&str, String>,
+    choices: &mut HashMap<i32, Vec<String>>,
+    nested: bool,
+) -> String {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut output = String::new();
+    while *position < chars.len() {
+        let c = chars[*position];
+        *position += 1;
+        match c {
+            '}' if nested => return output,
+            '}' => output.push_str("\\}"),
+            '\\' if *position < chars.len() => {
+                output.push_str(&escape(&chars[*position].to_string()));
+                *position += 1;
+            }
+            '$' if chars.get(*position).is_some_and(|c| c.is_ascii_digit()) => {
+                output.push('$');
+                output.push_str(&read_while(chars, position, |c| c.is_ascii_digit()));
+            }
+            '$' if chars.get(*position).is_some_and(|c| is_name(*c)) => {
+                let name = read_while(chars, position, is_name);
+                output.push_str(&escape(
+                    &variables.get(name.as_str()).cloned().unwrap_or_default(),
+                ));
+            }
+            '$' if chars.get(*position) == Some(&'{') => {
+                *position += 1;
+                let name = read_while(chars, position, is_name);
+                let next = chars.get(*position).copied();
+                if let Ok(stop) = name.parse::<i32>() {
+                    match next {
+                        Some(':') => {
+                            *position += 1;
+                            let default =
+                                convert_until_brace(chars, position, variables, choices, true);
+                            output.push_str(&format!("${{{stop}:{default}}}"));
+                        }
+                        Some('|') => {
+                            *position += 1;
+                            let list = read_while(chars, position, |c| c != '|');
+                            // Skip the closing `|}`
+                            *position = (*position + 2).min(chars.len());
+                            let options: Vec<String> = list
+                                .split(',')
+                                .map(|option| option.trim().to_string())
+                                .collect();
+                            output.push_str(&format!(
+                                "${{{stop}:{}}}",
+                                escape(options.first().map(String::as_str).unwrap_or_default())
+                            ));
+                            choices.insert(stop, options);
+                        }
+                        _ => {
+                            *position += 1;
+                            output.push_str(&format!("${stop}"));
+                        }
+                    }
+                } else {
+                    let default = match next {
+                        Some(':') => {
+                            *position += 1;
+                            convert_until_brace(chars, position, variables, choices, true)
+                        }
+                        _ => {
+                            // Skip anything unsupported, like transforms, up to the brace
+                            read_while(chars, position, |c| c != '}');
+                            *position += 1;
+                            String::new()
+                        }
+                    };
+                    match variables.get(name.as_str()) {
+                        Some(value) if !value.is_empty() => output.push_str(&escape(value)),
+                        _ => output.push_str(&default),
+                    }
+                }
+            }
+            '$' => output.push_str("\\$"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// The values of the VS Code snippet variables at the cursor.
+fn snippet_variables(state: &State) -> HashMap<&'static str, String> {
+    let buffer = &state.buffer;
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let mut line_start = cursor;
+    line_start.set_line_offset(0);
+    let mut line_end = cursor;
+    if !line_end.ends_line() {
+        line_end.forward_to_line_end();
+    }
+    let selected = buffer
+        .selection_bounds()
+        .map(|(start, end)| buffer.text(&start, &end, true).to_string())
+        .unwrap_or_default();
+    let mut word_start = cursor;
+    while word_start.backward_char() {
+        if !word_start.char().is_alphanumeric() && word_start.char() != '_' {
+            word_start.forward_char();
+            break;
+        }
+    }
+    let path = &state.current_file_path;
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    let now = glib::DateTime::now_local().ok();
+    let date = |format: &str| {
+        now.as_ref()
+            .and_then(|now| now.format(format).ok())
+            .map(|text| text.to_string())
+            .unwrap_or_default()
+    };
+    HashMap::from([
+        ("TM_FILENAME", file_name(path)),
+        (
+            "TM_FILENAME_BASE",
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        ),
+        (
+            "TM_DIRECTORY",
+            path.parent()
+                .map(|parent| parent.display().to_string())
+                .unwrap_or_default(),
+        ),
+        ("TM_FILEPATH", path.display().to_string()),
+        ("TM_SELECTED_TEXT", selected),
+        (
+            "TM_CURRENT_LINE",
+            buffer.text(&line_start, &line_end, true).to_string(),
+        ),
+        (
+            "TM_CURRENT_WORD",
+            buffer.text(&word_start, &cursor, true).to_string(),
+        ),
+        ("TM_LINE_INDEX", cursor.line().to_string()),
+        ("TM_LINE_NUMBER", (cursor.line() + 1).to_string()),
+        ("WORKSPACE_NAME", file_name(&state.current_folder_path)),
+        (
+            "WORKSPACE_FOLDER",
+            state.current_folder_path.display().to_string(),
+        ),
+        ("CURRENT_YEAR", date("%Y")),
+        ("CURRENT_YEAR_SHORT", date("%y")),
+        ("CURRENT_MONTH", date("%m")),
+        ("CURRENT_MONTH_NAME", date("%B")),
+        ("CURRENT_MONTH_NAME_SHORT", date("%b")),
+        ("CURRENT_DATE", date("%d")),
+        ("CURRENT_DAY_NAME", date("%A")),
+        ("CURRENT_DAY_NAME_SHORT", date("%a")),
+        ("CURRENT_HOUR", date("%H")),
+        ("CURRENT_MINUTE", date("%M")),
+        ("CURRENT_SECOND", date("%S")),
+        ("CURRENT_SECONDS_UNIX", date("%s")),
+        (
+            "RANDOM",
+            format!("{:06}", glib::random_int_range(0, 1_000_000)),
+        ),
+        (
+            "RANDOM_HEX",
+            format!("{:06x}", glib::random_int_range(0, 0x1000000)),
+        ),
+    ])
+}
+
+/// Expands the snippet at `index` in the library in place of the text between the
+/// `start` and `end` offsets, the typed prefix or the selection, as one undoable edit.
+pub(crate) fn insert_snippet(state: &mut State, index: usize, start: i32, end: i32) {
+    let Some(definition) = state.snippets.borrow().snippets.get(index).cloned() else {
+        return;
+    };
+    // The variables are read before the prefix or selection is replaced
+    let mut choices = HashMap::new();
+    let text = convert_snippet_body(&definition.body, &snippet_variables(state), &mut choices);
+    let snippet = match Snippet::new_parsed(&text) {
+        Ok(snippet) => snippet,
+        Err(_) => {
+            state.toast_overlay.add_toast(Toast::new(&format!(
+                "Couldn't parse snippet \"{}\"",
+                definition.name
+            )));
+            return;
+        }
+    };
+    let buffer = &state.buffer;
+    let mut start = buffer.iter_at_offset(start);
+    let mut end = buffer.iter_at_offset(end);
+    buffer.begin_user_action();
+    buffer.delete(&mut start, &mut end);
+    state.editor.push_snippet(&snippet, Some(&mut start));
+    buffer.end_user_action();
+    if choices.is_empty() {
+        state.snippets.borrow_mut().active = None;
+        return;
+    }
+
+    // Offer the choices through the completion popup whenever a tab stop with choices is focused
+    let show_choices = clone!(
+        #[weak(rename_to = editor)]
+        state.editor,
+        #[strong(rename_to = library)]
+        state.snippets,
+        move |snippet: &Snippet| {
+            let has_choices = library.borrow().active.as_ref().is_some_and(|active| {
+                &active.snippet == snippet && active.choices.contains_key(&snippet.focus_position())
+            });
+            if has_choices {
+                glib::idle_add_local_once(move || editor.completion().show());
+            }
+        }
+    );
+    snippet.connect_focus_position_notify(show_choices.clone());
+    state.snippets.borrow_mut().active = Some(ActiveSnippet {
+        snippet: snippet.clone(),
+        choices,
+    });
+    show_choices(&snippet);
+}
+
+/// Copies a VS Code snippet file into the snippets folder and reloads the snippets.
+pub(crate) fn import_snippets(state: &mut State, path: PathBuf) {
+    let imported = std::fs::read_to_string(&path)
+        .map(|contents| parse_snippet_file(&path, &contents).len())
+        .unwrap_or_default();
+    let copied = path
+        .file_name()
+        .map(|file_name| std::fs::copy(&path, snippets_dir().join(file_name)));
+    let message = match copied {
+        Some(Ok(_)) if imported > 0 => {
+            state.snippets.borrow_mut().snippets = load_snippets();
+            format!("Imported {imported} snippet(s)")
+        }
+        Some(Ok(_)) => "No snippets found in the file".to_string(),
+        _ => "Couldn't import snippets".to_string(),
+    };
+    state.toast_overlay.add_toast(Toast::new(&message));
+}
+
+pub fn setup_snippets(
+    editor: &sourceview5::View,
+    sender: &relm4::ComponentSender<State>,
+) -> Rc<RefCell<SnippetLibrary>> {
+    // Snippets only come from the JSON files, GtkSourceView's own are left out
+    SnippetManager::default().set_search_path(&[]);
+    let library = Rc::new(RefCell::new(SnippetLibrary {
+        snippets: load_snippets(),
+        active: None,
+    }));
+    attach_snippets(editor, &library, sender);
+    library
+}
+
+/// Offers the library's snippets in an editor's completion.
+pub fn attach_snippets(
+    editor: &sourceview5::View,
+    library: &Rc<RefCell<SnippetLibrary>>,
+    sender: &relm4::ComponentSender<State>,
+) {
+    // Snippets stay enabled for moving between tab stops
+    editor.set_enable_snippets(true);
+    editor.completion().add_provider(&SnippetProvider::new(
+        library.clone(),
+        sender.input_sender().clone(),
+    ));
+}
+
+/// A snippet, or one of the choices for the focused tab stop.
+#[derive(Debug, Clone)]
+pub enum SnippetProposal {
+    Snippet(usize),
+    Choice(String),
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct SnippetProposalObject {
+        pub proposal: OnceCell<SnippetProposal>,
+        pub label: OnceCell<(String, String)>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for SnippetProposalObject {
+        const NAME: &'static str = "CryptumSnippetProposal";
+        type Type = super::SnippetProposalObject;
+        type Interfaces = (CompletionProposal,);
+    }
+
+    impl ObjectImpl for SnippetProposalObject {}
+    impl CompletionProposalImpl for SnippetProposalObject {}
+
+    #[derive(Default)]
+    pub struct SnippetProvider {
+        pub library: OnceCell<Rc<RefCell<SnippetLibrary>>>,
+        pub sender: OnceCell<relm4::Sender<Msg>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for SnippetProvider {
+        const NAME: &'static str = "CryptumSnippetProvider";
+        type Type = super::SnippetProvider;
+        type Interfaces = (sourceview5::CompletionProvider,);
+    }
+
+    impl ObjectImpl for SnippetProvider {}
+
+    impl CompletionProviderImpl for SnippetProvider {
+        fn title(&self) -> Option<glib::GString> {
+            Some("Snippets".into())
+        }
+
+        fn priority(&self, _context: &CompletionContext) -> i32 {
+            // Choices for the focused tab stop come before everything else
+            200
+        }
+
+        fn populate_future(
+            &self,
+            context: &CompletionContext,
+        ) -> Pin<Box<dyn Future<Output = Result<gio::ListModel, glib::Error>>>> {
+            let store = gio::ListStore::new::<super::SnippetProposalObject>();
+            let word = context.word().to_string();
+            if let Some(library) = self.library.get() {
+                let library = library.borrow();
+                let choices = library
+                    .active
+                    .as_ref()
+                    .and_then(|active| active.choices.get(&active.snippet.focus_position()));
+                match choices {
+                    Some(choices) => {
+                        for choice in choices {
+                            store.append(&super::SnippetProposalObject::new(
+                                SnippetProposal::Choice(choice.clone()),
+                                choice.clone(),
+                                String::new(),
+                            ));
+                        }
+                    }
+                    // With a selection every snippet is listed, to wrap it in one
+                    None if !word.is_empty()
+                        || context
+                            .buffer()
+                            .is_some_and(|buffer| buffer.has_selection()) =>
+                    {
+                        let language = context.language().map(|language| language.id());
+                        for (index, snippet) in library.snippets.iter().enumerate() {
+                            if !snippet.applies_to(language.as_deref()) {
+                                continue;
+                            }
+                            if let Some(prefix) = snippet
+                                .prefixes
+                                .iter()
+                                .find(|prefix| prefix.starts_with(&word))
+                            {
+                                store.append(&super::SnippetProposalObject::new(
+                                    SnippetProposal::Snippet(index),
+                                    prefix.clone(),
+                                    snippet.name.clone(),
+                                ));
+                            }
+                        }
+                    }
+                    None => {}
+                }
+            }
+            Box::pin(async move { Ok(store.upcast()) })
+        }
+
+        fn display(
+            &self,
+            _context: &CompletionContext,
+            proposal: &CompletionProposal,
+            cell: &CompletionCell,
+        ) {
+            let Some(proposal) = proposal.downcast_ref::<super::SnippetProposalObject>() else {
+                return;
+            };
+            let (label, detail) = proposal.imp().label.get().cloned().unwrap_or_default();
+            match cell.column() {
+                CompletionColumn::TypedText => cell.set_text(Some(&label)),
+                CompletionColumn::After => cell.set_text(Some(&detail)),
+                _ => cell.set_text(None),
+            }
+        }
+
+        fn activate(&self, context: &CompletionContext, proposal: &CompletionProposal) {
+            let (Some(proposal), Some(buffer)) = (
+                proposal.downcast_ref::<super::SnippetProposalObject>(),
+                context.buffer(),
+            ) else {
+                return;
+            };
+            // Replace the selected placeholder text or the selection, or the typed word
+            let Some((mut start, mut end)) = buffer.selection_bounds().or(context.bounds()) else {
+                return;
+            };
+            match proposal.imp().proposal.get() {
+                Some(SnippetProposal::Choice(choice)) => {
+                    buffer.begin_user_action();
+                    buffer.delete(&mut start, &mut end);
+                    buffer.insert(&mut start, choice);
+                    buffer.end_user_action();
+                }
+                // Expanding needs the file's details, so it's left to the main loop, which
+                // also replaces the text
+                Some(SnippetProposal::Snippet(index)) => {
+                    if let Some(sender) = self.sender.get() {
+                        sender.emit(Msg::InsertSnippet(*index, start.offset(), end.offset()));
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct SnippetProposalObject(ObjectSubclass<imp::SnippetProposalObject>)
+        @implements CompletionProposal;
+}
+
+impl SnippetProposalObject {
+    fn new(proposal: SnippetProposal, label: String, detail: String) -> Self {
+        let object: Self = glib::Object::new();
+        _ = object.imp().proposal.set(proposal);
+        _ = object.imp().label.set((label, detail));
+        object
+    }
+}
+
+glib::wrapper! {
+    /// Completes snippet prefixes from the JSON snippet files, and the choices of the
+    /// focused tab stop while a snippet is being filled in.
+    pub struct SnippetProvider(ObjectSubclass<imp::SnippetProvider>)
+        @implements sourceview5::CompletionProvider;
+}
+
+impl SnippetProvider {
+    pub fn new(library: Rc<RefCell<SnippetLibrary>>, sender: relm4::Sender<Msg>) -> Self {
+        let provider: Self = glib::Object::new();
+        _ = provider.imp().library.set(library);
+        _ = provider.imp().sender.set(sender);
+        provider
+    }
+}
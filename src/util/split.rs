@@ -0,0 +1,505 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use gtk4::{EventControllerFocus, Paned, ScrolledWindow, glib, prelude::*};
+use libadwaita::{AlertDialog, ResponseAppearance, Toast, prelude::*};
+use relm4::gtk;
+use sourceview5::{SearchContext, prelude::*};
+
+use crate::{
+    app::model::{Msg, State},
+    fs::editorconfig::apply_editorconfig,
+    lsp::manager::{attach_lsp_view, close_document, open_document, watch_lsp_buffer},
+    util::{
+        brackets::setup_auto_close,
+        completion::attach_completion,
+        folding::{Folding, attach_fold_gutter, detach_fold_gutter, remember_folds, setup_folding},
+        indentation::DocumentIndentation,
+        keymap::{attach_keymap, detach_keymap},
+        markdown::{attach_preview_scroll, refresh_preview, watch_preview_buffer},
+        multi_cursor::{MultiCursor, setup_multi_cursor},
+        outline::{attach_outline, refresh_outline},
+        search::{search_context, update_search},
+        snippets::attach_snippets,
+        whitespace::apply_whitespace_drawing,
+        widget::setup_editor,
+    },
+};
+
+/// Editor settings a split shares with the main editor. The rest, like indentation,
+/// follow the file each one shows.
+const SHARED_PROPERTIES: &[&str] = &[
+    "monospace",
+    "show-line-numbers",
+    "highlight-current-line",
+    "auto-indent",
+    "smart-backspace",
+];
+
+/// An open file while a split showing another one has focus. The focused split's file,
+/// and what goes with it, is kept in `State` for everything else to work on. Splits of the
+/// same file share its buffer, and so its folds, carets and search.
+#[derive(Debug)]
+pub struct PaneDocument {
+    pub buffer: sourceview5::Buffer,
+    pub file_path: PathBuf,
+    pub folding: Rc<Folding>,
+    pub multi_cursor: MultiCursor,
+    pub search_context: SearchContext,
+    pub indentation: DocumentIndentation,
+}
+
+/// An editor with its scroll window and mini map.
+#[derive(Debug)]
+pub struct EditorPane {
+    pub container: gtk::Box,
+    pub view: sourceview5::View,
+}
+
+impl EditorPane {
+    fn shows(&self, buffer: &sourceview5::Buffer) -> bool {
+        self.view.buffer() == *buffer.upcast_ref::<gtk::TextBuffer>()
+    }
+}
+
+/// The editor area, holding the main editor and any splits of it in nested `Paned`s.
+/// A split starts on the file it was split from, and can then open another.
+#[derive(Debug)]
+pub struct SplitViews {
+    pub area: gtk::Box,
+    /// The main editor first, then splits in the order they were opened
+    pub panes: Vec<EditorPane>,
+    /// The pane whose file is in `State`, the last one focused
+    pub active: usize,
+    /// The files shown in other panes than the active one, but not in it
+    pub documents: Vec<PaneDocument>,
+    sender: relm4::ComponentSender<State>,
+}
+
+fn pane_container(scroll: &ScrolledWindow, mini_map: &sourceview5::Map) -> gtk::Box {
+    let container = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+    container.append(scroll);
+    container.append(mini_map);
+    container
+}
+
+/// Makes a pane's file the current one when its editor gains focus.
+fn track_focus(view: &sourceview5::View, sender: &relm4::ComponentSender<State>) {
+    let focus = EventControllerFocus::new();
+    focus.connect_enter(glib::clone!(
+        #[strong]
+        sender,
+        move |focus| {
+            if let Some(view) = focus.widget().and_downcast::<sourceview5::View>() {
+                sender.input(Msg::ActivatePane(view));
+            }
+        }
+    ));
+    view.add_controller(focus);
+}
+
+pub fn setup_splits(
+    editor: &sourceview5::View,
+    editor_scroll_window: &ScrolledWindow,
+    mini_map: &sourceview5::Map,
+    sender: &relm4::ComponentSender<State>,
+) -> SplitViews {
+    track_focus(editor, sender);
+    let container = pane_container(editor_scroll_window, mini_map);
+    let area = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .hexpand(true)
+        .build();
+    area.append(&container);
+    SplitViews {
+        area,
+        panes: vec![EditorPane {
+            container,
+            view: editor.clone(),
+        }],
+        active: 0,
+        documents: Vec::new(),
+        sender: sender.clone(),
+    }
+}
+
+/// The buffer of every open file.
+pub(crate) fn pane_buffers(state: &State) -> impl Iterator<Item = &sourceview5::Buffer> {
+    std::iter::once(&state.buffer).chain(
+        state
+            .splits
+            .documents
+            .iter()
+            .map(|document| &document.buffer),
+    )
+}
+
+/// The index of the pane holding keyboard focus, if any does.
+fn focused_pane(state: &State) -> Option<usize> {
+    let focus = GtkWindowExt::focus(&state.root)?;
+    state
+        .splits
+        .panes
+        .iter()
+        .position(|pane| focus == pane.view.clone().upcast::<gtk::Widget>())
+}
+
+/// Puts `new` where `old` is in the widget tree.
+fn replace_child(area: &gtk::Box, old: &gtk::Widget, new: &impl IsA<gtk::Widget>) {
+    match old
+        .parent()
+        .and_then(|parent| parent.downcast::<Paned>().ok())
+    {
+        Some(paned) if paned.start_child().as_ref() == Some(old) => {
+            paned.set_start_child(Some(new));
+        }
+        Some(paned) => paned.set_end_child(Some(new)),
+        None => {
+            area.remove(old);
+            area.append(new);
+        }
+    }
+}
+
+/// Sets up a split's editor the way the main one is. Its buffer is set up on its own, as
+/// splits of one file share it.
+fn setup_pane(state: &mut State, view: &sourceview5::View, sender: &relm4::ComponentSender<State>) {
+    attach_completion(view, &state.completion);
+    setup_auto_close(view, &state.brackets);
+    attach_snippets(view, &state.snippets, sender);
+    attach_lsp_view(view, sender);
+    attach_preview_scroll(view, &state.markdown_preview.container, sender);
+    attach_keymap(&mut state.keymap, view, sender);
+    track_focus(view, sender);
+    for property in SHARED_PROPERTIES {
+        state.splits.panes[0]
+            .view
+            .bind_property(property, view, property)
+            .bidirectional()
+            .sync_create()
+            .build();
+    }
+}
+
+/// Gives `view` a new empty buffer, set up the way the main one is, returning the
+/// document for it.
+fn new_document(state: &State, view: &sourceview5::View) -> PaneDocument {
+    let sender = &state.splits.sender;
+    let buffer = sourceview5::Buffer::builder()
+        .highlight_matching_brackets(true)
+        .build();
+    buffer.set_style_scheme(state.buffer_style.as_ref());
+    view.set_buffer(Some(&buffer));
+    let multi_cursor = setup_multi_cursor(view, &buffer);
+    let folding = setup_folding(view, &buffer, sender);
+    watch_lsp_buffer(&buffer, &state.lsp.dirty, sender);
+    attach_outline(&buffer, sender);
+    watch_preview_buffer(&buffer, &state.markdown_preview.container, sender);
+    buffer.connect_cursor_position_notify(glib::clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::CursorPositionChanged)
+    ));
+    let search_context = search_context(&buffer, &state.find_bar.context.settings(), sender);
+    search_context.set_highlight(state.find_bar.context.is_highlight());
+    PaneDocument {
+        buffer,
+        file_path: PathBuf::new(),
+        folding,
+        multi_cursor,
+        search_context,
+        indentation: state.indentation.document(),
+    }
+}
+
+/// Trades the current file for `document`.
+fn swap_document(state: &mut State, document: &mut PaneDocument) {
+    std::mem::swap(&mut state.buffer, &mut document.buffer);
+    std::mem::swap(&mut state.current_file_path, &mut document.file_path);
+    std::mem::swap(&mut state.folding, &mut document.folding);
+    std::mem::swap(&mut state.multi_cursor, &mut document.multi_cursor);
+    std::mem::swap(&mut state.find_bar.context, &mut document.search_context);
+    state.indentation.swap_document(&mut document.indentation);
+    state.find_bar.scope = None;
+    state.snippets.borrow_mut().active = None;
+}
+
+/// Takes the fold icons and caret handlers of the current file out of the active editor,
+/// which is about to show another one.
+fn detach_active_view(state: &State) {
+    detach_fold_gutter(&state.editor, &state.folding);
+    state.multi_cursor.detach(&state.editor);
+}
+
+/// Adds the fold icons and caret handlers of the current file to the active editor.
+fn attach_active_view(state: &State) {
+    attach_fold_gutter(&state.editor, &state.folding, &state.splits.sender);
+    state.multi_cursor.attach(&state.editor);
+    state.multi_cursor.set_editor(&state.editor);
+}
+
+/// Forgets the files no pane shows anymore.
+fn drop_hidden_documents(state: &mut State) {
+    let splits = &mut state.splits;
+    splits
+        .documents
+        .retain(|document| splits.panes.iter().any(|pane| pane.shows(&document.buffer)));
+}
+
+/// Lets the current file take over after a change of the active pane or what it shows.
+fn show_current_document(state: &mut State) {
+    apply_editorconfig(state);
+    open_document(state);
+    update_search(state);
+    refresh_outline(state);
+    refresh_preview(state);
+    apply_whitespace_drawing(state);
+}
+
+/// Gives the active editor a new buffer if its file is shown in another pane too, so what
+/// is opened next doesn't replace the file there.
+pub(crate) fn separate_document(state: &mut State) {
+    let active = state.splits.active;
+    let shared = state
+        .splits
+        .panes
+        .iter()
+        .enumerate()
+        .any(|(index, pane)| index != active && pane.shows(&state.buffer));
+    if !shared {
+        return;
+    }
+    close_document(state);
+    detach_active_view(state);
+    let mut document = new_document(state, &state.editor);
+    swap_document(state, &mut document);
+    state.splits.documents.push(document);
+}
+
+/// Shows `path` in the active editor with the buffer another pane already has for it.
+/// Returns false when no other pane has it open, leaving it to be loaded.
+pub(crate) fn switch_pane_document(state: &mut State, path: &Path) -> bool {
+    if path.as_os_str().is_empty() || path == state.current_file_path {
+        return false;
+    }
+    let Some(index) = state
+        .splits
+        .documents
+        .iter()
+        .position(|document| document.file_path == path)
+    else {
+        separate_document(state);
+        return false;
+    };
+    close_document(state);
+    detach_active_view(state);
+    let mut document = state.splits.documents.remove(index);
+    state.editor.set_buffer(Some(&document.buffer));
+    swap_document(state, &mut document);
+    state.splits.documents.push(document);
+    attach_active_view(state);
+    drop_hidden_documents(state);
+    show_current_document(state);
+    true
+}
+
+/// Splits the focused editor (or the main one) in two, side by side or stacked. The new
+/// split shows the current file, sharing its buffer.
+pub(crate) fn split_editor(
+    state: &mut State,
+    orientation: gtk4::Orientation,
+    sender: &relm4::ComponentSender<State>,
+) {
+    let view = setup_editor(&state.buffer);
+    setup_pane(state, &view, sender);
+    attach_fold_gutter(&view, &state.folding, sender);
+    state.multi_cursor.attach(&view);
+    let mini_map = sourceview5::Map::builder()
+        .width_request(120)
+        .overflow(gtk4::Overflow::Visible)
+        .view(&view)
+        .build();
+    state
+        .mini_map
+        .bind_property("visible", &mini_map, "visible")
+        .sync_create()
+        .build();
+    let scroll = ScrolledWindow::builder().child(&view).build();
+    let container = pane_container(&scroll, &mini_map);
+
+    let target = focused_pane(state).unwrap_or(state.splits.active);
+    let splits = &mut state.splits;
+    let old: gtk::Widget = splits.panes[target].container.clone().upcast();
+    let paned = Paned::builder()
+        .orientation(orientation)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+    replace_child(&splits.area, &old, &paned);
+    paned.set_start_child(Some(&old));
+    paned.set_end_child(Some(&container));
+    // Split evenly once the paned has been sized
+    glib::idle_add_local_once(glib::clone!(
+        #[weak]
+        paned,
+        move || {
+            let size = match paned.orientation() {
+                gtk4::Orientation::Horizontal => paned.width(),
+                _ => paned.height(),
+            };
+            paned.set_position(size / 2);
+        }
+    ));
+
+    // Start the new view on the same part of the document
+    view.scroll_to_mark(&state.buffer.get_insert(), 0.1, true, 0.0, 0.5);
+    view.grab_focus();
+    splits.panes.push(EditorPane { container, view });
+    apply_whitespace_drawing(state);
+}
+
+/// Makes the file of the pane showing `view` the current one, as its editor gained focus.
+pub(crate) fn activate_pane(state: &mut State, view: &sourceview5::View) {
+    let Some(index) = state
+        .splits
+        .panes
+        .iter()
+        .position(|pane| pane.view == *view)
+    else {
+        return;
+    };
+    state.splits.active = index;
+    state.editor = view.clone();
+    if state.splits.panes[index].shows(&state.buffer) {
+        state.multi_cursor.set_editor(view);
+        return;
+    }
+    let Some(position) = state
+        .splits
+        .documents
+        .iter()
+        .position(|document| state.splits.panes[index].shows(&document.buffer))
+    else {
+        return;
+    };
+    // Language servers are told about one file at a time
+    close_document(state);
+    remember_folds(state);
+    let mut document = state.splits.documents.remove(position);
+    swap_document(state, &mut document);
+    state.splits.documents.push(document);
+    state.multi_cursor.set_editor(view);
+    show_current_document(state);
+}
+
+/// Closes the focused split, or the newest one while the main editor is focused, asking
+/// first if its file has unsaved changes no other split shows.
+pub(crate) fn close_split(state: &mut State, sender: relm4::ComponentSender<State>) {
+    let index = match focused_pane(state) {
+        Some(index) if index > 0 => index,
+        _ => state.splits.panes.len() - 1,
+    };
+    if index == 0 {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("There are no splits to close"));
+        return;
+    }
+    let view = state.splits.panes[index].view.clone();
+    let buffer = view.buffer();
+    let shown_elsewhere = state
+        .splits
+        .panes
+        .iter()
+        .any(|pane| pane.view != view && pane.view.buffer() == buffer);
+    if shown_elsewhere || !buffer.is_modified() {
+        close_pane(state, &view);
+        return;
+    }
+    let dialog = AlertDialog::builder()
+        .heading("Close Unsaved File?")
+        .body("The split's file has unsaved changes, which will be lost.")
+        .default_response("cancel")
+        .close_response("cancel")
+        .build();
+    dialog.add_responses(&[("cancel", "Cancel"), ("close", "Close")]);
+    dialog.set_response_appearance("close", ResponseAppearance::Destructive);
+    dialog.connect_response(None, move |_, response| {
+        if response == "close" {
+            sender.input(Msg::ClosePane(view.clone()));
+        }
+    });
+    dialog.present(Some(&state.root));
+}
+
+/// Closes the split showing `view`, dropping its file if no other split shows it.
+pub(crate) fn close_pane(state: &mut State, view: &sourceview5::View) {
+    let Some(index) = state
+        .splits
+        .panes
+        .iter()
+        .position(|pane| pane.view == *view)
+        .filter(|index| *index > 0)
+    else {
+        return;
+    };
+    // The closing split can't stay the active one
+    if index == state.splits.active {
+        let other = state.splits.panes[index - 1].view.clone();
+        activate_pane(state, &other);
+    }
+    let document = state
+        .splits
+        .documents
+        .iter()
+        .find(|document| state.splits.panes[index].shows(&document.buffer));
+    match document {
+        Some(document) => {
+            detach_fold_gutter(view, &document.folding);
+            document.multi_cursor.detach(view);
+        }
+        None => {
+            detach_fold_gutter(view, &state.folding);
+            state.multi_cursor.detach(view);
+        }
+    }
+    let pane = state.splits.panes.remove(index);
+    if state.splits.active > index {
+        state.splits.active -= 1;
+    }
+    detach_keymap(&mut state.keymap, &pane.view);
+    drop_hidden_documents(state);
+    let splits = &mut state.splits;
+    let container: gtk::Widget = pane.container.upcast();
+    // The pane's paned is replaced by the other side of the split
+    if let Some(paned) = container
+        .parent()
+        .and_then(|parent| parent.downcast::<Paned>().ok())
+    {
+        let sibling = if paned.start_child().as_ref() == Some(&container) {
+            paned.end_child()
+        } else {
+            paned.start_child()
+        };
+        paned.set_start_child(None::<&gtk::Widget>);
+        paned.set_end_child(None::<&gtk::Widget>);
+        if let Some(sibling) = sibling {
+            replace_child(&splits.area, &paned.upcast(), &sibling);
+        }
+    }
+    let next = splits.panes[index.min(splits.panes.len() - 1)].view.clone();
+    next.grab_focus();
+}
+
+/// Moves focus to the next editor, wrapping around.
+pub(crate) fn focus_next_split(state: &mut State) {
+    let count = state.splits.panes.len();
+    let next = focused_pane(state).map_or(0, |index| (index + 1) % count);
+    state.splits.panes[next].view.grab_focus();
+}
@@ -0,0 +1,298 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Module,
+    Class,
+    Interface,
+    Type,
+    Constant,
+    Macro,
+    Heading,
+}
+
+impl SymbolKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SymbolKind::Function => "fn",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Impl => "impl",
+            SymbolKind::Module => "mod",
+            SymbolKind::Class => "class",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "const",
+            SymbolKind::Macro => "macro",
+            SymbolKind::Heading => "#",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Zero based line number
+    pub line: i32,
+    /// Nesting level, used to indent the outline
+    pub depth: usize,
+}
+
+static RUST_ITEM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^(\s*)(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|default|extern\s+"[^"]*")\s+)*(fn|struct|enum|union|trait|mod|type|const|static|macro_rules!)\s*([A-Za-z_][A-Za-z0-9_]*)"#,
+    )
+    .unwrap()
+});
+static RUST_IMPL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\s*)(?:unsafe\s+)?impl\b(?:\s*<[^{]*?>)?\s+([^{]+?)\s*(?:\{|where\b|$)").unwrap()
+});
+static PYTHON_ITEM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\s*)(?:async\s+)?(def|class)\s+([A-Za-z_][A-Za-z0-9_]*)").unwrap()
+});
+static JS_ITEM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(\s*)(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:async\s+)?(function\*?|class|interface|type|enum|namespace)\s+([A-Za-z_$][\w$]*)",
+    )
+    .unwrap()
+});
+static JS_ARROW: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(\s*)(?:export\s+)?(?:const|let|var)\s+([A-Za-z_$][\w$]*)\s*(?::[^=]+)?=\s*(?:async\s+)?(?:function\b|\([^)]*\)\s*(?::[^=]+)?=>|[A-Za-z_$][\w$]*\s*=>)",
+    )
+    .unwrap()
+});
+static JS_METHOD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(\s+)(?:(?:public|private|protected|static|readonly|async|get|set)\s+)*([A-Za-z_$][\w$]*)\s*(?:<[^>]*>)?\([^)]*\)\s*(?::[^{]+)?\{",
+    )
+    .unwrap()
+});
+static MARKDOWN_HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(#{1,6})\s+(.*?)(?:\s+#+)?\s*$").unwrap());
+
+const JS_KEYWORDS: &[&str] = &["if", "for", "while", "switch", "catch", "with", "return"];
+
+fn indent_depth(indent: &str) -> usize {
+    indent
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum::<usize>()
+        / 4
+}
+
+/// Extracts the symbols for the outline, based on the sourceview language id.
+/// Returns nothing for languages without an extractor.
+pub fn extract_symbols(language_id: &str, text: &str) -> Vec<Symbol> {
+    match language_id {
+        "rust" => extract_rust(text),
+        "python" | "python3" => extract_python(text),
+        "js" | "javascript" | "typescript" | "typescript-jsx" | "jsx" => extract_js(text),
+        "markdown" => extract_markdown(text),
+        _ => Vec::new(),
+    }
+}
+
+fn extract_rust(text: &str) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    for (line, content) in text.lines().enumerate() {
+        if let Some(caps) = RUST_ITEM.captures(content) {
+            let kind = match &caps[2] {
+                "fn" => SymbolKind::Function,
+                "struct" | "union" => SymbolKind::Struct,
+                "enum" => SymbolKind::Enum,
+                "trait" => SymbolKind::Trait,
+                "mod" => SymbolKind::Module,
+                "type" => SymbolKind::Type,
+                "macro_rules!" => SymbolKind::Macro,
+                _ => SymbolKind::Constant,
+            };
+            symbols.push(Symbol {
+                name: caps[3].to_string(),
+                kind,
+                line: line as i32,
+                depth: indent_depth(&caps[1]),
+            });
+        } else if let Some(caps) = RUST_IMPL.captures(content) {
+            symbols.push(Symbol {
+                name: caps[2].to_string(),
+                kind: SymbolKind::Impl,
+                line: line as i32,
+                depth: indent_depth(&caps[1]),
+            });
+        }
+    }
+    symbols
+}
+
+fn extract_python(text: &str) -> Vec<Symbol> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(line, content)| {
+            let caps = PYTHON_ITEM.captures(content)?;
+            Some(Symbol {
+                name: caps[3].to_string(),
+                kind: if &caps[2] == "class" {
+                    SymbolKind::Class
+                } else {
+                    SymbolKind::Function
+                },
+                line: line as i32,
+                depth: indent_depth(&caps[1]),
+            })
+        })
+        .collect()
+}
+
+fn extract_js(text: &str) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    for (line, content) in text.lines().enumerate() {
+        let symbol = if let Some(caps) = JS_ITEM.captures(content) {
+            let kind = match &caps[2] {
+                "class" => SymbolKind::Class,
+                "interface" => SymbolKind::Interface,
+                "type" => SymbolKind::Type,
+                "enum" => SymbolKind::Enum,
+                "namespace" => SymbolKind::Module,
+                _ => SymbolKind::Function,
+            };
+            Some((caps[1].to_string(), caps[3].to_string(), kind))
+        } else if let Some(caps) = JS_ARROW.captures(content) {
+            Some((
+                caps[1].to_string(),
+                caps[2].to_string(),
+                SymbolKind::Function,
+            ))
+        } else if let Some(caps) = JS_METHOD.captures(content)
+            && !JS_KEYWORDS.contains(&&caps[2])
+        {
+            Some((
+                caps[1].to_string(),
+                caps[2].to_string(),
+                SymbolKind::Function,
+            ))
+        } else {
+            None
+        };
+        if let Some((indent, name, kind)) = symbol {
+            symbols.push(Symbol {
+                name,
+                kind,
+                line: line as i32,
+                depth: indent_depth(&indent),
+            });
+        }
+    }
+    symbols
+}
+
+fn extract_markdown(text: &str) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut in_code_block = false;
+    for (line, content) in text.lines().enumerate() {
+        if content.trim_start().starts_with("```") || content.trim_start().starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+        if let Some(caps) = MARKDOWN_HEADING.captures(content) {
+            symbols.push(Symbol {
+                name: caps[2].to_string(),
+                kind: SymbolKind::Heading,
+                line: line as i32,
+                depth: caps[1].len() - 1,
+            });
+        }
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SymbolKind, extract_symbols};
+
+    fn outline(language_id: &str, text: &str) -> Vec<(String, SymbolKind, i32, usize)> {
+        extract_symbols(language_id, text)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind, symbol.line, symbol.depth))
+            .collect()
+    }
+
+    #[test]
+    fn extracts_rust_items() {
+        let text = "pub(crate) async fn load() {}\n\
+                    struct Point;\n\
+                    impl<T: Clone> Display for Wrapper<T> where T: Debug {\n    \
+                    pub const fn new() -> Self {}\n\
+                    }\n\
+                    macro_rules! square {}\n\
+                    // fn commented() {}\n";
+        assert_eq!(
+            outline("rust", text),
+            vec![
+                ("load".to_string(), SymbolKind::Function, 0, 0),
+                ("Point".to_string(), SymbolKind::Struct, 1, 0),
+                ("Display for Wrapper<T>".to_string(), SymbolKind::Impl, 2, 0),
+                ("new".to_string(), SymbolKind::Function, 3, 1),
+                ("square".to_string(), SymbolKind::Macro, 5, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_python_items() {
+        let text = "class Parser:\n    async def parse(self):\n        pass\ndef main():\n";
+        assert_eq!(
+            outline("python3", text),
+            vec![
+                ("Parser".to_string(), SymbolKind::Class, 0, 0),
+                ("parse".to_string(), SymbolKind::Function, 1, 1),
+                ("main".to_string(), SymbolKind::Function, 3, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_js_items() {
+        let text = "export default class App {\n    render() {\n        if (ready) {\n\
+                    }\n    }\n}\nconst add = (a, b) => a + b;\ninterface Props {}\n";
+        assert_eq!(
+            outline("typescript", text),
+            vec![
+                ("App".to_string(), SymbolKind::Class, 0, 0),
+                ("render".to_string(), SymbolKind::Function, 1, 1),
+                ("add".to_string(), SymbolKind::Function, 6, 0),
+                ("Props".to_string(), SymbolKind::Interface, 7, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_markdown_headings() {
+        let text = "# C#\n## Usage ##\n```\n# not a heading\n```\n### Issue #12\n#nospace\n";
+        assert_eq!(
+            outline("markdown", text),
+            vec![
+                ("C#".to_string(), SymbolKind::Heading, 0, 0),
+                ("Usage".to_string(), SymbolKind::Heading, 1, 1),
+                ("Issue #12".to_string(), SymbolKind::Heading, 5, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_languages() {
+        assert!(outline("c", "int main() {}").is_empty());
+    }
+}
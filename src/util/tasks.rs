@@ -0,0 +1,497 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::LazyLock,
+};
+
+use gtk4::{
+    GestureClick, Revealer, ScrolledWindow, TextView,
+    glib::{self, clone},
+    prelude::*,
+};
+use libadwaita::Toast;
+use regex::Regex;
+use relm4::gtk;
+use serde::Deserialize;
+
+use crate::{
+    app::model::{Msg, State},
+    fs::file::open_file,
+    lsp::{
+        client::path_to_uri,
+        diagnostics::{Diagnostic, show_diagnostics},
+    },
+    util::{
+        picker::{PickerAction, PickerItem, show_picker},
+        problems::refresh_problems,
+        widget::place_cursor,
+    },
+};
+
+/// `file:line` or `file:line:col`, as printed by most compilers and test runners
+static LOCATION_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"((?:[A-Za-z]:)?[\w./\\-]*\w\.\w+):(\d+)(?::(\d+))?").unwrap());
+/// `error: message` at the start of a line, which rustc prints above the ` --> file:line:col`
+/// line. Notes and help are matched too, so their locations aren't taken for the error's
+static PROBLEM_HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(error|warning|note|help)(?:\[[^\]]*\])?: (.+)").unwrap());
+/// `: error: message` after a location, as gcc, clang and many linters print it
+static PROBLEM_AFTER_LOCATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^:\s*(?:fatal )?(error|warning|note)(?:\[[^\]]*\])?:\s*(.+)").unwrap()
+});
+static ANSI_ESCAPE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]").unwrap());
+
+/// A task from `.cryptum/tasks.json` in the open folder.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TaskDefinition {
+    pub label: String,
+    /// Run through the shell when `args` is empty, so pipes and `&&` work
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Working directory, relative to the open folder
+    #[serde(default)]
+    pub cwd: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TasksFile {
+    tasks: Vec<TaskDefinition>,
+}
+
+/// A `file:line:col` link in the output, by output line and character range.
+#[derive(Debug)]
+struct OutputLink {
+    line: i32,
+    start: i32,
+    end: i32,
+    path: PathBuf,
+    target_line: i32,
+    target_column: i32,
+}
+
+/// The "Output" panel below the editor, and the task running in it.
+#[derive(Debug)]
+pub struct TaskRunner {
+    pub revealer: Revealer,
+    pub output: TextView,
+    pub status_label: gtk::Label,
+    pub cancel_button: gtk::Button,
+    pub rerun_button: gtk::Button,
+    /// Tasks from the last time the task picker was shown
+    pub tasks: Vec<TaskDefinition>,
+    pub last_task: Option<TaskDefinition>,
+    pub child: Option<Child>,
+    /// Incremented for every run, so output from a cancelled run can be ignored
+    pub run_id: u32,
+    cwd: PathBuf,
+    links: Vec<OutputLink>,
+    /// The last `error: message` line, waiting for its location
+    heading: Option<(u64, String)>,
+    sender: relm4::ComponentSender<State>,
+}
+
+pub fn setup_tasks(sender: &relm4::ComponentSender<State>) -> TaskRunner {
+    let output = TextView::builder()
+        .editable(false)
+        .cursor_visible(false)
+        .monospace(true)
+        .wrap_mode(gtk4::WrapMode::WordChar)
+        .left_margin(12)
+        .right_margin(12)
+        .build();
+    output.buffer().create_tag(
+        Some("link"),
+        &[("underline", &gtk4::pango::Underline::Single)],
+    );
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .height_request(200)
+        .child(&output)
+        .build();
+    let header_label = gtk::Label::builder()
+        .label("Output")
+        .css_classes(vec!["heading"])
+        .build();
+    let status_label = gtk::Label::builder()
+        .hexpand(true)
+        .halign(gtk4::Align::Start)
+        .ellipsize(gtk4::pango::EllipsizeMode::End)
+        .css_classes(vec!["dim-label"])
+        .build();
+    let rerun_button = gtk::Button::builder()
+        .icon_name("view-refresh-symbolic")
+        .tooltip_text("Re-run Task")
+        .css_classes(vec!["flat"])
+        .sensitive(false)
+        .build();
+    let cancel_button = gtk::Button::builder()
+        .icon_name("process-stop-symbolic")
+        .tooltip_text("Cancel Task")
+        .css_classes(vec!["flat"])
+        .sensitive(false)
+        .build();
+    let header = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .spacing(8)
+        .margin_start(12)
+        .margin_end(6)
+        .margin_top(2)
+        .build();
+    header.append(&header_label);
+    header.append(&status_label);
+    header.append(&rerun_button);
+    header.append(&cancel_button);
+    let content = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .build();
+    content.append(&gtk::Separator::new(gtk4::Orientation::Horizontal));
+    content.append(&header);
+    content.append(&scroll);
+    let revealer = Revealer::builder()
+        .transition_type(gtk4::RevealerTransitionType::SlideUp)
+        .child(&content)
+        .build();
+
+    // Events
+    rerun_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::RerunTask)
+    ));
+    cancel_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::CancelTask)
+    ));
+    let click = GestureClick::new();
+    click.connect_released(clone!(
+        #[strong]
+        sender,
+        #[weak]
+        output,
+        move |_, _, x, y| {
+            let (x, y) =
+                output.window_to_buffer_coords(gtk4::TextWindowType::Widget, x as i32, y as i32);
+            if let Some(iter) = output.iter_at_location(x, y) {
+                sender.input(Msg::TaskOutputClicked(iter.line(), iter.line_offset()));
+            }
+        }
+    ));
+    output.add_controller(click);
+
+    TaskRunner {
+        revealer,
+        output,
+        status_label,
+        cancel_button,
+        rerun_button,
+        tasks: Vec::new(),
+        last_task: None,
+        child: None,
+        run_id: 0,
+        cwd: PathBuf::new(),
+        links: Vec::new(),
+        heading: None,
+        sender: sender.clone(),
+    }
+}
+
+fn load_tasks(folder: &Path) -> Result<Vec<TaskDefinition>, String> {
+    let tasks_path = folder.join(".cryptum").join("tasks.json");
+    let contents = std::fs::read_to_string(&tasks_path)
+        .map_err(|_| "No tasks found in .cryptum/tasks.json".to_string())?;
+    serde_json::from_str::<TasksFile>(&contents)
+        .map(|tasks_file| tasks_file.tasks)
+        .map_err(|error| format!("Couldn't parse .cryptum/tasks.json: {error}"))
+}
+
+/// Lists the tasks of the open folder in the picker.
+pub(crate) fn show_task_picker(state: &mut State) {
+    if state.current_folder_path.as_os_str().is_empty() {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("Open a folder to run its tasks"));
+        return;
+    }
+    match load_tasks(&state.current_folder_path) {
+        Ok(tasks) => {
+            let items = tasks
+                .iter()
+                .enumerate()
+                .map(|(index, task)| PickerItem {
+                    title: task.label.clone(),
+                    subtitle: if task.args.is_empty() {
+                        task.command.clone()
+                    } else {
+                        format!("{} {}", task.command, task.args.join(" "))
+                    },
+                    action: PickerAction::RunTask(index),
+                    recent: false,
+                })
+                .collect();
+            state.task_runner.tasks = tasks;
+            show_picker(state, "Run Task", "Search tasks by name", items);
+        }
+        Err(message) => state.toast_overlay.add_toast(Toast::new(&message)),
+    }
+}
+
+/// Stops the running task along with anything it started.
+pub(crate) fn cancel_task(state: &mut State) {
+    let Some(child) = state.task_runner.child.as_mut() else {
+        return;
+    };
+    #[cfg(unix)]
+    {
+        // The task runs in its own process group, so this reaches e.g. cargo's rustc too
+        unsafe {
+            libc::killpg(child.id() as i32, libc::SIGTERM);
+        }
+    }
+    _ = child.kill();
+    state.task_runner.status_label.set_label("Cancelling…");
+}
+
+pub(crate) fn run_task(state: &mut State, task: TaskDefinition) {
+    cancel_task(state);
+    if let Some(mut child) = state.task_runner.child.take() {
+        _ = child.wait();
+    }
+    let runner = &mut state.task_runner;
+    runner.run_id += 1;
+    runner.links.clear();
+    runner.heading = None;
+    runner.output.buffer().set_text("");
+    runner.revealer.set_reveal_child(true);
+    runner.rerun_button.set_sensitive(true);
+    runner.last_task = Some(task.clone());
+    runner.cwd = match &task.cwd {
+        Some(cwd) => state.current_folder_path.join(cwd),
+        None => state.current_folder_path.clone(),
+    };
+    state.problems.task_diagnostics.clear();
+    refresh_problems(state);
+    show_diagnostics(state);
+    let runner = &mut state.task_runner;
+
+    let mut command = if task.args.is_empty() {
+        #[cfg(unix)]
+        let mut command = Command::new("sh");
+        #[cfg(unix)]
+        command.arg("-c");
+        #[cfg(windows)]
+        let mut command = Command::new("cmd");
+        #[cfg(windows)]
+        command.arg("/C");
+        command.arg(&task.command);
+        command
+    } else {
+        let mut command = Command::new(&task.command);
+        command.args(&task.args);
+        command
+    };
+    command
+        .current_dir(&runner.cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(error) => {
+            runner.status_label.set_label("");
+            append_output(state, &format!("Couldn't run {}: {error}\n", task.command));
+            return;
+        }
+    };
+    runner
+        .status_label
+        .set_label(&format!("Running {}…", task.label));
+    runner.cancel_button.set_sensitive(true);
+
+    // Stdout and stderr are read on their own threads and forwarded line by line.
+    // The channel closes once both are done, which marks the task as finished.
+    let (output_sender, output_receiver) = relm4::channel::<String>();
+    let readers: [Box<dyn Read + Send>; 2] = [
+        Box::new(child.stdout.take().unwrap()),
+        Box::new(child.stderr.take().unwrap()),
+    ];
+    for reader in readers {
+        let output_sender = output_sender.clone();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            let mut line = Vec::new();
+            while reader
+                .read_until(b'\n', &mut line)
+                .is_ok_and(|read| read > 0)
+            {
+                output_sender.emit(String::from_utf8_lossy(&line).to_string());
+                line.clear();
+            }
+        });
+    }
+    drop(output_sender);
+    runner.child = Some(child);
+    let run_id = runner.run_id;
+    let sender = runner.sender.clone();
+    relm4::spawn_local(async move {
+        while let Some(line) = output_receiver.recv().await {
+            sender.input(Msg::TaskOutput(run_id, line));
+        }
+        sender.input(Msg::TaskFinished(run_id));
+    });
+}
+
+pub(crate) fn rerun_task(state: &mut State) {
+    match state.task_runner.last_task.clone() {
+        Some(task) => run_task(state, task),
+        None => show_task_picker(state),
+    }
+}
+
+fn severity(word: &str) -> u64 {
+    match word {
+        "error" => 1,
+        "warning" => 2,
+        _ => 3,
+    }
+}
+
+/// Appends output to the panel, underlining `file:line:col` locations that exist. Errors
+/// and warnings at those locations are added to the problems panel.
+fn append_output(state: &mut State, text: &str) {
+    let runner = &mut state.task_runner;
+    let buffer = runner.output.buffer();
+    let text = ANSI_ESCAPE.replace_all(text, "");
+    let mut end = buffer.end_iter();
+    let first_line = end.line();
+    buffer.insert(&mut end, &text);
+    let source = runner
+        .last_task
+        .as_ref()
+        .map(|task| task.label.clone())
+        .unwrap_or_default();
+    let mut found_problems = false;
+
+    for (index, line) in text.lines().enumerate() {
+        let output_line = first_line + index as i32;
+        if let Some(captures) = PROBLEM_HEADING.captures(line) {
+            runner.heading = matches!(&captures[1], "error" | "warning")
+                .then(|| (severity(&captures[1]), captures[2].to_string()));
+        }
+        for captures in LOCATION_PATTERN.captures_iter(line) {
+            let (Some(location), Some(path), Some(target_line)) =
+                (captures.get(0), captures.get(1), captures.get(2))
+            else {
+                continue;
+            };
+            let path = runner.cwd.join(path.as_str());
+            if !path.is_file() {
+                continue;
+            }
+            let char_offset = |byte: usize| line[..byte].chars().count() as i32;
+            let link = OutputLink {
+                line: output_line,
+                start: char_offset(location.start()),
+                end: char_offset(location.end()),
+                path,
+                target_line: target_line.as_str().parse::<i32>().unwrap_or(1) - 1,
+                target_column: captures
+                    .get(3)
+                    .and_then(|column| column.as_str().parse::<i32>().ok())
+                    .unwrap_or(1)
+                    - 1,
+            };
+            if let (Some(start), Some(end)) = (
+                buffer.iter_at_line_offset(link.line, link.start),
+                buffer.iter_at_line_offset(link.line, link.end),
+            ) {
+                buffer.apply_tag_by_name("link", &start, &end);
+            }
+
+            let problem = match PROBLEM_AFTER_LOCATION.captures(&line[location.end()..]) {
+                Some(problem) => Some((severity(&problem[1]), problem[2].to_string())),
+                None if line[..location.start()].trim() == "-->" => runner.heading.take(),
+                None => None,
+            };
+            if let Some((severity, message)) = problem {
+                let start = (
+                    link.target_line.max(0) as u32,
+                    link.target_column.max(0) as u32,
+                );
+                state
+                    .problems
+                    .task_diagnostics
+                    .entry(path_to_uri(&link.path))
+                    .or_default()
+                    .push(Diagnostic {
+                        severity,
+                        message,
+                        source: source.clone(),
+                        start,
+                        end: start,
+                    });
+                found_problems = true;
+            }
+            runner.links.push(link);
+        }
+    }
+
+    runner
+        .output
+        .scroll_to_iter(&mut buffer.end_iter(), 0.0, false, 0.0, 0.0);
+    if found_problems {
+        refresh_problems(state);
+        show_diagnostics(state);
+    }
+}
+
+pub(crate) fn add_task_output(state: &mut State, run_id: u32, text: &str) {
+    if run_id == state.task_runner.run_id {
+        append_output(state, text);
+    }
+}
+
+pub(crate) fn finish_task(state: &mut State, run_id: u32) {
+    if run_id != state.task_runner.run_id {
+        return;
+    }
+    let runner = &mut state.task_runner;
+    runner.cancel_button.set_sensitive(false);
+    let status = runner.child.take().and_then(|mut child| child.wait().ok());
+    let label = runner
+        .last_task
+        .as_ref()
+        .map(|task| task.label.clone())
+        .unwrap_or_default();
+    let message = match status.and_then(|status| status.code()) {
+        Some(0) => format!("{label} finished"),
+        Some(code) => format!("{label} failed with exit code {code}"),
+        None => format!("{label} was stopped"),
+    };
+    runner.status_label.set_label(&message);
+    append_output(state, &format!("\n[{message}]\n"));
+}
+
+/// Opens the location under a click in the output, if there is one.
+pub(crate) fn open_task_link(state: &mut State, line: i32, offset: i32) {
+    let Some(link) = state
+        .task_runner
+        .links
+        .iter()
+        .find(|link| link.line == line && (link.start..link.end).contains(&offset))
+    else {
+        return;
+    };
+    let (path, target_line, target_column) =
+        (link.path.clone(), link.target_line, link.target_column);
+    if path != state.current_file_path {
+        open_file(state, path);
+    }
+    place_cursor(state, target_line, target_column);
+    state.editor.grab_focus();
+}
@@ -0,0 +1,225 @@
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+
+use gtk4::{Notebook, Revealer, ScrolledWindow, glib::clone, prelude::*};
+#[cfg(unix)]
+use gtk4::{gio, glib};
+use libadwaita::Toast;
+use relm4::gtk;
+#[cfg(unix)]
+use vte4::prelude::*;
+
+use crate::app::model::{Msg, State};
+
+/// The terminal pane below the editor, with one tab per terminal.
+#[derive(Debug)]
+pub struct TerminalPanel {
+    pub revealer: Revealer,
+    pub notebook: Notebook,
+    /// Numbers the tab titles
+    pub opened: u32,
+}
+
+pub fn setup_terminal(sender: &relm4::ComponentSender<State>) -> TerminalPanel {
+    let notebook = Notebook::builder()
+        .scrollable(true)
+        .height_request(240)
+        .build();
+    let new_button = gtk::Button::builder()
+        .icon_name("list-add-symbolic")
+        .tooltip_text("New Terminal (Ctrl+Shift+`)")
+        .css_classes(vec!["flat"])
+        .build();
+    new_button.connect_clicked(clone!(
+        #[strong]
+        sender,
+        move |_| sender.input(Msg::NewTerminal)
+    ));
+    notebook.set_action_widget(&new_button, gtk4::PackType::End);
+    let content = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .build();
+    content.append(&gtk::Separator::new(gtk4::Orientation::Horizontal));
+    content.append(&notebook);
+    let revealer = Revealer::builder()
+        .transition_type(gtk4::RevealerTransitionType::SlideUp)
+        .child(&content)
+        .build();
+
+    TerminalPanel {
+        revealer,
+        notebook,
+        opened: 0,
+    }
+}
+
+fn shell() -> String {
+    std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
+}
+
+/// The working directory for new terminals, the open folder if there is one.
+fn working_directory(state: &State) -> String {
+    if state.current_folder_path.is_dir() {
+        state.current_folder_path.display().to_string()
+    } else {
+        dirs::home_dir()
+            .map(|home| home.display().to_string())
+            .unwrap_or_else(|| "/".to_string())
+    }
+}
+
+/// Opens a new terminal tab running the user's shell.
+#[cfg(not(unix))]
+pub(crate) fn new_terminal(state: &mut State) {
+    state
+        .toast_overlay
+        .add_toast(Toast::new("The terminal isn't available on this platform"));
+}
+
+/// Opens a new terminal tab running the user's shell.
+#[cfg(unix)]
+pub(crate) fn new_terminal(state: &mut State) {
+    let terminal = vte4::Terminal::builder()
+        .vexpand(true)
+        .hexpand(true)
+        .build();
+    let shell = shell();
+    terminal.spawn_async(
+        vte4::PtyFlags::DEFAULT,
+        Some(&working_directory(state)),
+        &[&shell],
+        &[],
+        glib::SpawnFlags::SEARCH_PATH,
+        || {},
+        -1,
+        None::<&gio::Cancellable>,
+        clone!(
+            #[strong(rename_to = toast_overlay)]
+            state.toast_overlay,
+            #[strong]
+            shell,
+            move |result| {
+                if let Err(error) = result {
+                    toast_overlay.add_toast(Toast::new(&format!(
+                        "Couldn't start {shell}: {}",
+                        error.message()
+                    )));
+                }
+            }
+        ),
+    );
+
+    let panel = &mut state.terminal;
+    panel.opened += 1;
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .child(&terminal)
+        .build();
+    let close_button = gtk::Button::builder()
+        .icon_name("window-close-symbolic")
+        .tooltip_text("Close Terminal")
+        .css_classes(vec!["flat", "circular"])
+        .build();
+    let tab = gtk::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .spacing(4)
+        .build();
+    tab.append(&gtk::Label::new(Some(&format!(
+        "Terminal {}",
+        panel.opened
+    ))));
+    tab.append(&close_button);
+    let close_tab = clone!(
+        #[weak(rename_to = notebook)]
+        panel.notebook,
+        #[weak]
+        scroll,
+        move || {
+            if let Some(page) = notebook.page_num(&scroll) {
+                notebook.remove_page(Some(page));
+            }
+        }
+    );
+    close_button.connect_clicked(clone!(
+        #[strong]
+        close_tab,
+        move |_| close_tab()
+    ));
+    // Closing the shell closes its tab
+    terminal.connect_child_exited(move |_, _| close_tab());
+
+    let page = panel.notebook.append_page(&scroll, Some(&tab));
+    panel.notebook.set_tab_reorderable(&scroll, true);
+    panel.notebook.set_current_page(Some(page));
+    panel.revealer.set_reveal_child(true);
+    terminal.grab_focus();
+}
+
+/// Shows or hides the terminal pane, opening a terminal if there isn't one yet.
+pub(crate) fn toggle_terminal(state: &mut State) {
+    let panel = &state.terminal;
+    if panel.revealer.reveals_child() {
+        panel.revealer.set_reveal_child(false);
+        state.editor.grab_focus();
+    } else if panel.notebook.n_pages() == 0 {
+        new_terminal(state);
+    } else {
+        panel.revealer.set_reveal_child(true);
+        if let Some(terminal) = panel
+            .notebook
+            .nth_page(panel.notebook.current_page())
+            .and_then(|scroll| scroll.downcast::<ScrolledWindow>().ok())
+            .and_then(|scroll| scroll.child())
+        {
+            terminal.grab_focus();
+        }
+    }
+}
+
+/// Whether the terminal's shell is waiting at its prompt rather than running a program.
+/// VTE starts the shell in a new session, so it's idle while its own process group is
+/// the terminal's foreground one.
+#[cfg(unix)]
+fn shell_is_idle(terminal: &vte4::Terminal) -> bool {
+    let Some(pty) = terminal.pty() else {
+        return false;
+    };
+    let fd = pty.fd().as_raw_fd();
+    let (foreground, session) = unsafe { (libc::tcgetpgrp(fd), libc::tcgetsid(fd)) };
+    foreground > 0 && foreground == session
+}
+
+/// Moves the open terminals to the newly opened folder. Idle shells get a `cd`, and if a
+/// terminal is busy running a program a new tab is opened in the folder instead.
+#[cfg(unix)]
+pub(crate) fn terminals_follow_folder(state: &mut State) {
+    let quoted = state
+        .current_folder_path
+        .display()
+        .to_string()
+        .replace('\'', r"'\''");
+    let command = format!("cd '{quoted}'\n");
+    let notebook = &state.terminal.notebook;
+    let mut busy = false;
+    for page in 0..notebook.n_pages() {
+        let Some(terminal) = notebook
+            .nth_page(Some(page))
+            .and_then(|scroll| scroll.downcast::<ScrolledWindow>().ok())
+            .and_then(|scroll| scroll.child())
+            .and_then(|terminal| terminal.downcast::<vte4::Terminal>().ok())
+        else {
+            continue;
+        };
+        if shell_is_idle(&terminal) {
+            terminal.feed_child(command.as_bytes());
+        } else {
+            busy = true;
+        }
+    }
+    if busy {
+        new_terminal(state);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn terminals_follow_folder(_state: &mut State) {}
@@ -0,0 +1,271 @@
+use gtk4::{TextIter, glib, prelude::*};
+use heck::{ToKebabCase, ToLowerCamelCase, ToSnakeCase, ToUpperCamelCase};
+use libadwaita::Toast;
+use serde::Serialize;
+use sourceview5::prelude::*;
+
+use crate::app::model::State;
+
+/// A conversion applied to the selection by the transform actions.
+#[derive(Debug, Clone, Copy)]
+pub enum Transform {
+    UpperCase,
+    LowerCase,
+    TitleCase,
+    InvertCase,
+    SnakeCase,
+    CamelCase,
+    KebabCase,
+    PascalCase,
+    TrimWhitespace,
+    TabsToSpaces,
+    SpacesToTabs,
+    UrlEncode,
+    UrlDecode,
+    Base64Encode,
+    Base64Decode,
+    JsonPretty,
+    JsonMinify,
+}
+
+/// The selection, or the word under the cursor when nothing is selected.
+fn selection_or_word(buffer: &sourceview5::Buffer) -> Option<(TextIter, TextIter)> {
+    if let Some(bounds) = buffer.selection_bounds() {
+        return Some(bounds);
+    }
+    let mut start = buffer.iter_at_mark(&buffer.get_insert());
+    let mut end = start;
+    if !start.inside_word() && !start.ends_word() {
+        return None;
+    }
+    if !start.starts_word() {
+        start.backward_word_start();
+    }
+    if !end.ends_word() {
+        end.forward_word_end();
+    }
+    Some((start, end))
+}
+
+/// Applies `convert` to each line, so identifiers on separate lines stay separate.
+fn per_line(text: &str, convert: impl Fn(&str) -> String) -> String {
+    text.split('\n')
+        .map(|line| {
+            // Keep the indentation and trailing whitespace around the converted text
+            let start = line.len() - line.trim_start().len();
+            let end = line.trim_end().len().max(start);
+            format!(
+                "{}{}{}",
+                &line[..start],
+                convert(&line[start..end]),
+                &line[end..]
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn title_case(text: &str) -> String {
+    let mut capitalize = true;
+    text.chars()
+        .flat_map(|c| {
+            let converted: Vec<char> = if capitalize {
+                c.to_uppercase().collect()
+            } else {
+                c.to_lowercase().collect()
+            };
+            capitalize = c.is_whitespace();
+            converted
+        })
+        .collect()
+}
+
+fn invert_case(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().collect::<Vec<char>>()
+            } else {
+                c.to_uppercase().collect()
+            }
+        })
+        .collect()
+}
+
+/// Expands every tab to spaces up to the next tab stop, with the text starting at the
+/// visual column `start_column`.
+fn tabs_to_spaces(text: &str, tab_width: usize, start_column: usize) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut column = start_column;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = tab_width - column % tab_width;
+                result.push_str(&" ".repeat(spaces));
+                column += spaces;
+            }
+            '\n' => {
+                result.push(c);
+                column = 0;
+            }
+            _ => {
+                result.push(c);
+                column += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Turns the indentation of each line into tabs, keeping any leftover columns as spaces.
+fn spaces_to_tabs(text: &str, tab_width: usize) -> String {
+    per_line_indentation(text, |indentation| {
+        let width = tabs_to_spaces(indentation, tab_width, 0).len();
+        format!(
+            "{}{}",
+            "\t".repeat(width / tab_width),
+            " ".repeat(width % tab_width)
+        )
+    })
+}
+
+fn per_line_indentation(text: &str, convert: impl Fn(&str) -> String) -> String {
+    text.split('\n')
+        .map(|line| {
+            let start = line.len() - line.trim_start_matches([' ', '\t']).len();
+            format!("{}{}", convert(&line[..start]), &line[start..])
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn base64_decode(text: &str) -> Result<String, String> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let valid = text
+        .trim_end_matches('=')
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/');
+    if !valid || text.trim_end_matches('=').len() % 4 == 1 {
+        return Err("The selection isn't valid Base64".to_string());
+    }
+    String::from_utf8(glib::base64_decode(&text))
+        .map_err(|_| "The decoded Base64 isn't text".to_string())
+}
+
+/// Pretty prints JSON, indenting like the editor does.
+fn json_pretty(text: &str, indent: &str) -> Result<String, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(text).map_err(|error| format!("Invalid JSON: {error}"))?;
+    let mut output = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut output, formatter);
+    value
+        .serialize(&mut serializer)
+        .map_err(|error| error.to_string())?;
+    String::from_utf8(output).map_err(|error| error.to_string())
+}
+
+fn json_minify(text: &str) -> Result<String, String> {
+    serde_json::from_str::<serde_json::Value>(text)
+        .map(|value| value.to_string())
+        .map_err(|error| format!("Invalid JSON: {error}"))
+}
+
+/// Transforms `text`, which starts at `start` in the buffer.
+fn apply(
+    state: &State,
+    transform: Transform,
+    text: &str,
+    start: &TextIter,
+) -> Result<String, String> {
+    let tab_width = state.editor.tab_width().max(1) as usize;
+    let indent = if state.editor.is_insert_spaces_instead_of_tabs() {
+        " ".repeat(tab_width)
+    } else {
+        "\t".to_string()
+    };
+    Ok(match transform {
+        Transform::UpperCase => text.to_uppercase(),
+        Transform::LowerCase => text.to_lowercase(),
+        Transform::TitleCase => title_case(text),
+        Transform::InvertCase => invert_case(text),
+        Transform::SnakeCase => per_line(text, |line| line.to_snake_case()),
+        Transform::CamelCase => per_line(text, |line| line.to_lower_camel_case()),
+        Transform::KebabCase => per_line(text, |line| line.to_kebab_case()),
+        Transform::PascalCase => per_line(text, |line| line.to_upper_camel_case()),
+        Transform::TrimWhitespace => text
+            .trim()
+            .split('\n')
+            .map(str::trim_end)
+            .collect::<Vec<&str>>()
+            .join("\n"),
+        Transform::TabsToSpaces => {
+            tabs_to_spaces(text, tab_width, state.editor.visual_column(start) as usize)
+        }
+        Transform::SpacesToTabs => spaces_to_tabs(text, tab_width),
+        Transform::UrlEncode => glib::Uri::escape_string(text, None, true).to_string(),
+        Transform::UrlDecode => glib::Uri::unescape_string(text, None)
+            .map(|text| text.to_string())
+            .ok_or("The selection isn't valid URL encoded text")?,
+        Transform::Base64Encode => glib::base64_encode(text.as_bytes()).to_string(),
+        Transform::Base64Decode => base64_decode(text)?,
+        Transform::JsonPretty => json_pretty(text, &indent)?,
+        Transform::JsonMinify => json_minify(text)?,
+    })
+}
+
+/// Replaces the selection, or the word under the cursor, with its transformed text and
+/// selects the result.
+pub(crate) fn transform_selection(state: &mut State, transform: Transform) {
+    let buffer = &state.buffer;
+    let Some((start, end)) = selection_or_word(buffer) else {
+        state
+            .toast_overlay
+            .add_toast(Toast::new("Select some text to transform"));
+        return;
+    };
+    let text = buffer.text(&start, &end, true).to_string();
+    let result = match apply(state, transform, &text, &start) {
+        Ok(result) => result,
+        Err(error) => {
+            state.toast_overlay.add_toast(Toast::new(&error));
+            return;
+        }
+    };
+    if result == text {
+        return;
+    }
+    let (offset, end_offset) = (start.offset(), end.offset());
+    // Dropping the extra carets changes the buffer, so the iters are looked up after
+    state.multi_cursor.clear();
+    let mut start = buffer.iter_at_offset(offset);
+    let mut end = buffer.iter_at_offset(end_offset);
+    buffer.begin_user_action();
+    buffer.delete(&mut start, &mut end);
+    buffer.insert(&mut start, &result);
+    buffer.end_user_action();
+    buffer.select_range(&start, &buffer.iter_at_offset(offset));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_tabs_to_the_next_stop() {
+        assert_eq!(tabs_to_spaces("\tfn\tx", 4, 0), "    fn  x");
+        assert_eq!(tabs_to_spaces("a\tb\n\tc", 4, 0), "a   b\n    c");
+    }
+
+    #[test]
+    fn expands_tabs_from_the_selection_column() {
+        // A selection starting at column 2 only needs two spaces to reach the tab stop
+        assert_eq!(tabs_to_spaces("\tx", 4, 2), "  x");
+        assert_eq!(tabs_to_spaces("ab\tx\n\ty", 4, 1), "ab x\n    y");
+    }
+
+    #[test]
+    fn converts_indentation_to_tabs() {
+        assert_eq!(spaces_to_tabs("      x\n  \ty", 4), "\t  x\n\ty");
+    }
+}
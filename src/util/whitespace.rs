@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use gtk4::prelude::*;
+use serde::{Deserialize, Serialize};
+use sourceview5::{SpaceLocationFlags, SpaceTypeFlags, prelude::*};
+
+use crate::app::model::State;
+
+/// What happens to whitespace when saving, and which of it is drawn.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct WhitespaceOptions {
+    #[serde(default)]
+    pub trim_trailing_whitespace: bool,
+    /// End the file with exactly one line break
+    #[serde(default)]
+    pub final_newline: bool,
+    /// Indent every line the way the editor currently indents, tabs or spaces
+    #[serde(default)]
+    pub normalize_indentation: bool,
+    /// Draw every tab and space
+    #[serde(default)]
+    pub show_whitespace: bool,
+    /// Draw tabs and spaces at the end of lines
+    #[serde(default)]
+    pub show_trailing_whitespace: bool,
+}
+
+/// Options a language changes from the global ones, leaving out those it doesn't.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct LanguageWhitespace {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_trailing_whitespace: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_newline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize_indentation: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_whitespace: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_trailing_whitespace: Option<bool>,
+}
+
+/// The options that can be toggled in preferences.
+#[derive(Debug, Clone, Copy)]
+pub enum WhitespaceOption {
+    TrimTrailingWhitespace,
+    FinalNewline,
+    NormalizeIndentation,
+    ShowWhitespace,
+    ShowTrailingWhitespace,
+}
+
+/// Per language options by sourceview language id.
+pub fn default_language_whitespace() -> HashMap<String, LanguageWhitespace> {
+    HashMap::from([
+        // Two trailing spaces are a line break in markdown
+        (
+            "markdown".to_string(),
+            LanguageWhitespace {
+                trim_trailing_whitespace: Some(false),
+                ..Default::default()
+            },
+        ),
+        // Makefile recipes have to be indented with tabs
+        (
+            "makefile".to_string(),
+            LanguageWhitespace {
+                normalize_indentation: Some(false),
+                ..Default::default()
+            },
+        ),
+    ])
+}
+
+impl WhitespaceOptions {
+    pub fn set(&mut self, option: WhitespaceOption, value: bool) {
+        match option {
+            WhitespaceOption::TrimTrailingWhitespace => self.trim_trailing_whitespace = value,
+            WhitespaceOption::FinalNewline => self.final_newline = value,
+            WhitespaceOption::NormalizeIndentation => self.normalize_indentation = value,
+            WhitespaceOption::ShowWhitespace => self.show_whitespace = value,
+            WhitespaceOption::ShowTrailingWhitespace => self.show_trailing_whitespace = value,
+        }
+    }
+
+    fn with_language(self, language: &LanguageWhitespace) -> WhitespaceOptions {
+        WhitespaceOptions {
+            trim_trailing_whitespace: language
+                .trim_trailing_whitespace
+                .unwrap_or(self.trim_trailing_whitespace),
+            final_newline: language.final_newline.unwrap_or(self.final_newline),
+            normalize_indentation: language
+                .normalize_indentation
+                .unwrap_or(self.normalize_indentation),
+            show_whitespace: language.show_whitespace.unwrap_or(self.show_whitespace),
+            show_trailing_whitespace: language
+                .show_trailing_whitespace
+                .unwrap_or(self.show_trailing_whitespace),
+        }
+    }
+}
+
+/// The options for the current document's language.
+fn document_options(state: &State) -> WhitespaceOptions {
+    buffer_options(state, &state.buffer)
+}
+
+/// The options for the language of a buffer's document.
+fn buffer_options(state: &State, buffer: &sourceview5::Buffer) -> WhitespaceOptions {
+    buffer
+        .language()
+        .and_then(|language| state.whitespace_languages.get(language.id().as_str()))
+        .map_or(state.whitespace, |language| {
+            state.whitespace.with_language(language)
+        })
+}
+
+/// Removes spaces and tabs from the end of every line.
+pub(crate) fn trim_trailing_whitespace(buffer: &sourceview5::Buffer) {
+    for line in (0..buffer.line_count()).rev() {
+        let Some(mut end) = buffer.iter_at_line(line) else {
+            continue;
+        };
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let mut start = end;
+        loop {
+            let mut before = start;
+            if start.starts_line()
+                || !before.backward_char()
+                || !matches!(before.char(), ' ' | '\t')
+            {
+                break;
+            }
+            start = before;
+        }
+        if start != end {
+            buffer.delete(&mut start, &mut end);
+        }
+    }
+}
+
+/// Makes the buffer end with exactly one line break, or none.
+pub(crate) fn set_final_newline(buffer: &sourceview5::Buffer, newline: bool) {
+    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), true);
+    let content_end = text.trim_end_matches(['\n', '\r']).chars().count() as i32;
+    if content_end == 0 {
+        return;
+    }
+    let mut start = buffer.iter_at_offset(content_end);
+    let mut end = buffer.end_iter();
+    let wanted = if newline { "\n" } else { "" };
+    if buffer.text(&start, &end, true) != wanted {
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, wanted);
+    }
+}
+
+/// Rewrites the indentation of every line with tabs or spaces, keeping its width.
+/// Columns left over from a whole tab stay as spaces.
+pub(crate) fn normalize_indentation(
+    buffer: &sourceview5::Buffer,
+    use_spaces: bool,
+    tab_width: u32,
+) {
+    let tab_width = tab_width.max(1);
+    for line in 0..buffer.line_count() {
+        let Some(mut start) = buffer.iter_at_line(line) else {
+            continue;
+        };
+        let mut end = start;
+        let mut width = 0;
+        while matches!(end.char(), ' ' | '\t') && !end.ends_line() {
+            width = match end.char() {
+                '\t' => (width / tab_width + 1) * tab_width,
+                _ => width + 1,
+            };
+            end.forward_char();
+        }
+        // Lines with nothing after their indentation are left for trimming
+        if end.ends_line() {
+            continue;
+        }
+        let indentation = if use_spaces {
+            " ".repeat(width as usize)
+        } else {
+            format!(
+                "{}{}",
+                "\t".repeat((width / tab_width) as usize),
+                " ".repeat((width % tab_width) as usize)
+            )
+        };
+        if buffer.text(&start, &end, true) != indentation {
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &indentation);
+        }
+    }
+}
+
+/// Cleans up the buffer's whitespace before it's saved. EditorConfig properties take
+/// precedence over the options.
+pub(crate) fn clean_up_whitespace(state: &mut State) {
+    let options = document_options(state);
+    let config = &state.editorconfig;
+    let trim = config
+        .trim_trailing_whitespace
+        .unwrap_or(options.trim_trailing_whitespace);
+    let final_newline = config
+        .insert_final_newline
+        .or(options.final_newline.then_some(true));
+    if !trim && final_newline.is_none() && !options.normalize_indentation {
+        return;
+    }
+    let buffer = &state.buffer;
+    buffer.begin_user_action();
+    if options.normalize_indentation {
+        normalize_indentation(
+            buffer,
+            state.editor.is_insert_spaces_instead_of_tabs(),
+            state.editor.tab_width(),
+        );
+    }
+    if trim {
+        trim_trailing_whitespace(buffer);
+    }
+    if let Some(newline) = final_newline {
+        set_final_newline(buffer, newline);
+    }
+    buffer.end_user_action();
+}
+
+/// Draws whitespace in every editor the way the options for its document ask.
+pub(crate) fn apply_whitespace_drawing(state: &State) {
+    let visible = SpaceTypeFlags::SPACE | SpaceTypeFlags::TAB | SpaceTypeFlags::NBSP;
+    for pane in &state.splits.panes {
+        let Ok(buffer) = pane.view.buffer().downcast::<sourceview5::Buffer>() else {
+            continue;
+        };
+        let options = buffer_options(state, &buffer);
+        let drawer = pane.view.space_drawer();
+        drawer.set_types_for_locations(SpaceLocationFlags::ALL, SpaceTypeFlags::NONE);
+        if options.show_whitespace {
+            drawer.set_types_for_locations(
+                SpaceLocationFlags::LEADING
+                    | SpaceLocationFlags::INSIDE_TEXT
+                    | SpaceLocationFlags::TRAILING,
+                visible,
+            );
+        } else if options.show_trailing_whitespace {
+            drawer.set_types_for_locations(SpaceLocationFlags::TRAILING, visible);
+        }
+        drawer.set_enable_matrix(options.show_whitespace || options.show_trailing_whitespace);
+    }
+}
@@ -1,11 +1,15 @@
 use std::path::PathBuf;
 
-use gtk4::{glib::GString, prelude::WidgetExt};
+use gtk4::{
+    glib::GString,
+    prelude::{TextBufferExt, TextViewExt, WidgetExt},
+};
 use sourceview5::{Buffer, LanguageManager, prelude::BufferExt};
 
 use crate::{
     app::model::{ItemVis, State},
     fs::settings::save_settings,
+    util::split::pane_buffers,
 };
 
 pub fn setup_editor(buffer: &Buffer) -> sourceview5::View {
@@ -25,14 +29,15 @@ pub(crate) fn toggle_buffer_style(state: &mut State) {
     match state.buffer_style.as_ref().unwrap().to_string().as_str() {
         "Adwaita Dark" => {
             state.buffer_style = sourceview5::StyleSchemeManager::new().scheme("Adwaita");
-            state.buffer.set_style_scheme(state.buffer_style.as_ref());
         }
         "Adwaita" => {
             state.buffer_style = sourceview5::StyleSchemeManager::new().scheme("Adwaita-dark");
-            state.buffer.set_style_scheme(state.buffer_style.as_ref());
         }
         _ => {}
     }
+    for buffer in pane_buffers(state) {
+        buffer.set_style_scheme(state.buffer_style.as_ref());
+    }
     save_settings(state);
 }
 
@@ -48,6 +53,57 @@ pub(crate) fn update_vis(item: ItemVis, vis: bool, state: &mut State) {
     save_settings(state);
 }
 
+/// Moves the cursor to a zero based line and column, clamping to the end of the line,
+/// and scrolls it into view.
+pub(crate) fn place_cursor(state: &State, line: i32, column: i32) {
+    let iter = state
+        .buffer
+        .iter_at_line_offset(line, column)
+        .or_else(|| {
+            state.buffer.iter_at_line(line).map(|mut iter| {
+                if !iter.ends_line() {
+                    iter.forward_to_line_end();
+                }
+                iter
+            })
+        })
+        .unwrap_or_else(|| state.buffer.end_iter());
+    state.buffer.place_cursor(&iter);
+    state
+        .editor
+        .scroll_to_mark(&state.buffer.get_insert(), 0.1, true, 0.0, 0.5);
+    state.editor.grab_focus();
+}
+
+/// Replaces the current buffer as one undoable edit, keeping the cursor on its line.
+pub(crate) fn replace_buffer_text(state: &mut State, text: &str) {
+    state.multi_cursor.clear();
+    set_buffer_text(&state.buffer, text);
+    state
+        .editor
+        .scroll_to_mark(&state.buffer.get_insert(), 0.1, false, 0.0, 0.0);
+}
+
+/// Replaces a buffer's text as one undoable edit, keeping the cursor on its line.
+pub(crate) fn set_buffer_text(buffer: &Buffer, text: &str) {
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let (line, column) = (cursor.line(), cursor.line_offset());
+    buffer.begin_user_action();
+    buffer.delete(&mut buffer.start_iter(), &mut buffer.end_iter());
+    buffer.insert(&mut buffer.start_iter(), text);
+    buffer.end_user_action();
+
+    let mut cursor = buffer
+        .iter_at_line(line)
+        .unwrap_or_else(|| buffer.end_iter());
+    if !cursor.ends_line() {
+        let mut line_end = cursor;
+        line_end.forward_to_line_end();
+        cursor.set_line_offset(column.min(line_end.line_offset()));
+    }
+    buffer.place_cursor(&cursor);
+}
+
 pub fn update_syntax(
     language_manager: &LanguageManager,
     current_file_path: &String,